@@ -0,0 +1,231 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crypto;
+use crate::evaluation::{self, Target};
+use crate::introspector::HttpIntrospector;
+use crate::{extraction, Policy, ResponseContext};
+
+/// Serves the health report of the policy when the request addresses the configured health path,
+/// without forwarding anything to the upstream
+pub fn health(request: &impl HeadersHandler, policy: &Policy) -> Option<Flow<ResponseContext>> {
+    let config = &policy.config;
+    let path = config.health_path.as_deref()?;
+
+    if crate::request_path(request) != path || crate::request_method(request, config) != "GET" {
+        return None;
+    }
+
+    if !authorized(request, policy) {
+        logger::debug!("Rejected a health request without a valid admin secret.");
+        return Some(Flow::Break(Response::new(404)));
+    }
+
+    let report = json!({
+        "instance": policy.instance,
+        "configHash": policy.fingerprint,
+        "lastIdpContact": policy.last_idp_contact.get(),
+        "inFlightCalls": policy.limiter.as_ref().map(|limiter| limiter.in_flight()),
+    });
+
+    Some(Flow::Break(
+        Response::new(200)
+            .with_headers(vec![(
+                "content-type".to_string(),
+                "application/json".to_string(),
+            )])
+            .with_body(report.to_string()),
+    ))
+}
+
+/// Serves the metrics of the policy in the OpenMetrics text format when the request addresses the
+/// configured metrics path, so they can be scraped through the gateway
+pub fn metrics(request: &impl HeadersHandler, policy: &Policy) -> Option<Flow<ResponseContext>> {
+    let config = &policy.config;
+    let path = config.metrics_path.as_deref()?;
+
+    if crate::request_path(request) != path || crate::request_method(request, config) != "GET" {
+        return None;
+    }
+
+    if !authorized(request, policy) {
+        logger::debug!("Rejected a metrics request without a valid admin secret.");
+        return Some(Flow::Break(Response::new(404)));
+    }
+
+    let mut gauges = vec![];
+    if let Some(limiter) = &policy.limiter {
+        gauges.push(("in_flight_calls", limiter.in_flight() as u64));
+    }
+
+    Some(Flow::Break(
+        Response::new(200)
+            .with_headers(vec![(
+                "content-type".to_string(),
+                "application/openmetrics-text; version=1.0.0; charset=utf-8".to_string(),
+            )])
+            .with_body(policy.metrics.open_metrics(&gauges)),
+    ))
+}
+
+/// Serves the trace of the evaluation of the token of the request against the method and path
+/// given in the query string, without forwarding anything to the upstream
+pub async fn explain(
+    request: &impl HeadersHandler,
+    policy: &Policy,
+    client: &HttpClient,
+) -> Option<Flow<ResponseContext>> {
+    let config = &policy.config;
+    let path = config.explain_path.as_deref()?;
+
+    if crate::request_path(request) != path || crate::request_method(request, config) != "GET" {
+        return None;
+    }
+
+    if !authorized(request, policy) {
+        logger::debug!("Rejected an explain request without a valid admin secret.");
+        return Some(Flow::Break(Response::new(404)));
+    }
+
+    //the request to explain is described by the query string, defaulting to the explain request
+    let mut target = Target::of(request, config);
+    let uri = request.header(":path").unwrap_or_default();
+    let query = uri
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default();
+    let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap_or_default();
+    for (name, value) in params {
+        match name.as_str() {
+            "method" => target.method = value,
+            "path" => target.path = value,
+            "host" => target.host = value,
+            _ => {}
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default();
+
+    let mut checks = vec![];
+    let token = extraction::extract_token(request, config);
+    #[cfg(not(feature = "cache"))]
+    let cache = "disabled";
+    #[cfg(feature = "cache")]
+    let cache = match (policy.caches().next().is_some(), &token) {
+        (true, Ok(Some(token))) => {
+            let issuer = crate::issuer_hint(request, config);
+            let endpoint = crate::Endpoint::select(token, issuer.as_deref(), config);
+            match endpoint.map(|endpoint| endpoint.fingerprint()) {
+                Ok(route)
+                    if policy
+                        .caches()
+                        .any(|cache| cache.get(token, &route, now).is_some()) =>
+                {
+                    "hit"
+                }
+                _ => "miss",
+            }
+        }
+        (true, _) => "miss",
+        (false, _) => "disabled",
+    };
+
+    let validation = match token {
+        Ok(Some(token)) => {
+            let issuer = crate::issuer_hint(request, config);
+            let introspector = HttpIntrospector { policy, client };
+            crate::validate_token(
+                &token,
+                issuer.as_deref(),
+                policy,
+                client,
+                &introspector,
+                now,
+            )
+            .await
+        }
+        Ok(None) => Err(crate::FilterError::NoToken),
+        Err(err) => Err(err),
+    };
+    let validation = validation.and_then(|mut response| {
+        crate::normalize_claims(&mut response, config)?;
+        Ok(response)
+    });
+
+    if let Ok(response) = &validation {
+        for name in policy.rules.checks.iter() {
+            let result = evaluation::check(name, response, policy, &target, now);
+            checks.push(json!({
+                "name": name,
+                "passed": result.is_ok(),
+                "error": result.err().map(|err| format!("{:?}", err)),
+            }));
+        }
+    }
+
+    let allowed = validation.is_ok() && checks.iter().all(|check| check["passed"] == true);
+    let report = json!({
+        "request": { "method": target.method, "host": target.host, "path": target.path },
+        "validation": {
+            "passed": validation.is_ok(),
+            "error": validation.as_ref().err().map(|err| format!("{:?}", err)),
+            "subject": validation
+                .as_ref()
+                .ok()
+                .and_then(|response| response.claims.get("sub"))
+                .cloned()
+                .unwrap_or(Value::Null),
+        },
+        "cache": cache,
+        "checks": checks,
+        "decision": if allowed { "allow" } else { "deny" },
+    });
+
+    Some(Flow::Break(
+        Response::new(200)
+            .with_headers(vec![(
+                "content-type".to_string(),
+                "application/json".to_string(),
+            )])
+            .with_body(report.to_string()),
+    ))
+}
+
+/// Returns true when the request carries the configured admin secret
+fn authorized(request: &impl HeadersHandler, policy: &Policy) -> bool {
+    let config = &policy.config;
+
+    match config.health_secret.as_deref() {
+        Some(secret) => request
+            .header(&config.health_secret_header)
+            .is_some_and(|presented| crypto::secrets_match(&presented, secret)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, Headers};
+
+    #[test]
+    fn authorizes_the_requests_carrying_the_admin_secret() {
+        let policy = testing::policy(json!({ "healthSecret": "s3cret" }));
+        let header = policy.config.health_secret_header.clone();
+
+        assert!(authorized(&Headers::new(&[(&header, "s3cret")]), &policy));
+        assert!(!authorized(&Headers::new(&[(&header, "s3cre")]), &policy));
+        assert!(!authorized(&Headers::new(&[]), &policy));
+        assert!(!authorized(
+            &Headers::new(&[(&header, "s3cret")]),
+            &testing::policy(json!({}))
+        ));
+    }
+}
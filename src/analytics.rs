@@ -0,0 +1,60 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use proxy_wasm::hostcalls;
+use serde_json::{Map, Value};
+
+use crate::claims;
+use crate::generated::config::{AnalyticsDimensionsItem, Config};
+
+/// Value reported for a dimension whose claim is not present in the token
+const UNKNOWN: &str = "unknown";
+
+/// Value reported for a dimension whose claim is not one of the allowed values
+const OTHER: &str = "other";
+
+/// Publishes the claim derived dimensions as properties of the request, so the platform
+/// analytics can segment the traffic by identity attributes
+pub fn tag(config: &Config, claims: &Map<String, Value>) {
+    for dimension in config.analytics_dimensions.iter() {
+        if let Some(claim) = dimension.claim.as_deref() {
+            publish(config, dimension, claims::claim_as_string(claims, claim));
+        }
+    }
+}
+
+/// Publishes the dimensions derived from the decision taken for the request, which are set for
+/// the rejected requests as well
+pub fn tag_decision(config: &Config, rejection: Option<&str>) {
+    for dimension in config.analytics_dimensions.iter() {
+        let value = match (dimension.decision_field.as_deref(), rejection) {
+            (Some("outcome"), Some(_)) => "rejected".to_string(),
+            (Some("outcome"), None) => "accepted".to_string(),
+            (Some("reason"), Some(code)) => code.to_ascii_lowercase(),
+            (Some("reason"), None) => "none".to_string(),
+            _ => continue,
+        };
+        publish(config, dimension, Some(value));
+    }
+}
+
+/// Sets the property of the dimension, collapsing the values outside the allowed set to keep
+/// the cardinality low
+fn publish(config: &Config, dimension: &AnalyticsDimensionsItem, value: Option<String>) {
+    let value = match value {
+        None => UNKNOWN.to_string(),
+        Some(value) if dimension.values.is_empty() || dimension.values.contains(&value) => value,
+        Some(_) => OTHER.to_string(),
+    };
+    let path = vec![
+        config.analytics_property_prefix.as_str(),
+        dimension.name.as_str(),
+    ];
+
+    if let Err(status) = hostcalls::set_property(path, Some(value.as_bytes())) {
+        logger::debug!(
+            "Unable to set the analytics dimension {}. {:?}.",
+            dimension.name,
+            status
+        );
+    }
+}
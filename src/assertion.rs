@@ -0,0 +1,81 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{bail, Result};
+use serde_json::json;
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crypto::{self, SigningKey};
+use crate::generated::config::Config;
+use crate::FilterError;
+
+/// Type of the client assertions defined by RFC 7523
+pub const ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Seconds the signed assertions remain valid
+const LIFETIME: u64 = 60;
+
+/// Signs the assertions the policy authenticates to the introspection endpoint with, as defined
+/// by the private_key_jwt client authentication method
+pub struct ClientAssertion {
+    key: SigningKey,
+    key_id: Option<String>,
+    client_id: String,
+    audience: String,
+    issued: Cell<u64>,
+}
+
+impl ClientAssertion {
+    /// Loads the signing key when the private_key_jwt method is configured
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if config.client_auth_method != "private_key_jwt" {
+            return Ok(None);
+        }
+
+        let (client_id, pem) = match (&config.client_id, &config.client_assertion_key) {
+            (Some(client_id), Some(pem)) => (client_id, pem),
+            _ => bail!("private_key_jwt requires clientId and clientAssertionKey"),
+        };
+
+        let key = SigningKey::from_pem(
+            &config.client_assertion_algorithm,
+            pem,
+            "clientAssertionKey",
+        )?;
+
+        Ok(Some(Self {
+            key,
+            key_id: config.client_assertion_key_id.clone(),
+            client_id: client_id.clone(),
+            audience: config
+                .client_assertion_audience
+                .clone()
+                .unwrap_or_else(|| format!("https://{}{}", config.host, config.path)),
+            issued: Cell::new(0),
+        }))
+    }
+
+    /// Signs a new single-use assertion
+    pub fn sign(&self) -> Result<String, FilterError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| FilterError::Unexpected)?;
+        self.issued.set(self.issued.get().wrapping_add(1));
+        let jti = crypto::sha256_hex(format!(
+            "{}:{}:{}",
+            self.client_id,
+            now.as_nanos(),
+            self.issued.get()
+        ));
+
+        let claims = json!({
+            "iss": self.client_id,
+            "sub": self.client_id,
+            "aud": self.audience,
+            "jti": &jti[..32],
+            "iat": now.as_secs(),
+            "exp": now.as_secs() + LIFETIME,
+        });
+
+        self.key.sign(self.key_id.as_deref(), &claims)
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use serde_json::{json, Value};
+
+use crate::{crypto, FilterError};
+
+/// Severity assigned to an audit event, mapped to the log level used to emit it
+pub enum Severity {
+    Info,
+    High,
+}
+
+/// Emits a single line audit event tagged with the policy instance, with the details serialized
+/// as JSON so it can be parsed by log collectors
+pub fn emit(instance: &str, severity: Severity, event: &str, details: Value) {
+    let details = serde_json::to_string(&details).unwrap_or_default();
+
+    match severity {
+        Severity::Info => logger::info!(
+            "audit instance={} event={} details={}",
+            instance,
+            event,
+            details
+        ),
+        Severity::High => logger::warn!(
+            "audit instance={} event={} severity=high details={}",
+            instance,
+            event,
+            details
+        ),
+    }
+}
+
+/// Returns the fingerprint a token is logged as, so the raw token never reaches the logs
+pub fn fingerprint(token: &str) -> String {
+    crypto::sha256_hex(token)[..16].to_string()
+}
+
+/// Fields of the decision log line, correlating a decision with the access logs of the gateway
+pub struct Decision<'a> {
+    pub request_id: Option<&'a str>,
+    pub token_fingerprint: Option<&'a str>,
+    pub client_id: Option<&'a str>,
+    pub rejection: Option<&'a FilterError>,
+    pub latency_micros: u64,
+}
+
+/// Emits the structured log line of a decision, as configured by decisionLogging
+pub fn decision(instance: &str, logging: &str, decision: Decision) {
+    if logging == "off" || (logging == "rejections" && decision.rejection.is_none()) {
+        return;
+    }
+
+    let details = json!({
+        "request_id": decision.request_id,
+        "token_fingerprint": decision.token_fingerprint,
+        "client_id": decision.client_id,
+        "outcome": if decision.rejection.is_some() { "rejected" } else { "accepted" },
+        "reason": decision.rejection.map(FilterError::code),
+        "category": decision.rejection.map(|err| err.category().name()),
+        "latency_micros": decision.latency_micros,
+    });
+    logger::info!("decision instance={} details={}", instance, details);
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Entry points to the per-request hot path, only compiled for the `bench` feature so the
+//! benchmarks can measure it without a gateway
+
+use anyhow::Result;
+use pdk::api::hl::*;
+use serde_json::{Map, Value};
+
+use crate::cache::IntrospectionCache;
+use crate::evaluation;
+use crate::generated::config::Config;
+use crate::introspector::MockIntrospector;
+use crate::{extraction, Endpoint, FilterError, IntrospectionResponse, Policy, ResponseContext};
+
+pub use crate::evaluation::Target;
+
+/// Fingerprint of the endpoint the cached results are obtained from
+const ROUTE: &str = "bench";
+
+/// Policy built from a configuration along with a validated token and the request it arrived in
+pub struct Fixture {
+    pub policy: Policy,
+    pub response: IntrospectionResponse,
+    pub headers: Vec<(String, String)>,
+    pub target: Target,
+    pub cache: IntrospectionCache,
+}
+
+impl Fixture {
+    pub fn new(
+        config: &str,
+        claims: Map<String, Value>,
+        headers: Vec<(String, String)>,
+        target: Target,
+    ) -> Result<Self> {
+        let bytes = config.as_bytes();
+
+        Ok(Self {
+            policy: Policy::detached(serde_json::from_slice::<Config>(bytes)?, bytes)?,
+            response: IntrospectionResponse {
+                active: true,
+                max_age: None,
+                exp: claims.get("exp").and_then(Value::as_u64),
+                nbf: claims.get("nbf").and_then(Value::as_u64),
+                claims,
+            },
+            headers,
+            target,
+            cache: IntrospectionCache::new(300, 1000, 0),
+        })
+    }
+
+    /// Looks up the token in the configured token sources
+    pub fn extract(&self) -> Option<String> {
+        self.policy
+            .config
+            .token_sources
+            .iter()
+            .find_map(|source| extraction::from_source(&self.headers, source))
+    }
+
+    /// Evaluates the claim checks in their configured order, as the request filter does
+    pub fn evaluate(&self, now: u64) -> Result<(), FilterError> {
+        self.policy.rules.checks.iter().try_for_each(|check| {
+            evaluation::check(check, &self.response, &self.policy, &self.target, now)
+        })
+    }
+
+    /// Validates the token against canned introspection results, checking the validity of the
+    /// result and then the claim checks as the request filter does
+    pub fn validate(&self, token: &str, now: u64) -> Result<(), FilterError> {
+        let introspector = MockIntrospector::default().with(token, self.response.clone());
+        let endpoint = Endpoint::select(token, None, &self.policy.config)?;
+        let response = futures::executor::block_on(crate::introspected(
+            &introspector,
+            token,
+            &endpoint,
+            &self.policy,
+            now,
+        ))?;
+
+        self.policy.rules.checks.iter().try_for_each(|check| {
+            evaluation::check(check, &response, &self.policy, &self.target, now)
+        })
+    }
+
+    /// Caches the validated token and looks it up again
+    pub fn cache_lookup(&self, token: &str, now: u64) -> Option<IntrospectionResponse> {
+        self.cache.insert(token, ROUTE, &self.response, now);
+        self.cache.get(token, ROUTE, now)
+    }
+
+    /// Builds the early responses sent for a rejected token and an unavailable IdP
+    pub fn respond(&self) -> Vec<Flow<ResponseContext>> {
+        vec![
+            crate::unauthorized_response(
+                &self.policy,
+                "TOKEN_EXPIRED",
+                Some(("invalid_token", "expired")),
+            ),
+            crate::insufficient_scope_response(&self.policy, "INSUFFICIENT_SCOPE"),
+            crate::rate_limited_response(&self.policy, 429, 30),
+            crate::server_error_response(&self.policy, "UNEXPECTED_ERROR"),
+        ]
+    }
+}
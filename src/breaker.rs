@@ -0,0 +1,100 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::Cell;
+
+/// Stops calling the authorization server after consecutive failures to reach it, letting a
+/// single call through every cool-down period to probe whether it recovered
+pub struct CircuitBreaker {
+    threshold: Cell<u32>,
+    cool_down: Cell<u64>,
+    failures: Cell<u32>,
+    opened_at: Cell<Option<u64>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cool_down: u64) -> Self {
+        Self {
+            threshold: Cell::new(threshold),
+            cool_down: Cell::new(cool_down),
+            failures: Cell::new(0),
+            opened_at: Cell::new(None),
+        }
+    }
+
+    /// Applies a new threshold and cool-down, keeping the state of the circuit
+    pub fn retune(&self, threshold: u32, cool_down: u64) {
+        self.threshold.set(threshold);
+        self.cool_down.set(cool_down);
+    }
+
+    /// Returns true when the call can be made, which while the circuit is open only happens for
+    /// the probe made once the cool-down elapses
+    pub fn allows(&self, now: u64) -> bool {
+        match self.opened_at.get() {
+            None => true,
+            Some(opened_at) if now >= opened_at + self.cool_down.get() => {
+                self.opened_at.set(Some(now));
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Returns true while the circuit is open
+    pub fn is_open(&self) -> bool {
+        self.opened_at.get().is_some()
+    }
+
+    /// Records the outcome of a call, closing the circuit on success and opening it once the
+    /// consecutive failures reach the threshold
+    pub fn record(&self, reached: bool, now: u64) {
+        if reached {
+            self.failures.set(0);
+            self.opened_at.set(None);
+            return;
+        }
+
+        self.failures.set(self.failures.get().saturating_add(1));
+        if self.failures.get() >= self.threshold.get() {
+            self.opened_at.set(Some(now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_the_consecutive_failures_reach_the_threshold() {
+        let breaker = CircuitBreaker::new(2, 30);
+
+        breaker.record(false, 100);
+        assert!(breaker.allows(100));
+        breaker.record(false, 100);
+        assert!(breaker.is_open());
+        assert!(!breaker.allows(129));
+    }
+
+    #[test]
+    fn lets_a_probe_through_once_the_cool_down_elapses() {
+        let breaker = CircuitBreaker::new(1, 30);
+        breaker.record(false, 100);
+
+        assert!(breaker.allows(130));
+        //the other calls wait for the outcome of the probe
+        assert!(!breaker.allows(131));
+        breaker.record(true, 131);
+        assert!(!breaker.is_open());
+        assert!(breaker.allows(131));
+    }
+
+    #[test]
+    fn resets_the_failures_on_success() {
+        let breaker = CircuitBreaker::new(2, 30);
+
+        breaker.record(false, 100);
+        breaker.record(true, 100);
+        breaker.record(false, 100);
+        assert!(!breaker.is_open());
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+
+use crate::Policy;
+
+/// Categories of the headers injected into the forwarded request, from the highest priority to the
+/// lowest in their default order
+pub const INJECTIONS: [&str; 6] = [
+    "identity",
+    "routing",
+    "claimHeaders",
+    "mutations",
+    "context",
+    "claims",
+];
+
+/// Headers injected into the request forwarded to the upstream service, set once they are all
+/// known so their total size can be kept within the budget
+#[derive(Default)]
+pub struct Injections {
+    headers: Vec<(&'static str, String, String)>,
+}
+
+impl Injections {
+    pub fn set(&mut self, category: &'static str, name: &str, value: String) {
+        self.headers
+            .retain(|(_, header, _)| !header.eq_ignore_ascii_case(name));
+        self.headers.push((category, name.to_string(), value));
+    }
+
+    /// Removes the header from the request, along with any value injected for it so far
+    pub fn remove(&mut self, request: &impl HeadersHandler, name: &str) {
+        self.headers
+            .retain(|(_, header, _)| !header.eq_ignore_ascii_case(name));
+        request.remove_header(name);
+    }
+
+    /// Sets the injected headers in the request, dropping the ones of the lowest priority first, the
+    /// latest injected first within a category, while their size exceeds the budget
+    pub fn apply(mut self, request: &impl HeadersHandler, policy: &Policy) {
+        if let Some(budget) = policy.config.header_budget_bytes {
+            let budget = budget.max(0) as usize;
+            let priorities = &policy.rules.injections;
+            let rank = |category: &str| priorities.iter().position(|name| *name == category);
+
+            while self.size() > budget {
+                let lowest = self
+                    .headers
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(index, (category, ..))| (rank(category), *index))
+                    .map(|(index, _)| index);
+
+                if let Some(index) = lowest {
+                    let (category, name, _) = self.headers.remove(index);
+                    policy.metrics.increment("header_budget.dropped");
+                    logger::warn!(
+                        "Dropping the {} header {} to keep within the header budget.",
+                        category,
+                        name
+                    );
+                }
+            }
+        }
+
+        for (_, name, value) in self.headers.iter() {
+            request.set_header(name, value);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.headers
+            .iter()
+            .map(|(_, name, value)| name.len() + value.len())
+            .sum()
+    }
+}
@@ -0,0 +1,401 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+use crate::{crypto, IntrospectionResponse};
+
+/// Remembers the validation results of the active tokens, keyed by the hash of the token so the
+/// tokens themselves are never kept in memory
+pub struct IntrospectionCache {
+    ttl: Cell<u64>,
+    max_entries: Cell<usize>,
+    grace: Cell<u64>,
+    entries: RefCell<HashMap<String, Entry>>,
+}
+
+/// Cached result, fresh until its expiration and served stale until the end of the grace period,
+/// along with the fingerprint of the endpoint it was obtained from
+struct Entry {
+    expiration: u64,
+    stale_until: u64,
+    route: String,
+    response: IntrospectionResponse,
+}
+
+impl IntrospectionCache {
+    pub fn new(ttl: u64, max_entries: usize, grace: u64) -> Self {
+        Self {
+            ttl: Cell::new(ttl),
+            max_entries: Cell::new(max_entries),
+            grace: Cell::new(grace),
+            entries: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Applies new limits to the cache, keeping the entries cached so far
+    pub fn retune(&self, ttl: u64, max_entries: usize, grace: u64) {
+        self.ttl.set(ttl);
+        self.max_entries.set(max_entries);
+        self.grace.set(grace);
+    }
+
+    /// Returns the cached result for the token, unless it has expired or was obtained from another
+    /// endpoint than the one the token is now validated against. The expired results are kept
+    /// until replaced or evicted, so the result of the next validation can be compared with them.
+    pub fn get(&self, token: &str, route: &str, now: u64) -> Option<IntrospectionResponse> {
+        self.entries
+            .borrow()
+            .get(&crypto::sha256_hex(token))
+            .filter(|entry| entry.route == route && now < entry.expiration)
+            .map(|entry| entry.response.clone())
+    }
+
+    /// Returns the expired result for the token while it is within the grace period
+    pub fn get_stale(&self, token: &str, route: &str, now: u64) -> Option<IntrospectionResponse> {
+        self.entries
+            .borrow()
+            .get(&crypto::sha256_hex(token))
+            .filter(|entry| entry.route == route)
+            .filter(|entry| entry.expiration <= now && now < entry.stale_until)
+            .map(|entry| entry.response.clone())
+    }
+
+    /// Removes the result of the token, returning it
+    pub fn remove(&self, token: &str) -> Option<IntrospectionResponse> {
+        self.entries
+            .borrow_mut()
+            .remove(&crypto::sha256_hex(token))
+            .map(|entry| entry.response)
+    }
+
+    /// Returns whether a fresh or stale result is cached for the token
+    pub fn holds(&self, token: &str, now: u64) -> bool {
+        self.entries
+            .borrow()
+            .get(&crypto::sha256_hex(token))
+            .is_some_and(|entry| now < entry.stale_until)
+    }
+
+    /// Drops the results obtained from the endpoints no longer configured, returning how many
+    pub fn retain_routes(&self, routes: &HashSet<String>) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let before = entries.len();
+        entries.retain(|_, entry| routes.contains(&entry.route));
+        before - entries.len()
+    }
+
+    /// Changes the TTL of the results cached from now on
+    pub fn set_ttl(&self, ttl: u64) {
+        self.ttl.set(ttl);
+    }
+
+    /// Caches the result of an active token until the TTL elapses or the token expires, whatever
+    /// happens first. The grace period never extends past the expiration of the token. Returns the
+    /// result it replaced, when obtained from the same endpoint.
+    pub fn insert(
+        &self,
+        token: &str,
+        route: &str,
+        response: &IntrospectionResponse,
+        now: u64,
+    ) -> Option<IntrospectionResponse> {
+        //the Cache-Control of the authorization server, when honored, overrides the TTL
+        let ttl = response.max_age.unwrap_or_else(|| self.ttl.get());
+        let max_entries = self.max_entries.get();
+        if !response.active || max_entries == 0 || ttl == 0 {
+            return None;
+        }
+
+        let (expiration, stale_until) = match response.exp {
+            Some(exp) => (exp.min(now + ttl), exp.min(now + ttl + self.grace.get())),
+            None => (now + ttl, now + ttl + self.grace.get()),
+        };
+        if expiration <= now {
+            return None;
+        }
+
+        //replacing the result of the token frees its own entry, keeping it to be compared with
+        let key = crypto::sha256_hex(token);
+        let mut entries = self.entries.borrow_mut();
+        let full = |entries: &HashMap<String, Entry>| {
+            !entries.contains_key(&key) && entries.len() >= max_entries
+        };
+        if full(&entries) {
+            entries.retain(|_, entry| now < entry.stale_until);
+        }
+
+        //evicts the entry closest to its expiration when the cache is still full
+        if full(&entries) {
+            let closest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stale_until)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = closest {
+                entries.remove(&key);
+            }
+        }
+
+        entries
+            .insert(
+                key,
+                Entry {
+                    expiration,
+                    stale_until,
+                    route: route.to_string(),
+                    response: response.clone(),
+                },
+            )
+            .filter(|replaced| replaced.route == route)
+            .map(|replaced| replaced.response)
+    }
+}
+
+/// Maximum number of rejected tokens remembered at once
+const MAX_REJECTIONS: usize = 10000;
+
+/// Remembers for a short while the hashes of the structurally invalid tokens, so the ones
+/// replayed by scanners are dropped without being parsed again
+pub struct RejectionCache {
+    ttl: Cell<u64>,
+    entries: RefCell<HashMap<String, u64>>,
+}
+
+impl RejectionCache {
+    pub fn new(ttl: u64) -> Self {
+        Self {
+            ttl: Cell::new(ttl),
+            entries: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn retune(&self, ttl: u64) {
+        self.ttl.set(ttl);
+    }
+
+    /// Returns true when the token was rejected recently
+    pub fn contains(&self, token: &str, now: u64) -> bool {
+        self.entries
+            .borrow()
+            .get(&crypto::sha256_hex(token))
+            .is_some_and(|expiration| now < *expiration)
+    }
+
+    pub fn insert(&self, token: &str, now: u64) {
+        let mut entries = self.entries.borrow_mut();
+        if entries.len() >= MAX_REJECTIONS {
+            entries.retain(|_, expiration| now < *expiration);
+        }
+        if entries.len() < MAX_REJECTIONS {
+            entries.insert(crypto::sha256_hex(token), now + self.ttl.get());
+        }
+    }
+}
+
+/// Remembers for a short while the hashes of the tokens the authorization server reported as
+/// inactive or expired, so the clients retrying with them are rejected without introspecting
+pub struct NegativeCache {
+    ttl: Cell<u64>,
+    entries: RefCell<HashMap<String, (u64, bool, String)>>,
+}
+
+impl NegativeCache {
+    pub fn new(ttl: u64) -> Self {
+        Self {
+            ttl: Cell::new(ttl),
+            entries: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn retune(&self, ttl: u64) {
+        self.ttl.set(ttl);
+    }
+
+    /// Returns whether the recently rejected token was expired, as opposed to inactive, when it was
+    /// rejected by the endpoint it is now validated against
+    pub fn get(&self, token: &str, route: &str, now: u64) -> Option<bool> {
+        self.entries
+            .borrow()
+            .get(&crypto::sha256_hex(token))
+            .filter(|(expiration, _, rejected_by)| now < *expiration && rejected_by == route)
+            .map(|(_, expired, _)| *expired)
+    }
+
+    pub fn insert(&self, token: &str, route: &str, expired: bool, now: u64) {
+        let mut entries = self.entries.borrow_mut();
+        if entries.len() >= MAX_REJECTIONS {
+            entries.retain(|_, (expiration, _, _)| now < *expiration);
+        }
+        if entries.len() < MAX_REJECTIONS {
+            entries.insert(
+                crypto::sha256_hex(token),
+                (now + self.ttl.get(), expired, route.to_string()),
+            );
+        }
+    }
+
+    /// Drops the rejections of the endpoints no longer configured
+    pub fn retain_routes(&self, routes: &HashSet<String>) {
+        self.entries
+            .borrow_mut()
+            .retain(|_, (_, _, rejected_by)| routes.contains(rejected_by));
+    }
+}
+
+/// Maximum number of tokens waiting to be validated again at once
+const MAX_REVALIDATIONS: usize = 1000;
+
+/// Tokens whose stale results were served, waiting for the background job to validate them again.
+/// They are only kept in memory until the job picks them up.
+#[derive(Default)]
+pub struct Revalidations {
+    pending: RefCell<Vec<(String, Option<String>)>>,
+}
+
+impl Revalidations {
+    /// Queues the token along with the issuer named by the request, unless it is already waiting
+    /// or the queue is full
+    pub fn enqueue(&self, token: &str, issuer: Option<&str>) {
+        let mut pending = self.pending.borrow_mut();
+        if pending.len() < MAX_REVALIDATIONS && !pending.iter().any(|(queued, _)| queued == token) {
+            pending.push((token.to_string(), issuer.map(str::to_string)));
+        }
+    }
+
+    pub fn pop(&self) -> Option<(String, Option<String>)> {
+        self.pending.borrow_mut().pop()
+    }
+}
+
+/// Shrinks the TTL of the introspection cache while the upstream services reject the tokens it
+/// served as valid, which hints at revocations, and relaxes it once they agree again
+pub struct AdaptiveTtl {
+    min: u64,
+    max: u64,
+    threshold: u64,
+    window: u64,
+    effective: Cell<u64>,
+    served: Cell<u64>,
+    diverged: Cell<u64>,
+}
+
+impl AdaptiveTtl {
+    /// Adapts the TTL between the configured minimum and the cacheTtlSeconds
+    pub fn new(min: u64, max: u64, threshold: u64, window: u64) -> Self {
+        Self {
+            min: min.min(max),
+            max,
+            threshold,
+            window: window.max(1),
+            effective: Cell::new(max),
+            served: Cell::new(0),
+            diverged: Cell::new(0),
+        }
+    }
+
+    /// Records whether the upstream service rejected a request served from the cache. At the end
+    /// of each window, halves the TTL when the percentage of rejections exceeds the threshold and
+    /// doubles it back when it falls under half the threshold, returning the new TTL.
+    pub fn observe(&self, diverged: bool) -> Option<u64> {
+        self.served.set(self.served.get() + 1);
+        if diverged {
+            self.diverged.set(self.diverged.get() + 1);
+        }
+        if self.served.get() < self.window {
+            return None;
+        }
+
+        let percent = self.diverged.get() * 100 / self.served.get();
+        self.served.set(0);
+        self.diverged.set(0);
+
+        let current = self.effective.get();
+        let adapted = if percent > self.threshold {
+            (current / 2).max(self.min)
+        } else if percent * 2 <= self.threshold {
+            current.saturating_mul(2).max(1).min(self.max)
+        } else {
+            current
+        };
+
+        self.effective.set(adapted);
+        Some(adapted).filter(|adapted| *adapted != current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    const ROUTE: &str = "route";
+
+    #[test]
+    fn serves_the_results_fresh_then_stale_until_the_grace_period_ends() {
+        let cache = IntrospectionCache::new(60, 10, 30);
+        let response = testing::active(json!({ "exp": 1000 }));
+        cache.insert("token", ROUTE, &response, 100);
+
+        assert!(cache.get("token", ROUTE, 159).is_some());
+        assert!(cache.get_stale("token", ROUTE, 159).is_none());
+        assert!(cache.get("token", ROUTE, 160).is_none());
+        assert!(cache.get_stale("token", ROUTE, 160).is_some());
+        assert!(cache.get_stale("token", ROUTE, 190).is_none());
+    }
+
+    #[test]
+    fn caches_the_results_no_longer_than_the_token_is_valid() {
+        let cache = IntrospectionCache::new(60, 10, 30);
+        cache.insert("token", ROUTE, &testing::active(json!({ "exp": 120 })), 100);
+
+        assert!(cache.get("token", ROUTE, 119).is_some());
+        assert!(cache.get("token", ROUTE, 120).is_none());
+        assert!(cache.get_stale("token", ROUTE, 120).is_none());
+    }
+
+    #[test]
+    fn ignores_the_results_of_another_endpoint() {
+        let cache = IntrospectionCache::new(60, 10, 0);
+        cache.insert("token", ROUTE, &testing::active(json!({})), 100);
+
+        assert!(cache.get("token", "other-route", 100).is_none());
+    }
+
+    #[test]
+    fn evicts_the_result_closest_to_its_expiration_when_full() {
+        let cache = IntrospectionCache::new(60, 2, 0);
+        cache.insert("first", ROUTE, &testing::active(json!({ "exp": 130 })), 100);
+        cache.insert("second", ROUTE, &testing::active(json!({})), 100);
+        cache.insert("third", ROUTE, &testing::active(json!({})), 100);
+
+        assert!(cache.get("first", ROUTE, 100).is_none());
+        assert!(cache.get("second", ROUTE, 100).is_some());
+        assert!(cache.get("third", ROUTE, 100).is_some());
+    }
+
+    #[test]
+    fn remembers_the_rejected_tokens_for_their_endpoint_until_the_ttl_elapses() {
+        let negatives = NegativeCache::new(30);
+        negatives.insert("inactive", ROUTE, false, 100);
+        negatives.insert("expired", ROUTE, true, 100);
+
+        assert_eq!(negatives.get("inactive", ROUTE, 129), Some(false));
+        assert_eq!(negatives.get("expired", ROUTE, 129), Some(true));
+        assert_eq!(negatives.get("inactive", "other-route", 129), None);
+        assert_eq!(negatives.get("inactive", ROUTE, 130), None);
+    }
+
+    #[test]
+    fn queues_each_token_to_revalidate_once() {
+        let revalidations = Revalidations::default();
+        revalidations.enqueue("token", Some("issuer"));
+        revalidations.enqueue("token", None);
+
+        assert_eq!(
+            revalidations.pop(),
+            Some(("token".to_string(), Some("issuer".to_string())))
+        );
+        assert_eq!(revalidations.pop(), None);
+    }
+}
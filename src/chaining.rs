@@ -0,0 +1,67 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use proxy_wasm::hostcalls;
+use serde_json::{json, Value};
+
+use crate::generated::config::Config;
+use crate::{claims, IntrospectionResponse};
+
+/// Returns the reason to skip the validation set by an earlier policy of the chain: either the
+/// value of the veto property, such as a pending rejection, or the authentication it completed
+pub fn veto(config: &Config) -> Option<String> {
+    if let Some(flag) = config.veto_property.as_deref().and_then(property) {
+        let flag = String::from_utf8_lossy(&flag).trim().to_string();
+        if !flag.is_empty() {
+            return Some(flag);
+        }
+    }
+
+    let authentication = config
+        .authentication_property
+        .as_deref()
+        .and_then(property)
+        .and_then(|flag| serde_json::from_slice::<Value>(&flag).ok())?;
+
+    match authentication.get("complete") {
+        Some(Value::Bool(true)) => Some("authenticated".to_string()),
+        _ => None,
+    }
+}
+
+/// Flags the authentication as complete for the later policies of the chain, along with the
+/// principal it established
+pub fn complete(
+    config: &Config,
+    instance: &str,
+    mechanism: &str,
+    response: &IntrospectionResponse,
+) {
+    let name = match config.authentication_property.as_deref() {
+        Some(name) => name,
+        None => return,
+    };
+
+    let flag = json!({
+        "complete": true,
+        "policy": instance,
+        "mechanism": mechanism,
+        "sub": claims::claim_as_string(&response.claims, "sub"),
+        "client_id": claims::claim_as_string(&response.claims, "client_id"),
+        "scope": claims::scopes(&response.claims),
+    })
+    .to_string();
+
+    let path = name.split('.').collect();
+    if let Err(status) = hostcalls::set_property(path, Some(flag.as_bytes())) {
+        logger::debug!(
+            "Unable to flag the authentication as complete. {:?}.",
+            status
+        );
+    }
+}
+
+fn property(name: &str) -> Option<Vec<u8>> {
+    hostcalls::get_property(name.split('.').collect())
+        .ok()
+        .flatten()
+}
@@ -0,0 +1,186 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde_json::{Map, Value};
+
+use crate::generated::config::Config;
+
+/// Claims the granted scopes are read from, as named by the different authorization servers
+pub const SCOPE_CLAIMS: [&str; 2] = ["scope", "scp"];
+
+/// Claim the class of the token, `machine` or `user`, is recorded under once classified
+pub const TOKEN_CLASS_CLAIM: &str = "token_class";
+
+/// Removes the denied claims from the given claim set, returning the names of the claims that were present
+pub fn remove_claims(claims: &mut Map<String, Value>, denied: &[String]) -> Vec<String> {
+    denied
+        .iter()
+        .filter(|name| claims.remove(name.as_str()).is_some())
+        .cloned()
+        .collect()
+}
+
+/// Rewrites the claim names, including the ones of nested objects, applying the given
+/// canonicalization steps in order. Names colliding after the rewrite keep the last value.
+pub fn canonicalize(claims: &mut Map<String, Value>, steps: &[String]) {
+    for (name, mut value) in std::mem::take(claims) {
+        if let Value::Object(nested) = &mut value {
+            canonicalize(nested, steps);
+        }
+
+        let name = steps.iter().fold(name, |name, step| match step.as_str() {
+            "lowercase" => name.to_lowercase(),
+            "trim" => name.trim().to_string(),
+            "unescapeUnicode" => unescape_unicode(&name),
+            _ => name,
+        });
+
+        claims.insert(name, value);
+    }
+}
+
+/// Replaces the literal `\uXXXX` escape sequences of a name with the characters they stand for
+fn unescape_unicode(name: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = name;
+
+    while let Some(start) = rest.find("\\u") {
+        let escaped = rest
+            .get(start + 2..start + 6)
+            .and_then(|code| u32::from_str_radix(code, 16).ok())
+            .and_then(char::from_u32);
+
+        match escaped {
+            Some(escaped) => {
+                unescaped.push_str(&rest[..start]);
+                unescaped.push(escaped);
+                rest = &rest[start + 6..];
+            }
+            None => {
+                unescaped.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Returns the claim with the given name. Names are matched literally first, so namespaced claims
+/// such as `ext_org.unit` need no escaping, and then as a dot separated path into nested objects.
+pub fn lookup<'a>(claims: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    if let Some(value) = claims.get(name) {
+        return Some(value);
+    }
+
+    let mut segments = name.split('.');
+    let first = claims.get(segments.next()?)?;
+    segments.try_fold(first, |value, segment| value.get(segment))
+}
+
+/// Returns the claim rendered as a plain string, without the quotes a JSON string would have
+pub fn claim_as_string(claims: &Map<String, Value>, name: &str) -> Option<String> {
+    lookup(claims, name).map(|value| match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    })
+}
+
+/// Returns the claim rendered as a header value, joining the elements of arrays of strings with
+/// spaces as in the `scope` claim
+pub fn claim_as_header(claims: &Map<String, Value>, name: &str) -> Option<String> {
+    match lookup(claims, name)? {
+        Value::Array(values) if values.iter().all(Value::is_string) => Some(
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => claim_as_string(claims, name),
+    }
+}
+
+/// Returns the scopes granted to the token, whether listed in a space delimited string or in an
+/// array, under either the `scope` or the `scp` claim
+pub fn scopes(claims: &Map<String, Value>) -> Vec<&str> {
+    let mut scopes = vec![];
+
+    for name in SCOPE_CLAIMS.iter() {
+        let granted: Vec<&str> = match claims.get(*name) {
+            Some(Value::String(scope)) => scope.split_whitespace().collect(),
+            Some(Value::Array(scope)) => scope.iter().filter_map(Value::as_str).collect(),
+            _ => continue,
+        };
+
+        for scope in granted {
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+    }
+
+    scopes
+}
+
+/// Returns the audiences the token was issued for, listed in the `aud` claim as a single string or
+/// as an array of strings
+pub fn audiences(claims: &Map<String, Value>) -> Vec<&str> {
+    match claims.get("aud") {
+        Some(Value::String(audience)) => vec![audience.as_str()],
+        Some(Value::Array(audiences)) => audiences.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+/// Renders a template replacing every `{{claim}}` placeholder with the value of the claim,
+/// returning None when any of the referenced claims is missing
+pub fn render(template: &str, claims: &Map<String, Value>) -> Option<String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}")? + start;
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&claim_as_string(claims, rest[start + 2..end].trim())?);
+        rest = &rest[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    Some(rendered)
+}
+
+/// Returns the subjects of the actor chain of a delegated token, from the current actor to the
+/// earliest one, following the nested `act` claims
+pub fn actor_chain(claims: &Map<String, Value>) -> Vec<String> {
+    let mut actors = vec![];
+    let mut act = claims.get("act");
+
+    while let Some(Value::Object(actor)) = act {
+        actors.push(claim_as_string(actor, "sub").unwrap_or_default());
+        act = actor.get("act");
+    }
+
+    actors
+}
+
+/// Classifies the token as issued to a machine or to a user, either because its subject is the
+/// client it was issued to or because a configured claim says so. Returns None when disabled.
+pub fn classify(claims: &Map<String, Value>, config: &Config) -> Option<&'static str> {
+    let machine = match config.token_classification.as_str() {
+        "subEqualsClientId" => {
+            let subject = claim_as_string(claims, "sub");
+            subject.is_some() && subject == claim_as_string(claims, "client_id")
+        }
+        "claim" => {
+            let claim = config.machine_claim.as_deref()?;
+            match (claim_as_string(claims, claim), &config.machine_claim_value) {
+                (Some(value), Some(expected)) => &value == expected,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(if machine { "machine" } else { "user" })
+}
@@ -0,0 +1,62 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+/// Tick of the timer shared by the waits for a free slot and the waits between retries
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Caps the number of simultaneous outbound calls made by the policy instance
+pub struct OutboundLimiter {
+    max: usize,
+    wait: Duration,
+    in_flight: Cell<usize>,
+    timer: Rc<Timer>,
+}
+
+impl OutboundLimiter {
+    pub fn new(max: usize, wait: Duration, timer: Rc<Timer>) -> Self {
+        Self {
+            max,
+            wait,
+            in_flight: Cell::new(0),
+            timer,
+        }
+    }
+
+    /// Returns the number of outbound calls currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.get()
+    }
+
+    /// Waits for a free slot up to the configured time, returning None when none was released
+    pub async fn acquire(&self) -> Option<Permit<'_>> {
+        let start = SystemTime::now();
+
+        loop {
+            if self.in_flight.get() < self.max {
+                self.in_flight.set(self.in_flight.get() + 1);
+                return Some(Permit { limiter: self });
+            }
+
+            if start.elapsed().unwrap_or_default() >= self.wait {
+                return None;
+            }
+
+            self.timer.next_tick().await;
+        }
+    }
+}
+
+/// Slot of an in-flight outbound call, released when dropped
+pub struct Permit<'a> {
+    limiter: &'a OutboundLimiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let in_flight = &self.limiter.in_flight;
+        in_flight.set(in_flight.get().saturating_sub(1));
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{anyhow, Result};
+use base64::alphabet::URL_SAFE;
+use base64::engine::general_purpose::{
+    GeneralPurpose, GeneralPurposeConfig, STANDARD, URL_SAFE_NO_PAD,
+};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+#[cfg(feature = "decision-export")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "private-key-jwt")]
+use p256::ecdsa::signature::Signer;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::FilterError;
+
+/// Returns the lowercase hexadecimal SHA-256 digest of the given value
+pub fn sha256_hex(value: impl AsRef<[u8]>) -> String {
+    Sha256::digest(value.as_ref())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Compares the secrets in constant time, over their digests so their lengths are not leaked either
+pub fn secrets_match(presented: &str, expected: &str) -> bool {
+    let (presented, expected) = (
+        Sha256::digest(presented.as_bytes()),
+        Sha256::digest(expected.as_bytes()),
+    );
+
+    presented
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Encodes the bytes as unpadded base64url, as used by the JOSE specifications
+pub fn base64url(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes base64url, accepting the input both with and without padding
+pub fn base64url_decode(value: &str) -> Option<Vec<u8>> {
+    const LENIENT: GeneralPurpose = GeneralPurpose::new(
+        &URL_SAFE,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+
+    LENIENT.decode(value).ok()
+}
+
+/// Builds the HTTP Basic credentials of an OAuth client, encoding the identifier and the secret
+/// first as required by RFC 6749
+pub fn client_secret_basic(client_id: &str, client_secret: &str) -> String {
+    let encode = |value: &str| -> String {
+        value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    };
+
+    let credentials = format!("{}:{}", encode(client_id), encode(client_secret));
+    format!("Basic {}", STANDARD.encode(credentials))
+}
+
+/// Returns the HMAC-SHA256 of the data keyed with the given secret
+#[cfg(feature = "decision-export")]
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Private key the policy signs its JWTs with, so they can be verified with its public key alone
+pub enum SigningKey {
+    Rs256(Box<RsaPrivateKey>),
+    #[cfg(feature = "private-key-jwt")]
+    Es256(p256::ecdsa::SigningKey),
+}
+
+impl SigningKey {
+    /// Parses the PKCS#8 PEM key of the algorithm, naming the property it was configured with
+    pub fn from_pem(algorithm: &str, pem: &str, property: &str) -> Result<Self> {
+        let invalid = |err: &dyn std::fmt::Display| anyhow!("Invalid {}: {}", property, err);
+
+        match algorithm {
+            #[cfg(feature = "private-key-jwt")]
+            "ES256" => Ok(SigningKey::Es256(
+                p256::ecdsa::SigningKey::from_pkcs8_pem(pem).map_err(|err| invalid(&err))?,
+            )),
+            #[cfg(not(feature = "private-key-jwt"))]
+            "ES256" => anyhow::bail!("The configuration requires the private-key-jwt feature"),
+            _ => Ok(SigningKey::Rs256(Box::new(
+                RsaPrivateKey::from_pkcs8_pem(pem).map_err(|err| invalid(&err))?,
+            ))),
+        }
+    }
+
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            SigningKey::Rs256(_) => "RS256",
+            #[cfg(feature = "private-key-jwt")]
+            SigningKey::Es256(_) => "ES256",
+        }
+    }
+
+    /// Serializes the claims as a compact JWS signed with the key, named by the key id if any
+    pub fn sign(&self, key_id: Option<&str>, claims: &Value) -> Result<String, FilterError> {
+        let mut header = Map::new();
+        header.insert("typ".to_string(), json!("JWT"));
+        header.insert("alg".to_string(), json!(self.algorithm()));
+        if let Some(key_id) = key_id {
+            header.insert("kid".to_string(), json!(key_id));
+        }
+
+        let encode = |value: &Value| base64url(value.to_string().as_bytes());
+        let input = format!("{}.{}", encode(&Value::Object(header)), encode(claims));
+
+        let signature = match self {
+            SigningKey::Rs256(key) => key
+                .sign(
+                    Pkcs1v15Sign::new::<Sha256>(),
+                    &Sha256::digest(input.as_bytes()),
+                )
+                .map_err(|_| FilterError::Unexpected)?,
+            #[cfg(feature = "private-key-jwt")]
+            SigningKey::Es256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(input.as_bytes());
+                signature.to_bytes().to_vec()
+            }
+        };
+
+        Ok(format!("{}.{}", input, base64url(&signature)))
+    }
+}
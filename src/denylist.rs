@@ -0,0 +1,160 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{bail, Result};
+use pdk::api::hl::*;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+
+use crate::generated::config::Config;
+use crate::outbound::{Call, Validators};
+use crate::{claims, crypto, Policy};
+
+/// Document of the deny-list endpoint, listing the revoked token identifiers and token hashes
+#[derive(Deserialize)]
+struct Revoked {
+    #[serde(default)]
+    jti: Vec<String>,
+    #[serde(default, alias = "tokenSha256")]
+    token_sha256: Vec<String>,
+}
+
+/// Tokens revoked by the security team, fetched periodically from the deny-list endpoint and
+/// checked on every request, including the ones answered from the introspection cache
+pub struct DenyList {
+    jtis: RefCell<HashSet<String>>,
+    hashes: RefCell<HashSet<String>>,
+    fetched_at: Cell<Option<u64>>,
+    validators: Validators,
+}
+
+impl DenyList {
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match (&config.denylist_upstream, &config.denylist_host) {
+            (None, _) => Ok(None),
+            (Some(_), None) => bail!("denylistUpstream requires denylistHost"),
+            (Some(_), Some(_)) => Ok(Some(Self {
+                jtis: RefCell::new(HashSet::new()),
+                hashes: RefCell::new(HashSet::new()),
+                fetched_at: Cell::new(None),
+                validators: Validators::default(),
+            })),
+        }
+    }
+
+    /// Returns whether the token, or its jti, was revoked
+    pub fn denies(&self, token: &str, claims: &Map<String, Value>) -> bool {
+        let revoked_jti = claims::claim_as_string(claims, "jti")
+            .is_some_and(|jti| self.jtis.borrow().contains(&jti));
+
+        revoked_jti || self.hashes.borrow().contains(&crypto::sha256_hex(token))
+    }
+
+    /// Fetches the deny-list, keeping the previous one when the fetch fails
+    pub async fn refresh(&self, policy: &Policy, client: &HttpClient, now: u64) -> bool {
+        let config = &policy.config;
+        let authorization = policy.egress_authorization("denylist", client, now).await;
+        let conditional = self.validators.headers();
+        let mut headers: Vec<(&str, &str)> = conditional
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        if let Some(authorization) = authorization.as_deref() {
+            headers.push(("Authorization", authorization));
+        }
+
+        let response = Call::new(
+            "denylist",
+            config.denylist_upstream.as_deref().unwrap_or_default(),
+            config.denylist_host.as_deref().unwrap_or_default(),
+            config.denylist_path.as_str(),
+        )
+        .headers(headers)
+        .send(policy, client)
+        .await;
+
+        let revoked = match response {
+            //the deny-list did not change since the previous fetch
+            Ok(response) if response.status_code() == 304 && self.fetched_at.get().is_some() => {
+                policy.metrics.increment("denylist.not_modified");
+                self.fetched_at.set(Some(now));
+                return true;
+            }
+            Ok(response) if response.status_code() == 200 => {
+                let revoked = crate::bounded(&response, config)
+                    .ok()
+                    .and_then(|body| serde_json::from_slice::<Revoked>(body).ok());
+                //a document that cannot be parsed is fetched again in full
+                if revoked.is_some() {
+                    self.validators.store(&response);
+                }
+                revoked
+            }
+            Ok(response) => {
+                logger::warn!(
+                    "Deny-list request failed with status {}.",
+                    response.status_code()
+                );
+                None
+            }
+            Err(err) => {
+                logger::warn!("Deny-list request failed. {:?}.", err);
+                None
+            }
+        };
+
+        match revoked {
+            Some(revoked) => {
+                if self.fetched_at.get().is_none() {
+                    logger::info!(
+                        "Deny-list loaded with {} entries.",
+                        revoked.jti.len() + revoked.token_sha256.len()
+                    );
+                }
+                self.jtis.replace(revoked.jti.into_iter().collect());
+                self.hashes.replace(
+                    revoked
+                        .token_sha256
+                        .into_iter()
+                        .map(|hash| hash.to_ascii_lowercase())
+                        .collect(),
+                );
+                self.fetched_at.set(Some(now));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    fn denylist() -> DenyList {
+        let policy = testing::policy(json!({
+            "denylistUpstream": "denylist",
+            "denylistHost": "denylist.example.com"
+        }));
+        DenyList::from_config(&policy.config).unwrap().unwrap()
+    }
+
+    #[test]
+    fn denies_the_revoked_tokens_and_token_identifiers() {
+        let denylist = denylist();
+        denylist
+            .jtis
+            .replace(HashSet::from(["revoked-jti".to_string()]));
+        denylist
+            .hashes
+            .replace(HashSet::from([crypto::sha256_hex("revoked-token")]));
+        let claims = |jti: &str| testing::active(json!({ "jti": jti })).claims;
+
+        assert!(denylist.denies("token", &claims("revoked-jti")));
+        assert!(denylist.denies("revoked-token", &claims("jti")));
+        assert!(!denylist.denies("token", &claims("jti")));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+
+use crate::generated::config::Config;
+
+/// Properties that identify the services the policy sends outbound calls to
+const ENDPOINT_PROPERTIES: [&str; 8] = [
+    "upstream",
+    "host",
+    "path",
+    "prefixRoutes",
+    "issuerHeader",
+    "validationStrategy",
+    "userinfoPath",
+    "googleCertsUpstream",
+];
+
+/// Properties whose values must not be reported, only the fact that they changed
+const SECRET_PROPERTIES: [&str; 8] = [
+    "authorization",
+    "issuerRoutes",
+    "clientSecret",
+    "clientAssertionKey",
+    "healthSecret",
+    "receiptSigningKey",
+    "apiKeys",
+    "breakGlassTokens",
+];
+
+/// Difference between two configurations that changes how the policy enforces the requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Category of the change, one of `scopeRequired`, `scopeRelaxed`, `endpoint`, `secret` or
+    /// `setting`
+    pub kind: &'static str,
+    /// Property, or rule within a property, the change applies to
+    pub setting: String,
+    /// Human readable description of the change
+    pub detail: String,
+}
+
+/// Reports the differences between two configurations of the policy that are relevant to its
+/// runtime behavior, so operators can review how enforcement changes before applying an update.
+/// Both configurations must be valid.
+pub fn diff(before: &[u8], after: &[u8]) -> Result<Vec<Change>> {
+    serde_json::from_slice::<Config>(before)?;
+    serde_json::from_slice::<Config>(after)?;
+
+    let before: Map<String, Value> = serde_json::from_slice(before)?;
+    let after: Map<String, Value> = serde_json::from_slice(after)?;
+
+    let mut changes = vec![];
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    for name in names {
+        let old = before.get(name).unwrap_or(&Value::Null);
+        let new = after.get(name).unwrap_or(&Value::Null);
+        if old == new {
+            continue;
+        }
+
+        if name == "versionRules" {
+            changes.extend(scope_changes(old, new));
+        }
+
+        if SECRET_PROPERTIES.contains(&name.as_str()) {
+            changes.push(Change {
+                kind: "secret",
+                setting: name.clone(),
+                detail: "value changed".to_string(),
+            });
+        } else {
+            changes.push(Change {
+                kind: if ENDPOINT_PROPERTIES.contains(&name.as_str()) {
+                    "endpoint"
+                } else {
+                    "setting"
+                },
+                setting: name.clone(),
+                detail: format!("{} -> {}", old, new),
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Reports the scopes each API version starts or stops requiring
+fn scope_changes(before: &Value, after: &Value) -> Vec<Change> {
+    let scopes = |rules: &Value| -> Vec<(String, BTreeSet<String>)> {
+        rules
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|rule| {
+                let prefix = rule["pathPrefix"].as_str().unwrap_or_default().to_string();
+                let required = rule["requiredScopes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect();
+                (prefix, required)
+            })
+            .collect()
+    };
+
+    let before = scopes(before);
+    let after = scopes(after);
+    let required_by = |rules: &[(String, BTreeSet<String>)], prefix: &str| {
+        rules
+            .iter()
+            .find(|(rule, _)| rule == prefix)
+            .map(|(_, required)| required.clone())
+            .unwrap_or_default()
+    };
+
+    let prefixes: BTreeSet<&String> = before
+        .iter()
+        .chain(after.iter())
+        .map(|(prefix, _)| prefix)
+        .collect();
+    let mut changes = vec![];
+
+    for prefix in prefixes {
+        let old = required_by(&before, prefix);
+        let new = required_by(&after, prefix);
+        let setting = format!("versionRules[{}]", prefix);
+
+        for scope in new.difference(&old) {
+            changes.push(Change {
+                kind: "scopeRequired",
+                setting: setting.clone(),
+                detail: format!("scope {} becomes required", scope),
+            });
+        }
+        for scope in old.difference(&new) {
+            changes.push(Change {
+                kind: "scopeRelaxed",
+                setting: setting.clone(),
+                detail: format!("scope {} is no longer required", scope),
+            });
+        }
+    }
+
+    changes
+}
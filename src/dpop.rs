@@ -0,0 +1,361 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::generated::config::Config;
+use crate::jwt::Jwt;
+use crate::{claims, crypto, FilterError, IntrospectionResponse};
+
+/// Type of the proof JWTs defined by RFC 9449
+const PROOF_TYPE: &str = "dpop+jwt";
+
+/// Algorithms the proofs may be signed with, advertised in the challenges
+#[cfg(feature = "private-key-jwt")]
+pub const ALGORITHMS: &str = "RS256 RS384 RS512 ES256";
+#[cfg(not(feature = "private-key-jwt"))]
+pub const ALGORITHMS: &str = "RS256 RS384 RS512";
+
+/// Types of the keys the proofs may be signed with, the EC keys requiring the P-256 curve
+#[cfg(feature = "private-key-jwt")]
+const KEY_TYPES: [&str; 2] = ["RSA", "EC"];
+#[cfg(not(feature = "private-key-jwt"))]
+const KEY_TYPES: [&str; 1] = ["RSA"];
+
+/// Verifies the DPoP proofs presented along with the sender-constrained tokens, remembering the
+/// identifiers of the proofs seen during their validity window so they cannot be replayed
+pub struct ProofVerifier {
+    seen: RefCell<HashMap<String, u64>>,
+    max_entries: usize,
+}
+
+impl ProofVerifier {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        match config.dpop_mode.as_str() {
+            "disabled" => None,
+            _ => Some(Self {
+                seen: RefCell::new(HashMap::new()),
+                max_entries: config.dpop_replay_cache_max_entries.max(1) as usize,
+            }),
+        }
+    }
+
+    /// Verifies the proof of the request against the token and the key it is bound to. The
+    /// unbound tokens are accepted without a proof unless the DPoP mode requires it.
+    pub fn verify(
+        &self,
+        request: &impl HeadersHandler,
+        token: &str,
+        response: &IntrospectionResponse,
+        config: &Config,
+        now: u64,
+    ) -> Result<(), FilterError> {
+        let bound = response
+            .claims
+            .get("cnf")
+            .and_then(|cnf| cnf.get("jkt"))
+            .and_then(Value::as_str);
+        let proof = request.header("dpop");
+
+        let (thumbprint, proof) = match (bound, proof) {
+            (Some(thumbprint), Some(proof)) => (thumbprint, proof),
+            (Some(_), None) => {
+                return Err(FilterError::InvalidDpopProof("The DPoP proof is missing"))
+            }
+            (None, _) if config.dpop_mode == "required" => {
+                return Err(FilterError::InvalidDpopProof(
+                    "The access token is not bound to a key",
+                ))
+            }
+            (None, _) => return Ok(()),
+        };
+
+        let jwt = Jwt::decode(&proof)
+            .ok_or(FilterError::InvalidDpopProof("The DPoP proof is malformed"))?;
+        let jwk = jwt
+            .header
+            .get("jwk")
+            .filter(|_| jwt.header.get("typ").and_then(Value::as_str) == Some(PROOF_TYPE))
+            .ok_or(FilterError::InvalidDpopProof("The DPoP proof is malformed"))?;
+        if !KEY_TYPES.contains(&jwk.get("kty").and_then(Value::as_str).unwrap_or_default()) {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof key type is not supported",
+            ));
+        }
+
+        //the proofs carrying a private key are rejected, as required by RFC 9449
+        if jwk.get("d").is_some() || !jwt.verify_with(jwk) {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof signature is invalid",
+            ));
+        }
+        if jwk_thumbprint(jwk).as_deref() != Some(thumbprint) {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof key is not the one the token is bound to",
+            ));
+        }
+
+        let claim = |name: &str| claims::claim_as_string(&jwt.claims, name);
+        let method = request.header(":method").unwrap_or_default();
+        if claim("htm").as_deref() != Some(method.as_str()) {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof was made for another method",
+            ));
+        }
+        if claim("htu").map(|htu| normalize_uri(&htu)) != Some(request_uri(request)) {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof was made for another URI",
+            ));
+        }
+        let token_hash = crypto::base64url(&Sha256::digest(token.as_bytes()));
+        if claim("ath").as_deref() != Some(token_hash.as_str()) {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof was made for another access token",
+            ));
+        }
+
+        let window = config.dpop_iat_window_seconds.max(0) as u64;
+        let issued = jwt.claims.get("iat").and_then(Value::as_u64).unwrap_or(0);
+        if issued + window < now || issued > now + window {
+            return Err(FilterError::InvalidDpopProof(
+                "The DPoP proof is outside of its validity window",
+            ));
+        }
+
+        let jti =
+            claim("jti").ok_or(FilterError::InvalidDpopProof("The DPoP proof is malformed"))?;
+        self.remember(jti, issued + window, now)
+    }
+
+    /// Records the identifier of the proof until the end of its validity window, rejecting the
+    /// ones already seen. The new proofs are rejected while it is full, since forgetting a proof
+    /// still in its window would let it be replayed.
+    fn remember(&self, jti: String, expiration: u64, now: u64) -> Result<(), FilterError> {
+        let mut seen = self.seen.borrow_mut();
+        seen.retain(|_, until| *until >= now);
+
+        if seen.contains_key(&jti) {
+            return Err(FilterError::InvalidDpopProof("The DPoP proof was replayed"));
+        }
+        if seen.len() >= self.max_entries {
+            return Err(FilterError::InvalidDpopProof(
+                "Too many DPoP proofs are awaiting their expiration",
+            ));
+        }
+
+        seen.insert(jti, expiration);
+        Ok(())
+    }
+}
+
+/// Computes the SHA-256 thumbprint of the public key defined by RFC 7638, over its required
+/// members in lexicographic order
+fn jwk_thumbprint(jwk: &Value) -> Option<String> {
+    let member = |name: &str| jwk.get(name).and_then(Value::as_str);
+
+    let canonical = match member("kty")? {
+        "RSA" => format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            member("e")?,
+            member("n")?
+        ),
+        "EC" => format!(
+            r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+            member("crv")?,
+            member("x")?,
+            member("y")?
+        ),
+        _ => return None,
+    };
+
+    Some(crypto::base64url(&Sha256::digest(canonical.as_bytes())))
+}
+
+/// Returns the URI of the request the proof must have been made for, without query nor fragment
+fn request_uri(request: &impl HeadersHandler) -> String {
+    let scheme = request
+        .header(":scheme")
+        .unwrap_or_else(|| "https".to_string());
+    let authority = request
+        .header(":authority")
+        .or_else(|| request.header("host"))
+        .unwrap_or_default();
+    let path = request.header(":path").unwrap_or_default();
+
+    normalize_uri(&format!("{}://{}{}", scheme, authority, path))
+}
+
+/// Strips the query and the fragment of the URI and lowercases its scheme and authority
+fn normalize_uri(uri: &str) -> String {
+    let uri = uri.split(['?', '#']).next().unwrap_or_default();
+
+    match uri.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = match rest.find('/') {
+                Some(index) => rest.split_at(index),
+                None => (rest, "/"),
+            };
+            format!(
+                "{}://{}{}",
+                scheme.to_ascii_lowercase(),
+                authority.to_ascii_lowercase(),
+                path
+            )
+        }
+        None => uri.to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "private-key-jwt"))]
+mod tests {
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::{Signature, SigningKey};
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, Headers};
+
+    const TOKEN: &str = "bound-token";
+    const URI: &str = "https://api.example.com/orders";
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    /// Public JWK of the key, as carried in the header of the proofs
+    fn jwk(key: &SigningKey) -> Value {
+        let point = key.verifying_key().to_encoded_point(false);
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": crypto::base64url(point.x().unwrap()),
+            "y": crypto::base64url(point.y().unwrap()),
+        })
+    }
+
+    /// Signs a proof of the key for the request, the claims overriding the ones of a valid proof
+    fn proof(key: &SigningKey, claims: Value) -> String {
+        let header = json!({ "typ": PROOF_TYPE, "alg": "ES256", "jwk": jwk(key) });
+        let mut body = json!({
+            "htm": "GET",
+            "htu": URI,
+            "ath": crypto::base64url(&Sha256::digest(TOKEN.as_bytes())),
+            "iat": testing::now(),
+            "jti": "proof-1",
+        });
+        if let (Some(body), Value::Object(claims)) = (body.as_object_mut(), claims) {
+            body.extend(claims);
+        }
+
+        let encode = |value: &Value| crypto::base64url(value.to_string().as_bytes());
+        let input = format!("{}.{}", encode(&header), encode(&body));
+        let signature: Signature = key.sign(input.as_bytes());
+        format!("{}.{}", input, crypto::base64url(&signature.to_bytes()))
+    }
+
+    /// Verifies the proof sent along with a token bound to the given key
+    fn verify(
+        verifier: &ProofVerifier,
+        bound: &SigningKey,
+        proof: &str,
+    ) -> Result<(), FilterError> {
+        let policy = testing::policy(json!({ "dpopMode": "allowed" }));
+        let request = Headers::new(&[
+            (":method", "GET"),
+            (":scheme", "https"),
+            (":authority", "api.example.com"),
+            (":path", "/orders?page=2"),
+            ("dpop", proof),
+        ]);
+        let response = testing::active(json!({
+            "cnf": { "jkt": jwk_thumbprint(&jwk(bound)).unwrap() }
+        }));
+
+        verifier.verify(&request, TOKEN, &response, &policy.config, testing::now())
+    }
+
+    fn verifier(max_entries: i64) -> ProofVerifier {
+        let policy = testing::policy(json!({
+            "dpopMode": "allowed",
+            "dpopReplayCacheMaxEntries": max_entries
+        }));
+        ProofVerifier::from_config(&policy.config).unwrap()
+    }
+
+    fn rejection(result: Result<(), FilterError>) -> &'static str {
+        match result {
+            Err(FilterError::InvalidDpopProof(description)) => description,
+            _ => panic!("the proof was not rejected"),
+        }
+    }
+
+    #[test]
+    fn accepts_an_es256_proof_of_the_bound_key() {
+        assert!(verify(&verifier(10), &key(1), &proof(&key(1), json!({}))).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_proof_made_for_another_request() {
+        let verifier = verifier(10);
+        let mismatched = [
+            (
+                json!({ "htm": "POST" }),
+                "The DPoP proof was made for another method",
+            ),
+            (
+                json!({ "htu": "https://api.example.com/payments" }),
+                "The DPoP proof was made for another URI",
+            ),
+            (
+                json!({ "ath": "other-token-hash" }),
+                "The DPoP proof was made for another access token",
+            ),
+        ];
+
+        for (claims, description) in mismatched {
+            let proof = proof(&key(1), claims);
+            assert_eq!(rejection(verify(&verifier, &key(1), &proof)), description);
+        }
+    }
+
+    #[test]
+    fn rejects_a_proof_of_another_key() {
+        let proof = proof(&key(2), json!({}));
+
+        assert_eq!(
+            rejection(verify(&verifier(10), &key(1), &proof)),
+            "The DPoP proof key is not the one the token is bound to"
+        );
+    }
+
+    #[test]
+    fn rejects_a_replayed_proof() {
+        let verifier = verifier(10);
+        let proof = proof(&key(1), json!({}));
+
+        assert!(verify(&verifier, &key(1), &proof).is_ok());
+        assert_eq!(
+            rejection(verify(&verifier, &key(1), &proof)),
+            "The DPoP proof was replayed"
+        );
+    }
+
+    #[test]
+    fn rejects_the_new_proofs_while_the_replay_cache_is_full() {
+        let verifier = verifier(1);
+        let first = proof(&key(1), json!({ "jti": "proof-1" }));
+        let second = proof(&key(1), json!({ "jti": "proof-2" }));
+
+        assert!(verify(&verifier, &key(1), &first).is_ok());
+        assert_eq!(
+            rejection(verify(&verifier, &key(1), &second)),
+            "Too many DPoP proofs are awaiting their expiration"
+        );
+        assert_eq!(
+            rejection(verify(&verifier, &key(1), &first)),
+            "The DPoP proof was replayed"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+use crate::audit::{self, Severity};
+use crate::{claims, IntrospectionResponse, Policy};
+
+/// Claims expected to change on every validation, left out of the diffs
+const VOLATILE_CLAIMS: [&str; 4] = ["exp", "iat", "nbf", "jti"];
+
+/// Changes of the claims of a token between two of its validations
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Drift {
+    deactivated: bool,
+    scopes_removed: Vec<String>,
+    scopes_added: Vec<String>,
+    /// Boolean claims whose value changed, such as the flag of a disabled account
+    flipped: Vec<String>,
+    /// Other claims changed, added or removed, reported without their values
+    changed: Vec<String>,
+}
+
+impl Drift {
+    pub fn between(previous: &IntrospectionResponse, current: &IntrospectionResponse) -> Self {
+        if previous.active && !current.active {
+            return Self {
+                deactivated: true,
+                ..Self::default()
+            };
+        }
+
+        let (before, after) = (
+            claims::scopes(&previous.claims),
+            claims::scopes(&current.claims),
+        );
+        let names: BTreeSet<&String> = previous
+            .claims
+            .keys()
+            .chain(current.claims.keys())
+            .collect();
+        let (mut flipped, mut changed) = (vec![], vec![]);
+        for name in names {
+            if VOLATILE_CLAIMS.contains(&name.as_str())
+                || claims::SCOPE_CLAIMS.contains(&name.as_str())
+            {
+                continue;
+            }
+
+            match (previous.claims.get(name), current.claims.get(name)) {
+                (before, after) if before == after => {}
+                (Some(Value::Bool(_)), Some(Value::Bool(_))) => flipped.push(name.clone()),
+                _ => changed.push(name.clone()),
+            }
+        }
+
+        Self {
+            deactivated: false,
+            scopes_removed: missing(&before, &after),
+            scopes_added: missing(&after, &before),
+            flipped,
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Returns the scopes of the first list missing from the second one
+fn missing(scopes: &[&str], from: &[&str]) -> Vec<String> {
+    scopes
+        .iter()
+        .filter(|scope| !from.contains(scope))
+        .map(|scope| scope.to_string())
+        .collect()
+}
+
+/// Reports the changes of the claims of a cached token validated again, so the permission changes
+/// made at the authorization server that affect the active sessions can be detected
+pub fn report(
+    token: &str,
+    previous: &IntrospectionResponse,
+    current: &IntrospectionResponse,
+    policy: &Policy,
+) {
+    let drift = Drift::between(previous, current);
+    if drift.is_empty() {
+        return;
+    }
+
+    policy.metrics.increment("refresh.claims_changed");
+    //a token losing access is reported at a higher severity than one gaining it
+    let severity = if drift.deactivated || !drift.scopes_removed.is_empty() {
+        policy.metrics.increment("refresh.access_narrowed");
+        Severity::High
+    } else {
+        Severity::Info
+    };
+
+    audit::emit(
+        &policy.instance,
+        severity,
+        "claims_changed",
+        json!({
+            "token_fingerprint": audit::fingerprint(token),
+            "client_id": claims::claim_as_string(&previous.claims, "client_id"),
+            "deactivated": drift.deactivated,
+            "scopes_removed": drift.scopes_removed,
+            "scopes_added": drift.scopes_added,
+            "flipped": drift.flipped,
+            "changed": drift.changed,
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn diffs_the_claims_changed_between_two_validations() {
+        let previous = testing::active(json!({
+            "scope": "orders:read orders:write",
+            "account_disabled": false,
+            "groups": ["buyers"],
+            "exp": 1000
+        }));
+        let current = testing::active(json!({
+            "scope": "orders:read profile",
+            "account_disabled": true,
+            "groups": ["buyers", "sellers"],
+            "exp": 2000
+        }));
+
+        let drift = Drift::between(&previous, &current);
+
+        assert_eq!(drift.scopes_removed, vec!["orders:write"]);
+        assert_eq!(drift.scopes_added, vec!["profile"]);
+        assert_eq!(drift.flipped, vec!["account_disabled"]);
+        assert_eq!(drift.changed, vec!["groups"]);
+        assert!(Drift::between(&previous, &previous).is_empty());
+    }
+
+    #[test]
+    fn reports_the_deactivated_tokens() {
+        let previous = testing::active(json!({ "scope": "orders:read" }));
+        let mut current = testing::active(json!({}));
+        current.active = false;
+
+        let drift = Drift::between(&previous, &current);
+
+        assert!(drift.deactivated);
+        assert!(drift.scopes_removed.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{bail, Result};
+use pdk::api::hl::*;
+use serde_json::Value;
+use std::cell::RefCell;
+
+use crate::generated::config::Config;
+use crate::outbound::Call;
+use crate::{crypto, Policy};
+
+/// Seconds before its expiration the token is acquired again
+const REFRESH_MARGIN: u64 = 30;
+
+/// Seconds the tokens issued without an expires_in are assumed to remain valid
+const DEFAULT_LIFETIME: u64 = 300;
+
+/// Token owned by the policy, acquired with the client_credentials grant and attached to the
+/// outbound calls the policy makes to services other than the introspection endpoint
+pub struct EgressToken {
+    token: RefCell<Option<(String, u64)>>,
+}
+
+impl EgressToken {
+    /// Enables the token when its endpoint is configured, along with the client credentials
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match (&config.egress_token_upstream, &config.egress_token_host) {
+            (None, _) => Ok(None),
+            (Some(_), None) => bail!("egressTokenUpstream requires egressTokenHost"),
+            (Some(_), Some(_)) if config.client_id.is_none() || config.client_secret.is_none() => {
+                bail!("egressTokenUpstream requires clientId and clientSecret")
+            }
+            (Some(_), Some(_)) => Ok(Some(Self {
+                token: RefCell::new(None),
+            })),
+        }
+    }
+
+    /// Returns whether the token is missing or about to expire
+    pub fn expiring(&self, now: u64) -> bool {
+        self.token
+            .borrow()
+            .as_ref()
+            .is_none_or(|(_, expiration)| now + REFRESH_MARGIN >= *expiration)
+    }
+
+    /// Acquires a new token, keeping the previous one until it expires when the acquisition fails
+    pub async fn acquire(&self, policy: &Policy, client: &HttpClient, now: u64) -> bool {
+        let config = &policy.config;
+        let (client_id, client_secret) = match (&config.client_id, &config.client_secret) {
+            (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+            _ => return false,
+        };
+
+        let mut parameters = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = config.egress_token_scope.as_deref() {
+            parameters.push(("scope", scope));
+        }
+        let body = match serde_urlencoded::to_string(parameters) {
+            Ok(body) => body,
+            Err(_) => return false,
+        };
+        let authorization = crypto::client_secret_basic(client_id, client_secret);
+        let headers = vec![
+            ("content-type", "application/x-www-form-urlencoded"),
+            ("Authorization", authorization.as_str()),
+        ];
+
+        let response = Call::new(
+            "egress_token",
+            config.egress_token_upstream.as_deref().unwrap_or_default(),
+            config.egress_token_host.as_deref().unwrap_or_default(),
+            config.egress_token_path.as_str(),
+        )
+        .headers(headers)
+        .body(body.as_bytes())
+        .send(policy, client)
+        .await;
+
+        let issued = match response {
+            Ok(response) if response.status_code() == 200 => {
+                serde_json::from_slice::<Value>(response.body()).ok()
+            }
+            Ok(response) => {
+                logger::warn!(
+                    "Egress token request failed with status {}.",
+                    response.status_code()
+                );
+                None
+            }
+            Err(err) => {
+                logger::warn!("Egress token request failed. {:?}.", err);
+                None
+            }
+        };
+
+        let access_token = issued.as_ref().and_then(|issued| {
+            let token = issued.get("access_token").and_then(Value::as_str)?;
+            let lifetime = issued.get("expires_in").and_then(Value::as_u64);
+            Some((token.to_string(), lifetime.unwrap_or(DEFAULT_LIFETIME)))
+        });
+
+        match access_token {
+            Some((token, lifetime)) => {
+                policy.metrics.increment("egress_token.acquired");
+                self.token.replace(Some((token, now + lifetime)));
+                true
+            }
+            None => {
+                policy.metrics.increment("egress_token.failures");
+                false
+            }
+        }
+    }
+
+    /// Returns the Authorization header of an outbound call of the given kind, acquiring the token
+    /// first when it is about to expire and no background refresh did it yet
+    pub async fn authorization(
+        &self,
+        call: &str,
+        policy: &Policy,
+        client: &HttpClient,
+        now: u64,
+    ) -> Option<String> {
+        if !policy
+            .config
+            .egress_token_calls
+            .iter()
+            .any(|kind| kind == call)
+        {
+            return None;
+        }
+        if self.expiring(now) {
+            self.acquire(policy, client, now).await;
+        }
+
+        self.token
+            .borrow()
+            .as_ref()
+            .filter(|(_, expiration)| *expiration > now)
+            .map(|(token, _)| format!("Bearer {}", token))
+    }
+}
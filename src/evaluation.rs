@@ -0,0 +1,287 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use serde_json::Value;
+
+use crate::generated::config::Config;
+use crate::{claims, FilterError, IntrospectionResponse, Policy};
+
+/// Claim checks evaluated once the token has been validated, in their evaluation order
+pub const CHECKS: [&str; 14] = [
+    "validity",
+    "claims",
+    "issuedAt",
+    "issuer",
+    "audience",
+    "lifetime",
+    "grantType",
+    "actors",
+    "tenant",
+    "scope",
+    "route",
+    "machineClient",
+    "upgrade",
+    "contentType",
+];
+
+/// Request attributes the claim checks are evaluated against
+pub struct Target {
+    pub method: String,
+    pub host: String,
+    pub path: String,
+    pub upgrade: Option<String>,
+    pub content_type: Option<String>,
+}
+
+impl Target {
+    pub fn of(request: &impl HeadersHandler, config: &Config) -> Self {
+        Self {
+            method: crate::request_method(request, config),
+            host: request
+                .header(":authority")
+                .or_else(|| request.header("host"))
+                .unwrap_or_default(),
+            path: crate::request_path(request),
+            upgrade: request.header("upgrade"),
+            content_type: request.header("content-type"),
+        }
+    }
+}
+
+/// Evaluates the claim check with the given name against the validated token
+pub fn check(
+    name: &str,
+    response: &IntrospectionResponse,
+    policy: &Policy,
+    target: &Target,
+    now: u64,
+) -> Result<(), FilterError> {
+    let config = &policy.config;
+
+    match name {
+        "validity" => crate::check_validity(response, policy, now),
+        //asserts the values of the claims configured in claimValidations
+        "claims" => match policy.rules.failed_validation(&response.claims) {
+            Some(claim) => {
+                logger::debug!("Claim {} does not satisfy its validation.", claim);
+                Err(FilterError::ClaimValidationFailed)
+            }
+            None => Ok(()),
+        },
+        //surfaces the authorization servers whose clock runs ahead by issuing tokens in the future
+        "issuedAt" => {
+            let skew = config.clock_skew_seconds.max(0) as u64;
+
+            match response.claims.get("iat").and_then(Value::as_u64) {
+                Some(iat) if iat > now.saturating_add(skew) => {
+                    policy.metrics.increment("iat.future");
+                    logger::warn!("Token was issued {} seconds in the future.", iat - now);
+
+                    if config.future_issued_at == "reject" {
+                        Err(FilterError::IssuedInFuture)
+                    } else {
+                        Ok(())
+                    }
+                }
+                _ => Ok(()),
+            }
+        }
+        //pins the issuers accepted by the API, as reported in the validation response
+        "issuer" => {
+            let issuer = claims::claim_as_string(&response.claims, "iss");
+
+            if config.allowed_issuers.is_empty()
+                || issuer.is_some_and(|issuer| config.allowed_issuers.contains(&issuer))
+            {
+                Ok(())
+            } else {
+                Err(FilterError::UntrustedIssuer)
+            }
+        }
+        //rejects the tokens minted for other APIs
+        "audience" => {
+            let audiences = claims::audiences(&response.claims);
+
+            if config.expected_audiences.is_empty()
+                || audiences.iter().any(|audience| {
+                    config
+                        .expected_audiences
+                        .iter()
+                        .any(|expected| expected == audience)
+                })
+            {
+                Ok(())
+            } else {
+                Err(FilterError::InvalidAudience)
+            }
+        }
+        //enforces the maximum lifetime of the tokens even when the issuer grants longer ones
+        "lifetime" => {
+            let issued_at = response.claims.get("iat").and_then(Value::as_u64);
+
+            match (config.max_token_lifetime_seconds, response.exp, issued_at) {
+                (Some(max_lifetime), Some(exp), Some(iat))
+                    if exp.saturating_sub(iat) > max_lifetime.max(0) as u64 =>
+                {
+                    Err(FilterError::LifetimeTooLong)
+                }
+                _ => Ok(()),
+            }
+        }
+        //validates the grant through which the token was obtained, as reported by PingFederate
+        "grantType" => {
+            let grant_type = claims::claim_as_string(&response.claims, "grant_type");
+
+            if grant_type.is_some_and(|grant_type| config.denied_grant_types.contains(&grant_type))
+            {
+                Err(FilterError::GrantTypeNotAllowed)
+            } else {
+                Ok(())
+            }
+        }
+        //validates the delegation chain of the tokens obtained through token exchange
+        "actors" => {
+            let actors = claims::actor_chain(&response.claims);
+            let too_deep = config
+                .max_actor_depth
+                .is_some_and(|depth| actors.len() as i64 > depth);
+            let not_allowed = !config.allowed_actors.is_empty()
+                && actors
+                    .iter()
+                    .any(|actor| !config.allowed_actors.contains(actor));
+
+            if too_deep || not_allowed {
+                Err(FilterError::ActorNotAllowed)
+            } else {
+                Ok(())
+            }
+        }
+        //validates that the token was issued for the tenant the request is addressed to
+        "tenant" => match &policy.rules.tenant {
+            Some(tenant) if !tenant.matches(&target.host, &response.claims) => {
+                Err(FilterError::TenantMismatch)
+            }
+            _ => Ok(()),
+        },
+        //validates the scopes required by the policy and by the API version of the request
+        "scope" => {
+            let granted = claims::scopes(&response.claims);
+            if granted.is_empty() && !config.allow_empty_scope {
+                logger::debug!("Token grants no scope.");
+                return Err(FilterError::InsufficientScope);
+            }
+
+            let missing = config
+                .required_scopes
+                .iter()
+                .map(String::as_str)
+                .find(|scope| !granted.contains(scope))
+                .or_else(|| {
+                    policy
+                        .rules
+                        .version_for(&target.path)
+                        .and_then(|version| version.missing_scope(&response.claims))
+                });
+
+            match missing {
+                Some(scope) => {
+                    logger::debug!("Scope {} is required to access the resource.", scope);
+                    Err(FilterError::InsufficientScope)
+                }
+                None => Ok(()),
+            }
+        }
+        //validates the scopes and claims required by the route of the request
+        "route" => match policy.rules.route_for(&target.method, &target.path) {
+            Some(route) => {
+                if let Some(scope) = route.missing_scope(&response.claims) {
+                    logger::debug!("Scope {} is required by the route.", scope);
+                    return Err(FilterError::InsufficientScope);
+                }
+                match route.unmet_claim(&response.claims) {
+                    Some(claim) => {
+                        logger::debug!(
+                            "Claim {} does not have the value required by the route.",
+                            claim
+                        );
+                        Err(FilterError::RouteClaimMismatch)
+                    }
+                    None => Ok(()),
+                }
+            }
+            None => Ok(()),
+        },
+        //validates that the machine tokens were issued to one of the allowed clients
+        "machineClient" => {
+            let machine = claims::claim_as_string(&response.claims, claims::TOKEN_CLASS_CLAIM)
+                .is_some_and(|class| class == "machine");
+            let client_id = claims::claim_as_string(&response.claims, "client_id");
+
+            if machine
+                && !config.machine_client_ids.is_empty()
+                && !client_id
+                    .is_some_and(|client_id| config.machine_client_ids.contains(&client_id))
+            {
+                Err(FilterError::ClientNotAllowed)
+            } else {
+                Ok(())
+            }
+        }
+        //rejects the protocol upgrades, such as h2c, that would bypass the policy on later requests
+        "upgrade" => match &target.upgrade {
+            Some(upgrade)
+                if config.reject_upgrades
+                    && !config
+                        .allowed_upgrades
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(upgrade.trim())) =>
+            {
+                Err(FilterError::UpgradeNotAllowed)
+            }
+            _ => Ok(()),
+        },
+        //validates the content type against the ones accepted by the API version
+        "contentType" => match policy.rules.version_for(&target.path) {
+            Some(version) if !version.accepts(target.content_type.as_deref()) => {
+                Err(FilterError::UnsupportedContentType)
+            }
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Applies the enforcement mode of the cohort the token belongs to for the check, counting the
+/// outcomes per cohort so the readiness of the clients can be tracked before a full cutover
+pub fn enforce(
+    check: &str,
+    result: Result<(), FilterError>,
+    response: &IntrospectionResponse,
+    policy: &Policy,
+) -> Result<(), FilterError> {
+    let cohort = match policy.rules.cohort_for(check, &response.claims) {
+        Some(cohort) => cohort,
+        None => return result,
+    };
+
+    let outcome = match (&result, cohort.monitor) {
+        (Ok(()), _) => "passed",
+        (Err(_), true) => "monitored",
+        (Err(_), false) => "enforced",
+    };
+    policy
+        .metrics
+        .increment(&format!("cohort.{}.{}.{}", cohort.name, check, outcome));
+
+    match result {
+        Err(err) if cohort.monitor => {
+            logger::warn!(
+                "Check {} failed for the {} cohort, which is only monitored. {:?}.",
+                check,
+                cohort.name,
+                err
+            );
+            Ok(())
+        }
+        result => result,
+    }
+}
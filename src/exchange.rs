@@ -0,0 +1,230 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{bail, Result};
+use pdk::api::hl::*;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::generated::config::Config;
+use crate::outbound::Call;
+use crate::{crypto, FilterError, Policy};
+
+/// Grant type of the token exchange defined by RFC 8693
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// Seconds before their expiration the exchanged tokens stop being reused
+const EXPIRATION_MARGIN: u64 = 30;
+
+/// Seconds the tokens issued without an expires_in are assumed to remain valid
+const DEFAULT_LIFETIME: u64 = 300;
+
+/// Audience and resource the exchanged token is requested for
+pub struct Scope<'a> {
+    pub audience: Option<&'a str>,
+    pub resource: Option<&'a str>,
+}
+
+/// Exchanges the validated tokens for the audience-scoped ones the upstream service requires, as
+/// defined by RFC 8693, reusing the issued tokens until shortly before they expire
+pub struct TokenExchange {
+    tokens: RefCell<HashMap<String, (String, u64)>>,
+    max_entries: usize,
+}
+
+impl TokenExchange {
+    /// Enables the exchange when its endpoint is configured, along with the client credentials
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match (&config.exchange_upstream, &config.exchange_host) {
+            (None, _) => Ok(None),
+            (Some(_), None) => bail!("exchangeUpstream requires exchangeHost"),
+            (Some(_), Some(_)) if config.client_id.is_none() || config.client_secret.is_none() => {
+                bail!("exchangeUpstream requires clientId and clientSecret")
+            }
+            (Some(_), Some(_)) => Ok(Some(Self {
+                tokens: RefCell::new(HashMap::new()),
+                max_entries: config.exchange_cache_max_entries.max(1) as usize,
+            })),
+        }
+    }
+
+    /// Returns the token the validated one is exchanged for, narrowed to the audience and
+    /// resource of the route, calling the token endpoint unless a token previously issued for the
+    /// same pair is still valid
+    pub async fn exchange(
+        &self,
+        token: &str,
+        scope: Scope<'_>,
+        policy: &Policy,
+        client: &HttpClient,
+        now: u64,
+    ) -> Result<String, FilterError> {
+        let key = crypto::sha256_hex(format!(
+            "{}\n{}\n{}",
+            token,
+            scope.audience.unwrap_or_default(),
+            scope.resource.unwrap_or_default()
+        ));
+        if let Some((exchanged, _)) = self
+            .tokens
+            .borrow()
+            .get(&key)
+            .filter(|(_, expiration)| *expiration > now)
+        {
+            policy.metrics.increment("exchange.hit");
+            return Ok(exchanged.clone());
+        }
+
+        let (exchanged, lifetime) = request(token, &scope, policy, client)
+            .await
+            .inspect_err(|_| policy.metrics.increment("exchange.failures"))?;
+        policy.metrics.increment("exchange.issued");
+
+        if lifetime > EXPIRATION_MARGIN {
+            self.remember(
+                key,
+                exchanged.clone(),
+                now + lifetime - EXPIRATION_MARGIN,
+                now,
+            );
+        }
+        Ok(exchanged)
+    }
+
+    /// Keeps the exchanged token, making room by forgetting the expired ones first and then the
+    /// ones closest to their expiration
+    fn remember(&self, key: String, exchanged: String, expiration: u64, now: u64) {
+        let mut tokens = self.tokens.borrow_mut();
+        if tokens.len() >= self.max_entries {
+            tokens.retain(|_, (_, expiration)| *expiration > now);
+        }
+        if tokens.len() >= self.max_entries {
+            let earliest = tokens
+                .iter()
+                .min_by_key(|(_, (_, expiration))| *expiration)
+                .map(|(key, _)| key.clone());
+            if let Some(earliest) = earliest {
+                tokens.remove(&earliest);
+            }
+        }
+
+        tokens.insert(key, (exchanged, expiration));
+    }
+}
+
+/// Calls the token endpoint, returning the issued token along with its lifetime
+async fn request(
+    token: &str,
+    scope: &Scope<'_>,
+    policy: &Policy,
+    client: &HttpClient,
+) -> Result<(String, u64), FilterError> {
+    let config = &policy.config;
+    let (client_id, client_secret) = match (&config.client_id, &config.client_secret) {
+        (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+        _ => return Err(FilterError::ExchangeFailed),
+    };
+
+    let mut parameters = vec![
+        ("grant_type", GRANT_TYPE),
+        ("subject_token", token),
+        (
+            "subject_token_type",
+            config.exchange_subject_token_type.as_str(),
+        ),
+    ];
+    if let Some(audience) = scope.audience {
+        parameters.push(("audience", audience));
+    }
+    if let Some(resource) = scope.resource {
+        parameters.push(("resource", resource));
+    }
+    if let Some(scopes) = config.exchange_scope.as_deref() {
+        parameters.push(("scope", scopes));
+    }
+    let body = serde_urlencoded::to_string(parameters).map_err(|_| FilterError::Unexpected)?;
+    let authorization = crypto::client_secret_basic(client_id, client_secret);
+    let headers = vec![
+        ("content-type", "application/x-www-form-urlencoded"),
+        ("Authorization", authorization.as_str()),
+    ];
+
+    let response = Call::new(
+        "exchange",
+        config.exchange_upstream.as_deref().unwrap_or_default(),
+        config.exchange_host.as_deref().unwrap_or_default(),
+        config.exchange_path.as_str(),
+    )
+    .headers(headers)
+    .body(body.as_bytes())
+    .send(policy, client)
+    .await?;
+
+    if response.status_code() != 200 {
+        logger::warn!(
+            "Token exchange failed with status {}.",
+            response.status_code()
+        );
+        return Err(FilterError::ExchangeFailed);
+    }
+
+    let issued: Value = serde_json::from_slice(crate::bounded(&response, config)?)
+        .map_err(FilterError::NonParsableIntrospectionBody)?;
+    let exchanged = issued
+        .get("access_token")
+        .and_then(Value::as_str)
+        .ok_or(FilterError::ExchangeFailed)?;
+    let lifetime = issued.get("expires_in").and_then(Value::as_u64);
+
+    Ok((exchanged.to_string(), lifetime.unwrap_or(DEFAULT_LIFETIME)))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    fn policy(max_entries: i64) -> Policy {
+        testing::policy(json!({
+            "clientId": "gateway",
+            "clientSecret": "secret",
+            "exchangeUpstream": "sts",
+            "exchangeHost": "sts.example.com",
+            "exchangeCacheMaxEntries": max_entries
+        }))
+    }
+
+    fn key(token: &str, audience: &str) -> String {
+        crypto::sha256_hex(format!("{}\n{}\n", token, audience))
+    }
+
+    #[test]
+    fn reuses_the_token_issued_for_the_same_audience() {
+        let policy = policy(10);
+        let exchange = policy.exchange.as_ref().unwrap();
+        exchange.remember(key("token", "orders"), "exchanged".to_string(), 200, 100);
+        let scope = Scope {
+            audience: Some("orders"),
+            resource: None,
+        };
+
+        let reused =
+            block_on(exchange.exchange("token", scope, &policy, &HttpClient::default(), 199));
+
+        assert_eq!(reused.unwrap(), "exchanged");
+    }
+
+    #[test]
+    fn forgets_the_token_closest_to_its_expiration_when_full() {
+        let policy = policy(1);
+        let exchange = policy.exchange.as_ref().unwrap();
+        exchange.remember(key("first", ""), "first".to_string(), 200, 100);
+        exchange.remember(key("second", ""), "second".to_string(), 300, 100);
+
+        let tokens = exchange.tokens.borrow();
+        assert!(tokens.get(&key("first", "")).is_none());
+        assert!(tokens.get(&key("second", "")).is_some());
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+
+use crate::generated::config::{Config, TokenSourcesItem};
+use crate::metrics::Metrics;
+use crate::FilterError;
+
+/// Extracts the token from the request, resolving the configured expression first and falling
+/// back to the configured token sources in order
+pub fn extract_token(
+    request: &impl HeadersHandler,
+    config: &Config,
+) -> Result<Option<String>, FilterError> {
+    if let Some(token) = duplicate_authorization(request, config)? {
+        return Ok(token);
+    }
+
+    //the sender-constrained tokens are presented with the DPoP authorization scheme
+    if config.dpop_mode != "disabled" {
+        let token = from_headers(&request.headers(), "authorization", Some("DPoP "));
+        if token.is_some() {
+            return Ok(token);
+        }
+    }
+
+    let token = config
+        .token_extractor
+        .resolve_on_headers(request)
+        .ok()
+        .and_then(|result| result.as_str().map(str::to_string))
+        .filter(|token| !token.is_empty());
+
+    Ok(token.or_else(|| {
+        let headers = request.headers();
+
+        config
+            .token_sources
+            .iter()
+            .find_map(|source| from_source(&headers, source))
+    }))
+}
+
+/// Isolates the token68 value of the extracted credential, as defined by RFC 7235, from the
+/// auth-params some clients append to it, such as `, charset="utf-8"`, stripping or rejecting them
+/// as set by authorizationParsing
+pub fn normalize_credential(
+    credential: String,
+    config: &Config,
+    metrics: &Metrics,
+) -> Result<String, FilterError> {
+    if config.authorization_parsing == "off" {
+        return Ok(credential);
+    }
+
+    let end = credential
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "-._~+/".contains(c)))
+        .unwrap_or(credential.len());
+    let end = end + credential[end..].len() - credential[end..].trim_start_matches('=').len();
+    let rest = credential[end..].trim_start();
+
+    let strict = config.authorization_parsing == "strict";
+
+    //the lenient parsing leaves the credentials it cannot make sense of to the validation
+    if end == 0 || !(rest.is_empty() || auth_params(rest)) {
+        metrics.increment("authorization.malformed");
+        return if strict {
+            Err(FilterError::MalformedToken)
+        } else {
+            Ok(credential)
+        };
+    }
+    if rest.is_empty() {
+        return Ok(credential);
+    }
+
+    if strict {
+        metrics.increment("authorization.params_rejected");
+        return Err(FilterError::MalformedToken);
+    }
+    metrics.increment("authorization.params_stripped");
+    Ok(credential[..end].to_string())
+}
+
+/// Returns whether the value is a list of auth-params following the token, each a name and a
+/// value separated by an equals sign
+fn auth_params(value: &str) -> bool {
+    let params = match value.strip_prefix(',') {
+        Some(params) => params,
+        None => return false,
+    };
+
+    params
+        .split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .all(|param| match param.split_once('=') {
+            Some((name, _)) => {
+                let name = name.trim_end();
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+            }
+            None => false,
+        })
+}
+
+/// Removes the credential from the request forwarded to the upstream service, or moves it to the
+/// configured header, as set by forwardToken
+pub fn forward_token(request: &impl HeadersHandler, config: &Config) {
+    let target = match config.forward_token.as_str() {
+        "strip" => None,
+        "rewriteHeader" => config.forward_token_header.as_deref(),
+        _ => return,
+    };
+
+    let headers = std::iter::once("authorization").chain(
+        config
+            .token_sources
+            .iter()
+            .filter_map(|source| source.header.as_deref()),
+    );
+    for header in headers {
+        if let (Some(target), Some(value)) = (target, request.header(header)) {
+            request.set_header(target, &value);
+        }
+        request.remove_header(header);
+    }
+}
+
+/// Looks up a token in the header, query parameter or cookie named by the source, stripping the
+/// prefix of the source
+pub fn from_source(headers: &[(String, String)], source: &TokenSourcesItem) -> Option<String> {
+    let (kind, name, value) = match (&source.header, &source.query, &source.cookie) {
+        (Some(header), _, _) => ("header", header, lookup(headers, header)),
+        (None, Some(parameter), _) => ("query parameter", parameter, query(headers, parameter)),
+        (None, None, Some(cookie)) => ("cookie", cookie, cookie_value(headers, cookie)),
+        (None, None, None) => return None,
+    };
+
+    let token = value?;
+    let token = match source.prefix.as_deref() {
+        Some(prefix) => token.strip_prefix(prefix),
+        None => Some(token.as_str()),
+    }
+    .map(str::trim)
+    .filter(|token| !token.is_empty());
+
+    if token.is_none() {
+        logger::debug!(
+            "Ignoring the malformed token found in the {} {}.",
+            kind,
+            name
+        );
+    }
+
+    token.map(str::to_string)
+}
+
+fn lookup(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+fn query(headers: &[(String, String)], name: &str) -> Option<String> {
+    let path = lookup(headers, ":path")?;
+    let (_, query) = path.split_once('?')?;
+
+    serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+        .ok()?
+        .into_iter()
+        .find(|(parameter, _)| parameter == name)
+        .map(|(_, value)| value)
+}
+
+fn cookie_value(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .filter(|(header, _)| header.eq_ignore_ascii_case("cookie"))
+        .flat_map(|(_, value)| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Resolves the token of the requests carrying several Authorization headers as configured, since
+/// the extractors would otherwise pick one of them arbitrarily. Returns None when the request
+/// carries a single one.
+fn duplicate_authorization(
+    request: &impl HeadersHandler,
+    config: &Config,
+) -> Result<Option<Option<String>>, FilterError> {
+    let headers: Vec<(String, String)> = request
+        .headers()
+        .into_iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+        .collect();
+    if headers.len() < 2 {
+        return Ok(None);
+    }
+
+    let header = match config.duplicate_authorization.as_str() {
+        "reject" => {
+            logger::warn!(
+                "Rejecting a request with {} Authorization headers.",
+                headers.len()
+            );
+            return Err(FilterError::DuplicateAuthorization);
+        }
+        "last" => {
+            logger::warn!(
+                "Taking the last of {} Authorization headers.",
+                headers.len()
+            );
+            headers.last()
+        }
+        _ => {
+            logger::warn!(
+                "Taking the first of {} Authorization headers.",
+                headers.len()
+            );
+            headers.first()
+        }
+    };
+
+    Ok(Some(header.and_then(|header| {
+        from_headers(
+            std::slice::from_ref(header),
+            "authorization",
+            Some("Bearer "),
+        )
+    })))
+}
+
+/// Looks up a token in the full header map, which also contains the HTTP/2 pseudo-headers,
+/// stripping the given prefix from the header value
+pub fn from_headers(
+    headers: &[(String, String)],
+    header: &str,
+    prefix: Option<&str>,
+) -> Option<String> {
+    headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(header))
+        .find_map(|(_, value)| match prefix {
+            Some(prefix) => value.strip_prefix(prefix),
+            None => Some(value.as_str()),
+        })
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, Headers};
+
+    /// Request smuggling a second token in another Authorization header
+    fn request() -> Headers {
+        Headers::new(&[
+            (":path", "/orders"),
+            ("authorization", "Bearer first-token"),
+            ("Authorization", "Bearer last-token"),
+        ])
+    }
+
+    fn extracted(mode: &str) -> Result<Option<String>, FilterError> {
+        let policy = testing::policy(json!({ "duplicateAuthorization": mode }));
+        extract_token(&request(), &policy.config)
+    }
+
+    #[test]
+    fn rejects_the_duplicate_authorization_headers() {
+        let policy = testing::policy(json!({ "duplicateAuthorization": "reject" }));
+
+        assert!(matches!(
+            duplicate_authorization(&request(), &policy.config),
+            Err(FilterError::DuplicateAuthorization)
+        ));
+        assert!(matches!(
+            extracted("reject"),
+            Err(FilterError::DuplicateAuthorization)
+        ));
+    }
+
+    #[test]
+    fn takes_the_first_authorization_header() {
+        let policy = testing::policy(json!({ "duplicateAuthorization": "first" }));
+
+        assert_eq!(
+            duplicate_authorization(&request(), &policy.config).unwrap(),
+            Some(Some("first-token".to_string()))
+        );
+        assert_eq!(extracted("first").unwrap().as_deref(), Some("first-token"));
+    }
+
+    #[test]
+    fn takes_the_last_authorization_header() {
+        let policy = testing::policy(json!({ "duplicateAuthorization": "last" }));
+
+        assert_eq!(
+            duplicate_authorization(&request(), &policy.config).unwrap(),
+            Some(Some("last-token".to_string()))
+        );
+        assert_eq!(extracted("last").unwrap().as_deref(), Some("last-token"));
+    }
+
+    #[test]
+    fn ignores_a_single_authorization_header() {
+        let policy = testing::policy(json!({ "duplicateAuthorization": "reject" }));
+        let request = Headers::new(&[("authorization", "Bearer only-token")]);
+
+        assert!(matches!(
+            duplicate_authorization(&request, &policy.config),
+            Ok(None)
+        ));
+        assert_eq!(
+            extract_token(&request, &policy.config).unwrap().as_deref(),
+            Some("only-token")
+        );
+    }
+
+    #[test]
+    fn looks_up_the_first_matching_header() {
+        let headers = request().headers();
+
+        assert_eq!(
+            from_headers(&headers, "authorization", Some("Bearer ")).as_deref(),
+            Some("first-token")
+        );
+        assert_eq!(from_headers(&headers, "authorization", Some("DPoP ")), None);
+        assert_eq!(from_headers(&headers, "x-access-token", None), None);
+    }
+}
@@ -1,14 +1,1256 @@
 use serde::Deserialize;
 #[derive(Deserialize, Clone, Debug)]
 pub struct Config {
-    #[serde(alias = "authorization")]
+    #[serde(alias = "actorHeader")]
+    pub actor_header: Option<String>,
+    #[serde(
+        alias = "adaptiveTtlDivergencePercent",
+        default = "default_adaptive_ttl_divergence_percent"
+    )]
+    pub adaptive_ttl_divergence_percent: i64,
+    #[serde(alias = "adaptiveTtlMinSeconds")]
+    pub adaptive_ttl_min_seconds: Option<i64>,
+    #[serde(alias = "adaptiveTtlWindow", default = "default_adaptive_ttl_window")]
+    pub adaptive_ttl_window: i64,
+    #[serde(alias = "allowEmptyScope", default = "default_allow_empty_scope")]
+    pub allow_empty_scope: bool,
+    #[serde(alias = "allowedActors", default = "default_allowed_actors")]
+    pub allowed_actors: Vec<String>,
+    #[serde(alias = "allowedIssuers", default = "default_allowed_issuers")]
+    pub allowed_issuers: Vec<String>,
+    #[serde(alias = "allowedUpgrades", default = "default_allowed_upgrades")]
+    pub allowed_upgrades: Vec<String>,
+    #[serde(
+        alias = "analyticsDimensions",
+        default = "default_analytics_dimensions"
+    )]
+    pub analytics_dimensions: Vec<AnalyticsDimensionsItem>,
+    #[serde(
+        alias = "analyticsPropertyPrefix",
+        default = "default_analytics_property_prefix"
+    )]
+    pub analytics_property_prefix: String,
+    #[serde(alias = "apiKeyHeader", default = "default_api_key_header")]
+    pub api_key_header: String,
+    #[serde(alias = "apiKeys", default = "default_api_keys")]
+    pub api_keys: Vec<ApiKeysItem>,
+    #[serde(alias = "authenticationMechanismHeader")]
+    pub authentication_mechanism_header: Option<String>,
+    #[serde(alias = "authenticationProperty")]
+    pub authentication_property: Option<String>,
+    #[serde(
+        alias = "authenticationRoutes",
+        default = "default_authentication_routes"
+    )]
+    pub authentication_routes: Vec<AuthenticationRoutesItem>,
+    #[serde(alias = "authorityOverrides", default = "default_authority_overrides")]
+    pub authority_overrides: Vec<AuthorityOverridesItem>,
+    #[serde(alias = "authorization", default = "default_authorization")]
     pub authorization: String,
+    #[serde(
+        alias = "authorizationParsing",
+        default = "default_authorization_parsing"
+    )]
+    pub authorization_parsing: String,
+    #[serde(alias = "azureAudiences", default = "default_azure_audiences")]
+    pub azure_audiences: Vec<String>,
+    #[serde(alias = "azureTenantId")]
+    pub azure_tenant_id: Option<String>,
+    #[serde(
+        alias = "bodySignatureVerification",
+        default = "default_body_signature_verification"
+    )]
+    pub body_signature_verification: bool,
+    #[serde(alias = "breakGlassTokens", default = "default_break_glass_tokens")]
+    pub break_glass_tokens: Vec<BreakGlassTokensItem>,
+    #[serde(alias = "cacheControl", default = "default_cache_control")]
+    pub cache_control: bool,
+    #[serde(
+        alias = "cacheControlMaxSeconds",
+        default = "default_cache_control_max_seconds"
+    )]
+    pub cache_control_max_seconds: i64,
+    #[serde(alias = "cacheMaxEntries", default = "default_cache_max_entries")]
+    pub cache_max_entries: i64,
+    #[serde(alias = "cacheTtlSeconds")]
+    pub cache_ttl_seconds: Option<i64>,
+    #[serde(alias = "certificateBinding", default = "default_certificate_binding")]
+    pub certificate_binding: String,
+    #[serde(alias = "checkEvaluation", default = "default_check_evaluation")]
+    pub check_evaluation: String,
+    #[serde(alias = "checkPriorities", default = "default_check_priorities")]
+    pub check_priorities: Vec<String>,
+    #[serde(
+        alias = "circuitBreakerCoolDownSeconds",
+        default = "default_circuit_breaker_cool_down_seconds"
+    )]
+    pub circuit_breaker_cool_down_seconds: i64,
+    #[serde(
+        alias = "circuitBreakerFailures",
+        default = "default_circuit_breaker_failures"
+    )]
+    pub circuit_breaker_failures: Vec<String>,
+    #[serde(alias = "circuitBreakerThreshold")]
+    pub circuit_breaker_threshold: Option<i64>,
+    #[serde(
+        alias = "circuitOpenBehavior",
+        default = "default_circuit_open_behavior"
+    )]
+    pub circuit_open_behavior: String,
+    #[serde(
+        alias = "claimCanonicalization",
+        default = "default_claim_canonicalization"
+    )]
+    pub claim_canonicalization: Vec<String>,
+    #[serde(alias = "claimHeaders", default = "default_claim_headers")]
+    pub claim_headers: Vec<ClaimHeadersItem>,
+    #[serde(alias = "claimLimits", default = "default_claim_limits")]
+    pub claim_limits: Vec<ClaimLimitsItem>,
+    #[serde(alias = "claimMappings", default = "default_claim_mappings")]
+    pub claim_mappings: Vec<ClaimMappingsItem>,
+    #[serde(alias = "claimMappingsOnly", default = "default_claim_mappings_only")]
+    pub claim_mappings_only: bool,
+    #[serde(alias = "claimValidations", default = "default_claim_validations")]
+    pub claim_validations: Vec<ClaimValidationsItem>,
+    #[serde(alias = "claimsHeader")]
+    pub claims_header: Option<String>,
+    #[serde(alias = "claimsVersioning", default = "default_claims_versioning")]
+    pub claims_versioning: bool,
+    #[serde(
+        alias = "clientAssertionAlgorithm",
+        default = "default_client_assertion_algorithm"
+    )]
+    pub client_assertion_algorithm: String,
+    #[serde(alias = "clientAssertionAudience")]
+    pub client_assertion_audience: Option<String>,
+    #[serde(alias = "clientAssertionKey")]
+    pub client_assertion_key: Option<String>,
+    #[serde(alias = "clientAssertionKeyId")]
+    pub client_assertion_key_id: Option<String>,
+    #[serde(alias = "clientAuthMethod", default = "default_client_auth_method")]
+    pub client_auth_method: String,
+    #[serde(
+        alias = "clientCertificatePrincipals",
+        default = "default_client_certificate_principals"
+    )]
+    pub client_certificate_principals: Vec<String>,
+    #[serde(alias = "clientId")]
+    pub client_id: Option<String>,
+    #[serde(alias = "clientRateLimit")]
+    pub client_rate_limit: Option<i64>,
+    #[serde(
+        alias = "clientRateLimitMaxClients",
+        default = "default_client_rate_limit_max_clients"
+    )]
+    pub client_rate_limit_max_clients: i64,
+    #[serde(
+        alias = "clientRateLimitWindowSeconds",
+        default = "default_client_rate_limit_window_seconds"
+    )]
+    pub client_rate_limit_window_seconds: i64,
+    #[serde(alias = "clientSecret")]
+    pub client_secret: Option<String>,
+    #[serde(alias = "clockSkewSeconds", default = "default_clock_skew_seconds")]
+    pub clock_skew_seconds: i64,
+    #[serde(
+        alias = "cognitoAppClientIds",
+        default = "default_cognito_app_client_ids"
+    )]
+    pub cognito_app_client_ids: Vec<String>,
+    #[serde(alias = "cognitoJwksUpstream")]
+    pub cognito_jwks_upstream: Option<String>,
+    #[serde(alias = "cognitoUserPoolId")]
+    pub cognito_user_pool_id: Option<String>,
+    #[serde(
+        alias = "concurrencyWaitMillis",
+        default = "default_concurrency_wait_millis"
+    )]
+    pub concurrency_wait_millis: i64,
+    #[serde(alias = "contextIdHeader", default = "default_context_id_header")]
+    pub context_id_header: String,
+    #[serde(alias = "contextIdTrailer", default = "default_context_id_trailer")]
+    pub context_id_trailer: String,
+    #[serde(alias = "decisionExport", default = "default_decision_export")]
+    pub decision_export: bool,
+    #[serde(alias = "decisionExportKey")]
+    pub decision_export_key: Option<String>,
+    #[serde(alias = "decisionLogging", default = "default_decision_logging")]
+    pub decision_logging: String,
+    #[serde(alias = "deniedGrantTypes", default = "default_denied_grant_types")]
+    pub denied_grant_types: Vec<String>,
+    #[serde(
+        alias = "denyUnmatchedRoutes",
+        default = "default_deny_unmatched_routes"
+    )]
+    pub deny_unmatched_routes: bool,
+    #[serde(alias = "denylistHost")]
+    pub denylist_host: Option<String>,
+    #[serde(alias = "denylistPath", default = "default_denylist_path")]
+    pub denylist_path: String,
+    #[serde(
+        alias = "denylistRefreshSeconds",
+        default = "default_denylist_refresh_seconds"
+    )]
+    pub denylist_refresh_seconds: i64,
+    #[serde(alias = "denylistUpstream")]
+    pub denylist_upstream: Option<String>,
+    #[serde(
+        alias = "dpopIatWindowSeconds",
+        default = "default_dpop_iat_window_seconds"
+    )]
+    pub dpop_iat_window_seconds: i64,
+    #[serde(alias = "dpopMode", default = "default_dpop_mode")]
+    pub dpop_mode: String,
+    #[serde(
+        alias = "dpopReplayCacheMaxEntries",
+        default = "default_dpop_replay_cache_max_entries"
+    )]
+    pub dpop_replay_cache_max_entries: i64,
+    #[serde(
+        alias = "duplicateAuthorization",
+        default = "default_duplicate_authorization"
+    )]
+    pub duplicate_authorization: String,
+    #[serde(alias = "egressTokenCalls", default = "default_egress_token_calls")]
+    pub egress_token_calls: Vec<String>,
+    #[serde(alias = "egressTokenHost")]
+    pub egress_token_host: Option<String>,
+    #[serde(alias = "egressTokenPath", default = "default_egress_token_path")]
+    pub egress_token_path: String,
+    #[serde(alias = "egressTokenScope")]
+    pub egress_token_scope: Option<String>,
+    #[serde(alias = "egressTokenUpstream")]
+    pub egress_token_upstream: Option<String>,
+    #[serde(alias = "enforcementCohorts", default = "default_enforcement_cohorts")]
+    pub enforcement_cohorts: Vec<EnforcementCohortsItem>,
+    #[serde(alias = "exchangeAudience")]
+    pub exchange_audience: Option<String>,
+    #[serde(
+        alias = "exchangeCacheMaxEntries",
+        default = "default_exchange_cache_max_entries"
+    )]
+    pub exchange_cache_max_entries: i64,
+    #[serde(alias = "exchangeHost")]
+    pub exchange_host: Option<String>,
+    #[serde(alias = "exchangePath", default = "default_exchange_path")]
+    pub exchange_path: String,
+    #[serde(alias = "exchangeResource")]
+    pub exchange_resource: Option<String>,
+    #[serde(alias = "exchangeScope")]
+    pub exchange_scope: Option<String>,
+    #[serde(
+        alias = "exchangeSubjectTokenType",
+        default = "default_exchange_subject_token_type"
+    )]
+    pub exchange_subject_token_type: String,
+    #[serde(alias = "exchangeUpstream")]
+    pub exchange_upstream: Option<String>,
+    #[serde(alias = "exemptions", default = "default_exemptions")]
+    pub exemptions: Vec<ExemptionsItem>,
+    #[serde(alias = "expectedAudiences", default = "default_expected_audiences")]
+    pub expected_audiences: Vec<String>,
+    #[serde(alias = "explainPath")]
+    pub explain_path: Option<String>,
+    #[serde(alias = "extraParameters", default = "default_extra_parameters")]
+    pub extra_parameters: Vec<ExtraParametersItem>,
+    #[serde(alias = "failOpen", default = "default_fail_open")]
+    pub fail_open: bool,
+    #[serde(alias = "failOpenHeader", default = "default_fail_open_header")]
+    pub fail_open_header: String,
+    #[serde(alias = "fingerprintHeader")]
+    pub fingerprint_header: Option<String>,
+    #[serde(alias = "forwardToken", default = "default_forward_token")]
+    pub forward_token: String,
+    #[serde(alias = "forwardTokenHeader")]
+    pub forward_token_header: Option<String>,
+    #[serde(alias = "futureIssuedAt", default = "default_future_issued_at")]
+    pub future_issued_at: String,
+    #[serde(alias = "googleAudience")]
+    pub google_audience: Option<String>,
+    #[serde(alias = "googleCertsHost", default = "default_google_certs_host")]
+    pub google_certs_host: String,
+    #[serde(alias = "googleCertsUpstream")]
+    pub google_certs_upstream: Option<String>,
+    #[serde(alias = "googleIssuerPattern")]
+    pub google_issuer_pattern: Option<String>,
+    #[serde(alias = "headRequests", default = "default_head_requests")]
+    pub head_requests: String,
+    #[serde(alias = "headerBudgetBytes")]
+    pub header_budget_bytes: Option<i64>,
+    #[serde(alias = "headerMutations", default = "default_header_mutations")]
+    pub header_mutations: Vec<HeaderMutationsItem>,
+    #[serde(alias = "headerPriorities", default = "default_header_priorities")]
+    pub header_priorities: Vec<String>,
+    #[serde(
+        alias = "headersOnlyRequests",
+        default = "default_headers_only_requests"
+    )]
+    pub headers_only_requests: String,
+    #[serde(alias = "healthPath")]
+    pub health_path: Option<String>,
+    #[serde(alias = "healthSecret")]
+    pub health_secret: Option<String>,
+    #[serde(alias = "healthSecretHeader", default = "default_health_secret_header")]
+    pub health_secret_header: String,
     #[serde(alias = "host")]
     pub host: String,
+    #[serde(
+        alias = "idpRateLimitedStatus",
+        default = "default_idp_rate_limited_status"
+    )]
+    pub idp_rate_limited_status: i64,
+    #[serde(alias = "introspectionContextHeader")]
+    pub introspection_context_header: Option<String>,
+    #[serde(alias = "introspectionContextProperty")]
+    pub introspection_context_property: Option<String>,
+    #[serde(alias = "introspectionResponseJwks")]
+    pub introspection_response_jwks: Option<String>,
+    #[serde(
+        alias = "introspectionRetryAttempts",
+        default = "default_introspection_retry_attempts"
+    )]
+    pub introspection_retry_attempts: i64,
+    #[serde(
+        alias = "introspectionRetryDelayMillis",
+        default = "default_introspection_retry_delay_millis"
+    )]
+    pub introspection_retry_delay_millis: i64,
+    #[serde(
+        alias = "introspectionRetryStatuses",
+        default = "default_introspection_retry_statuses"
+    )]
+    pub introspection_retry_statuses: Vec<i64>,
+    #[serde(alias = "introspectionTimeoutMillis")]
+    pub introspection_timeout_millis: Option<i64>,
+    #[serde(alias = "issuerHeader")]
+    pub issuer_header: Option<String>,
+    #[serde(alias = "issuerRoutes", default = "default_issuer_routes")]
+    pub issuer_routes: Vec<IssuerRoutesItem>,
+    #[serde(
+        alias = "jwksBackgroundRefresh",
+        default = "default_jwks_background_refresh"
+    )]
+    pub jwks_background_refresh: bool,
+    #[serde(alias = "jwksHost")]
+    pub jwks_host: Option<String>,
+    #[serde(alias = "jwksPath", default = "default_jwks_path")]
+    pub jwks_path: String,
+    #[serde(alias = "jwksRefreshSeconds", default = "default_jwks_refresh_seconds")]
+    pub jwks_refresh_seconds: i64,
+    #[serde(alias = "jwksUpstream")]
+    pub jwks_upstream: Option<String>,
+    #[serde(alias = "jwtAlgorithms", default = "default_jwt_algorithms")]
+    pub jwt_algorithms: Vec<String>,
+    #[serde(alias = "jwtAudiences", default = "default_jwt_audiences")]
+    pub jwt_audiences: Vec<String>,
+    #[serde(alias = "jwtHandling", default = "default_jwt_handling")]
+    pub jwt_handling: String,
+    #[serde(alias = "jwtIssuer")]
+    pub jwt_issuer: Option<String>,
+    #[serde(alias = "keycloakClients", default = "default_keycloak_clients")]
+    pub keycloak_clients: Vec<String>,
+    #[serde(
+        alias = "keycloakRoleFlattening",
+        default = "default_keycloak_role_flattening"
+    )]
+    pub keycloak_role_flattening: bool,
+    #[serde(
+        alias = "machineCacheMaxEntries",
+        default = "default_machine_cache_max_entries"
+    )]
+    pub machine_cache_max_entries: i64,
+    #[serde(alias = "machineCacheTtlSeconds")]
+    pub machine_cache_ttl_seconds: Option<i64>,
+    #[serde(alias = "machineClaim")]
+    pub machine_claim: Option<String>,
+    #[serde(alias = "machineClaimValue")]
+    pub machine_claim_value: Option<String>,
+    #[serde(alias = "machineClientIds", default = "default_machine_client_ids")]
+    pub machine_client_ids: Vec<String>,
+    #[serde(alias = "malformedTokenCacheSeconds")]
+    pub malformed_token_cache_seconds: Option<i64>,
+    #[serde(alias = "maxActorDepth")]
+    pub max_actor_depth: Option<i64>,
+    #[serde(alias = "maxConcurrentCalls")]
+    pub max_concurrent_calls: Option<i64>,
+    #[serde(alias = "maxResponseBytes", default = "default_max_response_bytes")]
+    pub max_response_bytes: i64,
+    #[serde(alias = "maxTokenLength", default = "default_max_token_length")]
+    pub max_token_length: i64,
+    #[serde(alias = "maxTokenLifetimeSeconds")]
+    pub max_token_lifetime_seconds: Option<i64>,
+    #[serde(alias = "metricsPath")]
+    pub metrics_path: Option<String>,
+    #[serde(alias = "negativeCacheTtlSeconds")]
+    pub negative_cache_ttl_seconds: Option<i64>,
+    #[serde(alias = "opaqueHandling", default = "default_opaque_handling")]
+    pub opaque_handling: String,
+    #[serde(alias = "outboundHeaders", default = "default_outbound_headers")]
+    pub outbound_headers: Vec<OutboundHeadersItem>,
+    #[serde(alias = "outboundProxyUpstream")]
+    pub outbound_proxy_upstream: Option<String>,
+    #[serde(alias = "outboundTimeoutMillis")]
+    pub outbound_timeout_millis: Option<i64>,
+    #[serde(alias = "outboundTraceHeader")]
+    pub outbound_trace_header: Option<String>,
+    #[serde(alias = "outcomeHeaders", default = "default_outcome_headers")]
+    pub outcome_headers: Vec<OutcomeHeadersItem>,
+    #[serde(alias = "outcomeTrailer", default = "default_outcome_trailer")]
+    pub outcome_trailer: String,
+    #[serde(alias = "outcomeTrailers", default = "default_outcome_trailers")]
+    pub outcome_trailers: bool,
     #[serde(alias = "path")]
     pub path: String,
+    #[serde(alias = "policyInstanceName")]
+    pub policy_instance_name: Option<String>,
+    #[serde(alias = "prefixRoutes", default = "default_prefix_routes")]
+    pub prefix_routes: Vec<PrefixRoutesItem>,
+    #[serde(
+        alias = "propagationProfiles",
+        default = "default_propagation_profiles"
+    )]
+    pub propagation_profiles: Vec<PropagationProfilesItem>,
+    #[serde(alias = "realm", default = "default_realm")]
+    pub realm: String,
+    #[serde(alias = "receiptHeader", default = "default_receipt_header")]
+    pub receipt_header: String,
+    #[serde(
+        alias = "receiptSigningAlgorithm",
+        default = "default_receipt_signing_algorithm"
+    )]
+    pub receipt_signing_algorithm: String,
+    #[serde(alias = "receiptSigningKey")]
+    pub receipt_signing_key: Option<String>,
+    #[serde(alias = "receiptSigningKeyId")]
+    pub receipt_signing_key_id: Option<String>,
+    #[serde(alias = "refreshHintHeader", default = "default_refresh_hint_header")]
+    pub refresh_hint_header: String,
+    #[serde(alias = "refreshHintTarget")]
+    pub refresh_hint_target: Option<String>,
+    #[serde(
+        alias = "refreshHintWindowSeconds",
+        default = "default_refresh_hint_window_seconds"
+    )]
+    pub refresh_hint_window_seconds: i64,
+    #[serde(
+        alias = "refreshJitterPercent",
+        default = "default_refresh_jitter_percent"
+    )]
+    pub refresh_jitter_percent: i64,
+    #[serde(
+        alias = "rejectUnknownPrefixes",
+        default = "default_reject_unknown_prefixes"
+    )]
+    pub reject_unknown_prefixes: bool,
+    #[serde(alias = "rejectUpgrades", default = "default_reject_upgrades")]
+    pub reject_upgrades: bool,
+    #[serde(alias = "rejectionResponses", default = "default_rejection_responses")]
+    pub rejection_responses: Vec<RejectionResponsesItem>,
+    #[serde(
+        alias = "remoteActivePointer",
+        default = "default_remote_active_pointer"
+    )]
+    pub remote_active_pointer: String,
+    #[serde(alias = "remoteBody")]
+    pub remote_body: Option<String>,
+    #[serde(alias = "remoteClaimsPointer")]
+    pub remote_claims_pointer: Option<String>,
+    #[serde(alias = "remoteExpPointer")]
+    pub remote_exp_pointer: Option<String>,
+    #[serde(alias = "remoteHeaders", default = "default_remote_headers")]
+    pub remote_headers: Vec<RemoteHeadersItem>,
+    #[serde(alias = "remoteMethod", default = "default_remote_method")]
+    pub remote_method: String,
+    #[serde(alias = "removedClaims", default = "default_removed_claims")]
+    pub removed_claims: Vec<String>,
+    #[serde(alias = "requiredScopes", default = "default_required_scopes")]
+    pub required_scopes: Vec<String>,
+    #[serde(alias = "retryAfterSeconds")]
+    pub retry_after_seconds: Option<i64>,
+    #[serde(alias = "retryJitterSeconds", default = "default_retry_jitter_seconds")]
+    pub retry_jitter_seconds: i64,
+    #[serde(alias = "routes", default = "default_routes")]
+    pub routes: Vec<RoutesItem>,
+    #[serde(alias = "routingHeaders", default = "default_routing_headers")]
+    pub routing_headers: Vec<RoutingHeadersItem>,
+    #[serde(alias = "sharedCache", default = "default_shared_cache")]
+    pub shared_cache: bool,
+    #[serde(
+        alias = "sharedCacheRevocationSeconds",
+        default = "default_shared_cache_revocation_seconds"
+    )]
+    pub shared_cache_revocation_seconds: i64,
+    #[serde(alias = "staleGraceSeconds")]
+    pub stale_grace_seconds: Option<i64>,
+    #[serde(alias = "startupProbe", default = "default_startup_probe")]
+    pub startup_probe: bool,
+    #[serde(alias = "startupProbeToken")]
+    pub startup_probe_token: Option<String>,
+    #[serde(
+        alias = "strictPathNormalization",
+        default = "default_strict_path_normalization"
+    )]
+    pub strict_path_normalization: bool,
+    #[serde(alias = "subjectHeader")]
+    pub subject_header: Option<String>,
+    #[serde(alias = "tenantClaim")]
+    pub tenant_claim: Option<String>,
+    #[serde(alias = "tenantHostPattern")]
+    pub tenant_host_pattern: Option<String>,
+    #[serde(alias = "timeoutStatus", default = "default_timeout_status")]
+    pub timeout_status: i64,
+    #[serde(alias = "tokenCharset", default = "default_token_charset")]
+    pub token_charset: String,
+    #[serde(
+        alias = "tokenClassification",
+        default = "default_token_classification"
+    )]
+    pub token_classification: String,
     #[serde(alias = "tokenExtractor")]
     pub token_extractor: pdk::api::expression::Expression,
+    #[serde(
+        alias = "tokenFormatDetection",
+        default = "default_token_format_detection"
+    )]
+    pub token_format_detection: bool,
+    #[serde(alias = "tokenPrecheck", default = "default_token_precheck")]
+    pub token_precheck: bool,
+    #[serde(alias = "tokenRequireJwt", default = "default_token_require_jwt")]
+    pub token_require_jwt: bool,
+    #[serde(alias = "tokenSlots", default = "default_token_slots")]
+    pub token_slots: Vec<TokenSlotsItem>,
+    #[serde(alias = "tokenSlotsMode", default = "default_token_slots_mode")]
+    pub token_slots_mode: String,
+    #[serde(alias = "tokenSources", default = "default_token_sources")]
+    pub token_sources: Vec<TokenSourcesItem>,
+    #[serde(alias = "tokenTypeHint")]
+    pub token_type_hint: Option<String>,
+    #[serde(alias = "trustedGatewayHeader")]
+    pub trusted_gateway_header: Option<String>,
+    #[serde(alias = "trustedGatewayJwks")]
+    pub trusted_gateway_jwks: Option<String>,
     #[serde(alias = "upstream")]
     pub upstream: String,
+    #[serde(alias = "userinfoPath", default = "default_userinfo_path")]
+    pub userinfo_path: String,
+    #[serde(alias = "validationStrategy", default = "default_validation_strategy")]
+    pub validation_strategy: String,
+    #[serde(alias = "validityPrecedence", default = "default_validity_precedence")]
+    pub validity_precedence: String,
+    #[serde(alias = "versionRules", default = "default_version_rules")]
+    pub version_rules: Vec<VersionRulesItem>,
+    #[serde(alias = "vetoProperty")]
+    pub veto_property: Option<String>,
+    #[serde(alias = "warmUpDelayMillis", default = "default_warm_up_delay_millis")]
+    pub warm_up_delay_millis: i64,
+    #[serde(alias = "warmUpRate", default = "default_warm_up_rate")]
+    pub warm_up_rate: i64,
+    #[serde(alias = "warmUpSeconds")]
+    pub warm_up_seconds: Option<i64>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnalyticsDimensionsItem {
+    #[serde(alias = "claim")]
+    pub claim: Option<String>,
+    #[serde(alias = "decisionField")]
+    pub decision_field: Option<String>,
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "values", default = "default_values")]
+    pub values: Vec<String>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ApiKeysItem {
+    #[serde(alias = "principal")]
+    pub principal: String,
+    #[serde(alias = "sha256")]
+    pub sha256: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct AuthenticationRoutesItem {
+    #[serde(alias = "mechanisms")]
+    pub mechanisms: Vec<String>,
+    #[serde(alias = "pathPrefix")]
+    pub path_prefix: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct AuthorityOverridesItem {
+    #[serde(alias = "authority")]
+    pub authority: String,
+    #[serde(alias = "claim")]
+    pub claim: Option<String>,
+    #[serde(alias = "equals")]
+    pub equals: Option<String>,
+    #[serde(alias = "pathPrefix")]
+    pub path_prefix: Option<String>,
+    #[serde(alias = "scope")]
+    pub scope: Option<String>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct BreakGlassTokensItem {
+    #[serde(alias = "notAfter")]
+    pub not_after: i64,
+    #[serde(alias = "notBefore")]
+    pub not_before: Option<i64>,
+    #[serde(alias = "scope")]
+    pub scope: Option<String>,
+    #[serde(alias = "sha256")]
+    pub sha256: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClaimHeadersItem {
+    #[serde(alias = "claim")]
+    pub claim: String,
+    #[serde(alias = "header")]
+    pub header: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClaimLimitsItem {
+    #[serde(alias = "claim")]
+    pub claim: String,
+    #[serde(alias = "maxBytes")]
+    pub max_bytes: Option<i64>,
+    #[serde(alias = "maxElements")]
+    pub max_elements: Option<i64>,
+    #[serde(alias = "onExceed", default = "default_on_exceed")]
+    pub on_exceed: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClaimMappingsItem {
+    #[serde(alias = "claim")]
+    pub claim: String,
+    #[serde(alias = "pointer")]
+    pub pointer: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClaimValidationsItem {
+    #[serde(alias = "claim")]
+    pub claim: String,
+    #[serde(alias = "operator", default = "default_operator")]
+    pub operator: String,
+    #[serde(alias = "value", default = "default_value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnforcementCohortsItem {
+    #[serde(alias = "check")]
+    pub check: String,
+    #[serde(alias = "claim")]
+    pub claim: Option<String>,
+    #[serde(alias = "mode", default = "default_mode")]
+    pub mode: String,
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "operator", default = "default_operator")]
+    pub operator: String,
+    #[serde(alias = "value", default = "default_value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ExemptionsItem {
+    #[serde(alias = "methods", default = "default_methods")]
+    pub methods: Vec<String>,
+    #[serde(alias = "pathPattern")]
+    pub path_pattern: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ExtraParametersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct HeaderMutationsItem {
+    #[serde(alias = "claim")]
+    pub claim: Option<String>,
+    #[serde(alias = "equals")]
+    pub equals: Option<String>,
+    #[serde(alias = "header")]
+    pub header: String,
+    #[serde(alias = "scope")]
+    pub scope: Option<String>,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct IssuerRoutesItem {
+    #[serde(alias = "authorization", default = "default_authorization")]
+    pub authorization: String,
+    #[serde(alias = "clientId")]
+    pub client_id: Option<String>,
+    #[serde(alias = "clientSecret")]
+    pub client_secret: Option<String>,
+    #[serde(alias = "host")]
+    pub host: String,
+    #[serde(alias = "issuer")]
+    pub issuer: String,
+    #[serde(alias = "path")]
+    pub path: String,
+    #[serde(alias = "upstream")]
+    pub upstream: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct OutboundHeadersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct OutcomeHeadersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct PrefixRoutesItem {
+    #[serde(alias = "authorization")]
+    pub authorization: String,
+    #[serde(alias = "host")]
+    pub host: String,
+    #[serde(alias = "path")]
+    pub path: String,
+    #[serde(alias = "prefix")]
+    pub prefix: String,
+    #[serde(alias = "upstream")]
+    pub upstream: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct PropagationProfilesItem {
+    #[serde(alias = "claimsHeader")]
+    pub claims_header: String,
+    #[serde(alias = "removedClaims", default = "default_removed_claims")]
+    pub removed_claims: Vec<String>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct RejectionResponsesItem {
+    #[serde(alias = "body")]
+    pub body: Option<String>,
+    #[serde(alias = "category")]
+    pub category: String,
+    #[serde(alias = "headers", default = "default_headers")]
+    pub headers: Vec<HeadersItem>,
+    #[serde(alias = "status")]
+    pub status: Option<i64>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct HeadersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct RemoteHeadersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct RoutesItem {
+    #[serde(alias = "exchangeAudience")]
+    pub exchange_audience: Option<String>,
+    #[serde(alias = "exchangeResource")]
+    pub exchange_resource: Option<String>,
+    #[serde(alias = "methods", default = "default_methods")]
+    pub methods: Vec<String>,
+    #[serde(alias = "pathPattern")]
+    pub path_pattern: String,
+    #[serde(alias = "requiredClaims", default = "default_required_claims")]
+    pub required_claims: Vec<RequiredClaimsItem>,
+    #[serde(alias = "requiredScopes", default = "default_required_scopes")]
+    pub required_scopes: Vec<String>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct RequiredClaimsItem {
+    #[serde(alias = "claim")]
+    pub claim: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct RoutingHeadersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct TokenSlotsItem {
+    #[serde(alias = "authorization")]
+    pub authorization: Option<String>,
+    #[serde(alias = "header")]
+    pub header: String,
+    #[serde(alias = "host")]
+    pub host: Option<String>,
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "path")]
+    pub path: Option<String>,
+    #[serde(alias = "prefix")]
+    pub prefix: Option<String>,
+    #[serde(alias = "upstream")]
+    pub upstream: Option<String>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct TokenSourcesItem {
+    #[serde(alias = "cookie")]
+    pub cookie: Option<String>,
+    #[serde(alias = "header")]
+    pub header: Option<String>,
+    #[serde(alias = "prefix")]
+    pub prefix: Option<String>,
+    #[serde(alias = "query")]
+    pub query: Option<String>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct VersionRulesItem {
+    #[serde(alias = "contentTypes", default = "default_content_types")]
+    pub content_types: Vec<String>,
+    #[serde(alias = "pathPrefix")]
+    pub path_prefix: String,
+    #[serde(alias = "requiredScopes", default = "default_required_scopes")]
+    pub required_scopes: Vec<String>,
+    #[serde(alias = "responseHeaders", default = "default_response_headers")]
+    pub response_headers: Vec<ResponseHeadersItem>,
+}
+#[derive(Deserialize, Clone, Debug)]
+pub struct ResponseHeadersItem {
+    #[serde(alias = "name")]
+    pub name: String,
+    #[serde(alias = "value")]
+    pub value: String,
+}
+fn default_allowed_actors() -> Vec<String> {
+    vec![]
+}
+fn default_allowed_issuers() -> Vec<String> {
+    vec![]
+}
+fn default_allowed_upgrades() -> Vec<String> {
+    vec![]
+}
+fn default_analytics_dimensions() -> Vec<AnalyticsDimensionsItem> {
+    vec![]
+}
+fn default_analytics_property_prefix() -> String {
+    "analytics".to_string()
+}
+fn default_values() -> Vec<String> {
+    vec![]
+}
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+fn default_api_keys() -> Vec<ApiKeysItem> {
+    vec![]
+}
+fn default_authentication_routes() -> Vec<AuthenticationRoutesItem> {
+    vec![]
+}
+fn default_azure_audiences() -> Vec<String> {
+    vec![]
+}
+fn default_break_glass_tokens() -> Vec<BreakGlassTokensItem> {
+    vec![]
+}
+fn default_cache_max_entries() -> i64 {
+    1000
+}
+fn default_check_evaluation() -> String {
+    "firstFailure".to_string()
+}
+fn default_check_priorities() -> Vec<String> {
+    vec![]
+}
+fn default_claim_canonicalization() -> Vec<String> {
+    vec![]
+}
+fn default_claim_headers() -> Vec<ClaimHeadersItem> {
+    vec![]
+}
+fn default_claim_limits() -> Vec<ClaimLimitsItem> {
+    vec![]
+}
+fn default_on_exceed() -> String {
+    "truncate".to_string()
+}
+fn default_claim_mappings() -> Vec<ClaimMappingsItem> {
+    vec![]
+}
+fn default_claim_mappings_only() -> bool {
+    false
+}
+fn default_client_certificate_principals() -> Vec<String> {
+    vec![]
+}
+fn default_cognito_app_client_ids() -> Vec<String> {
+    vec![]
+}
+fn default_concurrency_wait_millis() -> i64 {
+    0
+}
+fn default_deny_unmatched_routes() -> bool {
+    false
+}
+fn default_denied_grant_types() -> Vec<String> {
+    vec![]
+}
+fn default_expected_audiences() -> Vec<String> {
+    vec![]
+}
+fn default_google_certs_host() -> String {
+    "www.googleapis.com".to_string()
+}
+fn default_header_mutations() -> Vec<HeaderMutationsItem> {
+    vec![]
+}
+fn default_headers_only_requests() -> String {
+    "validate".to_string()
+}
+fn default_health_secret_header() -> String {
+    "X-Policy-Admin-Secret".to_string()
+}
+fn default_prefix_routes() -> Vec<PrefixRoutesItem> {
+    vec![]
+}
+fn default_receipt_header() -> String {
+    "X-Decision-Receipt".to_string()
+}
+fn default_reject_upgrades() -> bool {
+    false
+}
+fn default_reject_unknown_prefixes() -> bool {
+    false
+}
+fn default_remote_active_pointer() -> String {
+    "/active".to_string()
+}
+fn default_remote_headers() -> Vec<RemoteHeadersItem> {
+    vec![]
+}
+fn default_remote_method() -> String {
+    "POST".to_string()
+}
+fn default_removed_claims() -> Vec<String> {
+    vec![]
+}
+fn default_retry_jitter_seconds() -> i64 {
+    0
+}
+fn default_routing_headers() -> Vec<RoutingHeadersItem> {
+    vec![]
+}
+fn default_idp_rate_limited_status() -> i64 {
+    503
+}
+fn default_jwks_path() -> String {
+    "/.well-known/jwks.json".to_string()
+}
+fn default_jwks_refresh_seconds() -> i64 {
+    3600
+}
+fn default_jwt_algorithms() -> Vec<String> {
+    vec!["RS256".to_string()]
+}
+fn default_jwt_audiences() -> Vec<String> {
+    vec![]
+}
+fn default_jwt_handling() -> String {
+    "introspect".to_string()
+}
+fn default_keycloak_clients() -> Vec<String> {
+    vec![]
+}
+fn default_keycloak_role_flattening() -> bool {
+    false
+}
+fn default_machine_client_ids() -> Vec<String> {
+    vec![]
+}
+fn default_max_response_bytes() -> i64 {
+    1048576
+}
+fn default_max_token_length() -> i64 {
+    8192
+}
+fn default_opaque_handling() -> String {
+    "introspect".to_string()
+}
+fn default_token_classification() -> String {
+    "none".to_string()
+}
+fn default_token_format_detection() -> bool {
+    false
+}
+fn default_token_slots() -> Vec<TokenSlotsItem> {
+    vec![]
+}
+fn default_token_sources() -> Vec<TokenSourcesItem> {
+    vec![]
+}
+fn default_userinfo_path() -> String {
+    "/userinfo".to_string()
+}
+fn default_validation_strategy() -> String {
+    "introspection".to_string()
+}
+fn default_validity_precedence() -> String {
+    "exp".to_string()
+}
+fn default_version_rules() -> Vec<VersionRulesItem> {
+    vec![]
+}
+fn default_content_types() -> Vec<String> {
+    vec![]
+}
+fn default_required_scopes() -> Vec<String> {
+    vec![]
+}
+fn default_response_headers() -> Vec<ResponseHeadersItem> {
+    vec![]
+}
+fn default_body_signature_verification() -> bool {
+    false
+}
+fn default_clock_skew_seconds() -> i64 {
+    0
+}
+fn default_duplicate_authorization() -> String {
+    "first".to_string()
+}
+fn default_introspection_retry_attempts() -> i64 {
+    1
+}
+fn default_introspection_retry_delay_millis() -> i64 {
+    100
+}
+fn default_introspection_retry_statuses() -> Vec<i64> {
+    vec![502, 503, 504]
+}
+fn default_circuit_breaker_cool_down_seconds() -> i64 {
+    30
+}
+fn default_circuit_open_behavior() -> String {
+    "failClosed".to_string()
+}
+fn default_strict_path_normalization() -> bool {
+    false
+}
+fn default_fail_open() -> bool {
+    false
+}
+fn default_fail_open_header() -> String {
+    "X-Auth-Fail-Open".to_string()
+}
+fn default_decision_export() -> bool {
+    false
+}
+fn default_extra_parameters() -> Vec<ExtraParametersItem> {
+    vec![]
+}
+fn default_authorization() -> String {
+    "".to_string()
+}
+fn default_client_auth_method() -> String {
+    "client_secret_basic".to_string()
+}
+fn default_future_issued_at() -> String {
+    "warn".to_string()
+}
+fn default_authority_overrides() -> Vec<AuthorityOverridesItem> {
+    vec![]
+}
+fn default_client_assertion_algorithm() -> String {
+    "RS256".to_string()
+}
+fn default_refresh_hint_window_seconds() -> i64 {
+    300
+}
+fn default_refresh_hint_header() -> String {
+    "X-Token-Refresh".to_string()
+}
+fn default_allow_empty_scope() -> bool {
+    true
+}
+fn default_exemptions() -> Vec<ExemptionsItem> {
+    vec![]
+}
+fn default_methods() -> Vec<String> {
+    vec![]
+}
+fn default_realm() -> String {
+    "oauth2".to_string()
+}
+fn default_rejection_responses() -> Vec<RejectionResponsesItem> {
+    vec![]
+}
+fn default_headers() -> Vec<HeadersItem> {
+    vec![]
+}
+fn default_jwks_background_refresh() -> bool {
+    false
+}
+fn default_timeout_status() -> i64 {
+    504
+}
+fn default_header_priorities() -> Vec<String> {
+    vec![]
+}
+fn default_forward_token() -> String {
+    "preserve".to_string()
+}
+fn default_machine_cache_max_entries() -> i64 {
+    1000
+}
+fn default_claims_versioning() -> bool {
+    false
+}
+fn default_propagation_profiles() -> Vec<PropagationProfilesItem> {
+    vec![]
+}
+fn default_issuer_routes() -> Vec<IssuerRoutesItem> {
+    vec![]
+}
+fn default_routes() -> Vec<RoutesItem> {
+    vec![]
+}
+fn default_required_claims() -> Vec<RequiredClaimsItem> {
+    vec![]
+}
+fn default_egress_token_path() -> String {
+    "/oauth2/token".to_string()
+}
+fn default_egress_token_calls() -> Vec<String> {
+    vec![
+        "jwks".to_string(),
+        "remote".to_string(),
+        "denylist".to_string(),
+    ]
+}
+fn default_dpop_mode() -> String {
+    "disabled".to_string()
+}
+fn default_dpop_iat_window_seconds() -> i64 {
+    60
+}
+fn default_dpop_replay_cache_max_entries() -> i64 {
+    10000
+}
+fn default_outcome_trailers() -> bool {
+    false
+}
+fn default_outcome_trailer() -> String {
+    "x-auth-outcome".to_string()
+}
+fn default_context_id_header() -> String {
+    "x-request-id".to_string()
+}
+fn default_context_id_trailer() -> String {
+    "x-auth-context-id".to_string()
+}
+fn default_adaptive_ttl_divergence_percent() -> i64 {
+    1
+}
+fn default_adaptive_ttl_window() -> i64 {
+    100
+}
+fn default_certificate_binding() -> String {
+    "disabled".to_string()
+}
+fn default_head_requests() -> String {
+    "asGet".to_string()
+}
+fn default_decision_logging() -> String {
+    "off".to_string()
+}
+fn default_enforcement_cohorts() -> Vec<EnforcementCohortsItem> {
+    vec![]
+}
+fn default_mode() -> String {
+    "enforce".to_string()
+}
+fn default_operator() -> String {
+    "equals".to_string()
+}
+fn default_value() -> String {
+    "".to_string()
+}
+fn default_authorization_parsing() -> String {
+    "lenient".to_string()
+}
+fn default_denylist_path() -> String {
+    "/revoked".to_string()
+}
+fn default_denylist_refresh_seconds() -> i64 {
+    30
+}
+fn default_client_rate_limit_window_seconds() -> i64 {
+    60
+}
+fn default_client_rate_limit_max_clients() -> i64 {
+    10000
+}
+fn default_exchange_path() -> String {
+    "/oauth2/token".to_string()
+}
+fn default_exchange_subject_token_type() -> String {
+    "urn:ietf:params:oauth:token-type:access_token".to_string()
+}
+fn default_exchange_cache_max_entries() -> i64 {
+    10000
+}
+fn default_circuit_breaker_failures() -> Vec<String> {
+    vec!["unreachable".to_string()]
+}
+fn default_token_precheck() -> bool {
+    false
+}
+fn default_token_charset() -> String {
+    "token68".to_string()
+}
+fn default_token_require_jwt() -> bool {
+    false
+}
+fn default_startup_probe() -> bool {
+    false
+}
+fn default_outcome_headers() -> Vec<OutcomeHeadersItem> {
+    vec![]
+}
+fn default_shared_cache() -> bool {
+    false
+}
+fn default_shared_cache_revocation_seconds() -> i64 {
+    3600
+}
+fn default_claim_validations() -> Vec<ClaimValidationsItem> {
+    vec![]
+}
+fn default_token_slots_mode() -> String {
+    "all".to_string()
+}
+fn default_cache_control() -> bool {
+    false
+}
+fn default_cache_control_max_seconds() -> i64 {
+    3600
+}
+fn default_outbound_headers() -> Vec<OutboundHeadersItem> {
+    vec![]
+}
+fn default_receipt_signing_algorithm() -> String {
+    "RS256".to_string()
+}
+fn default_refresh_jitter_percent() -> i64 {
+    0
+}
+fn default_warm_up_rate() -> i64 {
+    50
+}
+fn default_warm_up_delay_millis() -> i64 {
+    0
 }
@@ -0,0 +1,83 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+#[cfg(any(test, feature = "bench"))]
+use std::cell::Cell;
+#[cfg(any(test, feature = "bench"))]
+use std::collections::HashMap;
+
+use crate::{Endpoint, FilterError, IntrospectionResponse, Policy};
+
+/// Source of the validation results of the tokens the authorization server is asked about, so the
+/// validation logic can run against canned results instead of a gateway
+pub trait Introspector {
+    async fn introspect(
+        &self,
+        token: &str,
+        endpoint: &Endpoint<'_>,
+    ) -> Result<IntrospectionResponse, FilterError>;
+}
+
+/// Introspects the tokens at the endpoints of the authorization server, as defined by RFC 7662
+pub struct HttpIntrospector<'a> {
+    pub policy: &'a Policy,
+    pub client: &'a HttpClient,
+}
+
+impl Introspector for HttpIntrospector<'_> {
+    async fn introspect(
+        &self,
+        token: &str,
+        endpoint: &Endpoint<'_>,
+    ) -> Result<IntrospectionResponse, FilterError> {
+        crate::introspect_token(token, endpoint, self.policy, self.client).await
+    }
+}
+
+/// Answers with the results registered for each token, reporting the other tokens as inactive,
+/// and counts the calls made to it
+#[cfg(any(test, feature = "bench"))]
+#[derive(Default)]
+pub struct MockIntrospector {
+    responses: HashMap<String, IntrospectionResponse>,
+    unreachable: bool,
+    calls: Cell<usize>,
+}
+
+#[cfg(any(test, feature = "bench"))]
+impl MockIntrospector {
+    pub fn with(mut self, token: &str, response: IntrospectionResponse) -> Self {
+        self.responses.insert(token.to_string(), response);
+        self
+    }
+
+    /// Fails every call as an authorization server that cannot be reached
+    #[cfg(test)]
+    pub fn unreachable(mut self) -> Self {
+        self.unreachable = true;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+}
+
+#[cfg(any(test, feature = "bench"))]
+impl Introspector for MockIntrospector {
+    async fn introspect(
+        &self,
+        token: &str,
+        _endpoint: &Endpoint<'_>,
+    ) -> Result<IntrospectionResponse, FilterError> {
+        self.calls.set(self.calls.get() + 1);
+        if self.unreachable {
+            return Err(FilterError::Timeout);
+        }
+
+        self.responses
+            .get(token)
+            .cloned()
+            .ok_or(FilterError::InactiveToken)
+    }
+}
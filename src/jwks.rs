@@ -0,0 +1,192 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+
+use crate::jwt::Jwt;
+use crate::outbound::{Call, Validators};
+use crate::{claims, FilterError, IntrospectionResponse, Policy};
+
+/// Minimum seconds between two fetches of the JWKS triggered by tokens signed with unknown keys
+const MIN_REFRESH_INTERVAL: u64 = 60;
+
+/// Validates signed JWTs locally against the keys published by the issuer, fetching the JWKS
+/// when it is first needed and refreshing it periodically or when a token names an unknown key
+#[derive(Default)]
+pub struct KeySet {
+    keys: RefCell<Vec<Value>>,
+    fetched_at: Cell<Option<u64>>,
+    validators: Validators,
+}
+
+impl KeySet {
+    pub async fn validate(
+        &self,
+        token: &str,
+        policy: &Policy,
+        client: &HttpClient,
+        authorization: Option<&str>,
+        now: u64,
+    ) -> Result<IntrospectionResponse, FilterError> {
+        let config = &policy.config;
+        let jwt = Jwt::decode(token).ok_or(FilterError::MalformedToken)?;
+
+        let algorithm = jwt.algorithm().unwrap_or_default();
+        if !config
+            .jwt_algorithms
+            .iter()
+            .any(|allowed| allowed == algorithm)
+        {
+            return Err(FilterError::InvalidSignature);
+        }
+
+        let keys = self
+            .keys_for(jwt.key_id(), policy, client, authorization, now)
+            .await?;
+        if !jwt.verify(&keys) {
+            return Err(FilterError::InvalidSignature);
+        }
+
+        if let Some(issuer) = config.jwt_issuer.as_deref() {
+            if claims::claim_as_string(&jwt.claims, "iss").as_deref() != Some(issuer) {
+                return Err(FilterError::UntrustedIssuer);
+            }
+        }
+
+        if !config.jwt_audiences.is_empty()
+            && !claims::audiences(&jwt.claims).iter().any(|audience| {
+                config
+                    .jwt_audiences
+                    .iter()
+                    .any(|accepted| accepted == audience)
+            })
+        {
+            return Err(FilterError::InvalidAudience);
+        }
+
+        Ok(IntrospectionResponse {
+            active: true,
+            max_age: None,
+            exp: jwt.claims.get("exp").and_then(Value::as_u64),
+            nbf: jwt.claims.get("nbf").and_then(Value::as_u64),
+            claims: jwt.claims,
+        })
+    }
+
+    /// Fetches the keys ahead of the requests needing them, keeping the cached ones on failure
+    pub async fn refresh(
+        &self,
+        policy: &Policy,
+        client: &HttpClient,
+        authorization: Option<&str>,
+        now: u64,
+    ) -> bool {
+        match self.fetch(policy, client, authorization).await {
+            Ok(keys) => {
+                self.fetched_at.set(Some(now));
+                if let Some(keys) = keys {
+                    self.keys.replace(keys);
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the cached keys, fetching them again once they are stale or when the token was
+    /// signed with a key that is not in the cached set
+    async fn keys_for(
+        &self,
+        kid: Option<&str>,
+        policy: &Policy,
+        client: &HttpClient,
+        authorization: Option<&str>,
+        now: u64,
+    ) -> Result<Vec<Value>, FilterError> {
+        let known = |keys: &[Value]| match kid {
+            Some(kid) => keys
+                .iter()
+                .any(|key| key.get("kid").and_then(Value::as_str) == Some(kid)),
+            None => true,
+        };
+
+        let refresh = policy.config.jwks_refresh_seconds.max(0) as u64;
+        let stale = match self.fetched_at.get() {
+            Some(fetched_at) if now < fetched_at + refresh => {
+                !known(&self.keys.borrow()) && now >= fetched_at + MIN_REFRESH_INTERVAL
+            }
+            _ => true,
+        };
+
+        //keeps validating with the previous keys when a refresh fails
+        if stale {
+            match self.fetch(policy, client, authorization).await {
+                Ok(keys) => {
+                    self.fetched_at.set(Some(now));
+                    if let Some(keys) = keys {
+                        self.keys.replace(keys);
+                    }
+                }
+                Err(err) if self.fetched_at.get().is_none() => return Err(err),
+                Err(_) => logger::warn!("Unable to refresh the JWKS, using the cached keys."),
+            }
+        }
+
+        Ok(self.keys.borrow().clone())
+    }
+
+    /// Fetches the JWKS document of the issuer, authenticated with the token of the policy if any,
+    /// resolving to None when the document did not change since the previous fetch
+    async fn fetch(
+        &self,
+        policy: &Policy,
+        client: &HttpClient,
+        authorization: Option<&str>,
+    ) -> Result<Option<Vec<Value>>, FilterError> {
+        let config = &policy.config;
+        //only asks for the changes once there are keys to keep
+        let conditional = match self.fetched_at.get() {
+            Some(_) => self.validators.headers(),
+            None => vec![],
+        };
+        let mut headers: Vec<(&str, &str)> = conditional
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        if let Some(authorization) = authorization {
+            headers.push(("Authorization", authorization));
+        }
+        let response = Call::new(
+            "jwks",
+            config.jwks_upstream.as_deref().unwrap_or_default(),
+            config.jwks_host.as_deref().unwrap_or_default(),
+            config.jwks_path.as_str(),
+        )
+        .headers(headers)
+        .send(policy, client)
+        .await?;
+
+        match response.status_code() {
+            304 if self.fetched_at.get().is_some() => {
+                policy.metrics.increment("jwks.not_modified");
+                return Ok(None);
+            }
+            200 => {}
+            status => {
+                logger::warn!("JWKS request failed with status {}.", status);
+                return Err(FilterError::InvalidSignature);
+            }
+        }
+
+        let jwks: Value = serde_json::from_slice(crate::bounded(&response, config)?)
+            .map_err(FilterError::NonParsableIntrospectionBody)?;
+        self.validators.store(&response);
+
+        Ok(Some(
+            jwks.get("keys")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default(),
+        ))
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde_json::{Map, Value};
+use sha2::digest::const_oid::AssociatedOid;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::crypto;
+
+/// JWT split into its parts, whose claims must not be trusted until the signature is verified
+pub struct Jwt<'a> {
+    pub header: Map<String, Value>,
+    pub claims: Map<String, Value>,
+    signing_input: &'a str,
+    signature: Vec<u8>,
+}
+
+impl<'a> Jwt<'a> {
+    pub fn decode(token: &'a str) -> Option<Self> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, claims) = signing_input.split_once('.')?;
+
+        Some(Self {
+            header: serde_json::from_slice(&crypto::base64url_decode(header)?).ok()?,
+            claims: serde_json::from_slice(&crypto::base64url_decode(claims)?).ok()?,
+            signing_input,
+            signature: crypto::base64url_decode(signature)?,
+        })
+    }
+
+    pub fn algorithm(&self) -> Option<&str> {
+        self.header.get("alg").and_then(Value::as_str)
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.header.get("kid").and_then(Value::as_str)
+    }
+
+    /// Verifies the signature against the keys of a JWKS, trying every key when the token does
+    /// not identify the one used to sign it
+    pub fn verify(&self, keys: &[Value]) -> bool {
+        keys.iter()
+            .filter(|key| match self.key_id() {
+                Some(kid) => key.get("kid").and_then(Value::as_str) == Some(kid),
+                None => true,
+            })
+            .any(|key| self.verify_with(key))
+    }
+
+    /// Verifies the signature against a single key, regardless of the key the token names
+    pub fn verify_with(&self, key: &Value) -> bool {
+        let input = self.signing_input.as_bytes();
+        match self.algorithm() {
+            Some("RS256") => verify_rsa::<Sha256>(key, input, &self.signature),
+            Some("RS384") => verify_rsa::<Sha384>(key, input, &self.signature),
+            Some("RS512") => verify_rsa::<Sha512>(key, input, &self.signature),
+            #[cfg(feature = "private-key-jwt")]
+            Some("ES256") => verify_es256(key, input, &self.signature),
+            _ => false,
+        }
+    }
+}
+
+/// Verifies an ECDSA signature made with the P-256 key of the JWK, as the concatenated r and s
+/// defined by RFC 7518
+#[cfg(feature = "private-key-jwt")]
+fn verify_es256(jwk: &Value, input: &[u8], signature: &[u8]) -> bool {
+    use p256::ecdsa::signature::Verifier;
+    use p256::ecdsa::{Signature, VerifyingKey};
+    use p256::EncodedPoint;
+
+    let member = |name: &str| jwk.get(name).and_then(Value::as_str);
+    if member("kty") != Some("EC") || member("crv") != Some("P-256") {
+        return false;
+    }
+
+    let coordinate = |name: &str| {
+        member(name)
+            .and_then(crypto::base64url_decode)
+            .filter(|bytes| bytes.len() == 32)
+    };
+    let (x, y) = match (coordinate("x"), coordinate("y")) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return false,
+    };
+
+    let point =
+        EncodedPoint::from_affine_coordinates(x.as_slice().into(), y.as_slice().into(), false);
+    match (
+        VerifyingKey::from_encoded_point(&point),
+        Signature::from_slice(signature),
+    ) {
+        (Ok(key), Ok(signature)) => key.verify(input, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+/// Verifies a PKCS#1 v1.5 signature made with the RSA key of the JWK over the digest of the input
+fn verify_rsa<D>(jwk: &Value, input: &[u8], signature: &[u8]) -> bool
+where
+    D: Digest + AssociatedOid,
+{
+    if jwk.get("kty").and_then(Value::as_str) != Some("RSA") {
+        return false;
+    }
+
+    let component = |name: &str| {
+        jwk.get(name)
+            .and_then(Value::as_str)
+            .and_then(crypto::base64url_decode)
+            .map(|bytes| BigUint::from_bytes_be(&bytes))
+    };
+
+    let key = match (component("n"), component("e")) {
+        (Some(n), Some(e)) => RsaPublicKey::new(n, e),
+        _ => return false,
+    };
+
+    match key {
+        Ok(key) => key
+            .verify(Pkcs1v15Sign::new::<D>(), &D::digest(input), signature)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
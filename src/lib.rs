@@ -1,164 +1,3818 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+mod admin;
+mod analytics;
+#[cfg(feature = "private-key-jwt")]
+mod assertion;
+mod audit;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod breaker;
+mod budget;
+#[cfg(feature = "cache")]
+mod cache;
+mod chaining;
+mod claims;
+mod concurrency;
+mod crypto;
+mod denylist;
+pub mod diff;
+mod dpop;
+#[cfg(feature = "cache")]
+mod drift;
+mod egress;
+mod evaluation;
+mod exchange;
+mod extraction;
 mod generated;
+mod introspector;
+#[cfg(feature = "jwks")]
+mod jwks;
+mod jwt;
+mod metrics;
+mod mtls;
+mod outbound;
+mod path;
+mod presets;
+mod propagation;
+mod ratelimit;
+mod rejection;
+mod reload;
+mod retry;
+mod rules;
+mod scheduler;
+#[cfg(feature = "cache")]
+mod shared;
+#[cfg(feature = "decision-export")]
+pub mod simulation;
+mod startup;
+#[cfg(test)]
+mod testing;
+mod token;
+#[cfg(feature = "cache")]
+mod warmup;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use pdk::api::hl::*;
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::MapType;
 
-use crate::generated::config::Config;
-use serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "private-key-jwt")]
+use crate::assertion::ClientAssertion;
+use crate::audit::Severity;
+use crate::breaker::CircuitBreaker;
+use crate::budget::Injections;
+#[cfg(feature = "cache")]
+use crate::cache::{AdaptiveTtl, IntrospectionCache, NegativeCache, RejectionCache, Revalidations};
+use crate::concurrency::OutboundLimiter;
+use crate::crypto::SigningKey;
+use crate::denylist::DenyList;
+use crate::dpop::ProofVerifier;
+use crate::egress::EgressToken;
+use crate::evaluation::Target;
+use crate::exchange::{Scope, TokenExchange};
+use crate::generated::config::{BreakGlassTokensItem, Config, IssuerRoutesItem, PrefixRoutesItem};
+use crate::introspector::{HttpIntrospector, Introspector};
+#[cfg(feature = "jwks")]
+use crate::jwks::KeySet;
+use crate::jwt::Jwt;
+use crate::metrics::Metrics;
+use crate::mtls::ClientCertificates;
+use crate::outbound::{Call, Outbound};
+use crate::presets::{CognitoUserPool, GoogleServiceAccounts};
+use crate::propagation::Profile;
+use crate::ratelimit::ClientRateLimiter;
+use crate::reload::RuntimeState;
+use crate::retry::Backoff;
+use crate::rules::Rules;
+use crate::scheduler::Scheduler;
+#[cfg(feature = "cache")]
+use crate::shared::SharedCache;
+use crate::token::TokenFormat;
+#[cfg(feature = "cache")]
+use crate::warmup::WarmUp;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "cache")]
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+/// Authentication mechanisms tried, in order, on the routes without a configured precedence
+const DEFAULT_MECHANISMS: [&str; 4] = ["gateway", "token", "mtls", "apiKey"];
+
+#[derive(Debug)]
 pub enum FilterError {
     Unexpected,
     NoToken,
+    UnknownTokenPrefix,
+    MalformedToken,
+    UnsupportedTokenFormat,
+    UntrustedIssuer,
+    InvalidAudience,
+    UntrustedClientCertificate,
+    InvalidApiKey,
+    CompatibilityCheckFailed(&'static str),
+    InvalidSignature,
+    GrantTypeNotAllowed,
+    ActorNotAllowed,
+    ClientNotAllowed,
+    RateLimited(u64),
+    ClientRateLimited(u64),
     InactiveToken,
     ExpiredToken,
+    LifetimeTooLong,
     NotYetActive,
+    IssuedInFuture,
+    TooManyOutboundCalls,
+    WarmingUp,
+    CircuitOpen,
+    TenantMismatch,
+    InsufficientScope,
+    ClaimTooLarge,
+    UnmatchedRoute,
+    RouteClaimMismatch,
+    ClaimValidationFailed,
+    NonCanonicalPath,
+    UpgradeNotAllowed,
+    UnsupportedContentType,
+    DuplicateAuthorization,
+    TokenPrecheckFailed(&'static str),
+    InvalidBodySignature,
+    InvalidDpopProof(&'static str),
+    TokenBindingMismatch,
+    RevokedToken,
+    ExchangeFailed,
+    ResponseTooLarge(usize),
+    Timeout,
     ClientError(HttpClientError),
+    IdpStatus(u32),
     NonParsableIntrospectionBody(serde_json::Error),
+    UnverifiedIntrospectionResponse,
+    UnsignedIntrospectionResponse,
+}
+
+impl FilterError {
+    /// Returns the stable code of the error, exposed to the clients by the rejection templates
+    pub fn code(&self) -> &'static str {
+        match self {
+            FilterError::Unexpected => "UNEXPECTED_ERROR",
+            FilterError::NoToken => "TOKEN_MISSING",
+            FilterError::UnknownTokenPrefix => "TOKEN_PREFIX_UNKNOWN",
+            FilterError::MalformedToken => "TOKEN_MALFORMED",
+            FilterError::UnsupportedTokenFormat => "TOKEN_FORMAT_UNSUPPORTED",
+            FilterError::UntrustedIssuer => "ISSUER_UNTRUSTED",
+            FilterError::InvalidAudience => "AUDIENCE_INVALID",
+            FilterError::UntrustedClientCertificate => "CLIENT_CERTIFICATE_UNTRUSTED",
+            FilterError::InvalidApiKey => "API_KEY_INVALID",
+            FilterError::CompatibilityCheckFailed(_) => "COMPATIBILITY_CHECK_FAILED",
+            FilterError::InvalidSignature => "SIGNATURE_INVALID",
+            FilterError::GrantTypeNotAllowed => "GRANT_TYPE_NOT_ALLOWED",
+            FilterError::ActorNotAllowed => "ACTOR_NOT_ALLOWED",
+            FilterError::ClientNotAllowed => "CLIENT_NOT_ALLOWED",
+            FilterError::RateLimited(_) => "IDP_RATE_LIMITED",
+            FilterError::ClientRateLimited(_) => "CLIENT_RATE_LIMITED",
+            FilterError::InactiveToken => "TOKEN_INACTIVE",
+            FilterError::ExpiredToken => "TOKEN_EXPIRED",
+            FilterError::LifetimeTooLong => "TOKEN_LIFETIME_TOO_LONG",
+            FilterError::NotYetActive => "TOKEN_NOT_YET_VALID",
+            FilterError::IssuedInFuture => "TOKEN_ISSUED_IN_FUTURE",
+            FilterError::TooManyOutboundCalls => "TOO_MANY_OUTBOUND_CALLS",
+            FilterError::WarmingUp => "WARMING_UP",
+            FilterError::CircuitOpen => "CIRCUIT_OPEN",
+            FilterError::TenantMismatch => "TENANT_MISMATCH",
+            FilterError::InsufficientScope => "INSUFFICIENT_SCOPE",
+            FilterError::ClaimTooLarge => "CLAIM_TOO_LARGE",
+            FilterError::UnmatchedRoute => "ROUTE_UNMATCHED",
+            FilterError::RouteClaimMismatch => "ROUTE_CLAIM_MISMATCH",
+            FilterError::ClaimValidationFailed => "CLAIM_VALIDATION_FAILED",
+            FilterError::NonCanonicalPath => "PATH_NOT_CANONICAL",
+            FilterError::UpgradeNotAllowed => "UPGRADE_NOT_ALLOWED",
+            FilterError::UnsupportedContentType => "CONTENT_TYPE_UNSUPPORTED",
+            FilterError::DuplicateAuthorization => "AUTHORIZATION_DUPLICATED",
+            FilterError::TokenPrecheckFailed(_) => "TOKEN_PRECHECK_FAILED",
+            FilterError::InvalidBodySignature => "BODY_SIGNATURE_INVALID",
+            FilterError::InvalidDpopProof(_) => "DPOP_PROOF_INVALID",
+            FilterError::TokenBindingMismatch => "TOKEN_BINDING_MISMATCH",
+            FilterError::RevokedToken => "TOKEN_REVOKED",
+            FilterError::ExchangeFailed => "TOKEN_EXCHANGE_FAILED",
+            FilterError::ResponseTooLarge(_) => "IDP_RESPONSE_TOO_LARGE",
+            FilterError::Timeout => "IDP_TIMEOUT",
+            FilterError::ClientError(_) => "IDP_UNREACHABLE",
+            FilterError::IdpStatus(_) => "IDP_STATUS_UNEXPECTED",
+            FilterError::NonParsableIntrospectionBody(_) => "IDP_RESPONSE_INVALID",
+            FilterError::UnverifiedIntrospectionResponse => "IDP_RESPONSE_UNVERIFIED",
+            FilterError::UnsignedIntrospectionResponse => "IDP_RESPONSE_UNSIGNED",
+        }
+    }
+
+    /// Returns the category of the error, which decides the status of the rejection
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            FilterError::NonCanonicalPath
+            | FilterError::UpgradeNotAllowed
+            | FilterError::UnsupportedContentType
+            | FilterError::DuplicateAuthorization
+            | FilterError::TokenPrecheckFailed(_)
+            | FilterError::ClientRateLimited(_) => ErrorCategory::Request,
+            FilterError::NoToken
+            | FilterError::UnknownTokenPrefix
+            | FilterError::MalformedToken
+            | FilterError::UnsupportedTokenFormat
+            | FilterError::UntrustedIssuer
+            | FilterError::UntrustedClientCertificate
+            | FilterError::InvalidApiKey
+            | FilterError::CompatibilityCheckFailed(_)
+            | FilterError::InvalidSignature
+            | FilterError::InactiveToken
+            | FilterError::ExpiredToken
+            | FilterError::LifetimeTooLong
+            | FilterError::NotYetActive
+            | FilterError::IssuedInFuture
+            | FilterError::InvalidBodySignature
+            | FilterError::InvalidDpopProof(_)
+            | FilterError::TokenBindingMismatch
+            | FilterError::RevokedToken => ErrorCategory::Authentication,
+            FilterError::InvalidAudience
+            | FilterError::GrantTypeNotAllowed
+            | FilterError::ActorNotAllowed
+            | FilterError::ClientNotAllowed
+            | FilterError::TenantMismatch
+            | FilterError::InsufficientScope
+            | FilterError::ClaimTooLarge
+            | FilterError::UnmatchedRoute
+            | FilterError::RouteClaimMismatch
+            | FilterError::ClaimValidationFailed => ErrorCategory::Authorization,
+            FilterError::Unexpected
+            | FilterError::RateLimited(_)
+            | FilterError::TooManyOutboundCalls
+            | FilterError::WarmingUp
+            | FilterError::CircuitOpen
+            | FilterError::ExchangeFailed
+            | FilterError::ResponseTooLarge(_)
+            | FilterError::Timeout
+            | FilterError::ClientError(_)
+            | FilterError::IdpStatus(_)
+            | FilterError::NonParsableIntrospectionBody(_)
+            | FilterError::UnverifiedIntrospectionResponse
+            | FilterError::UnsignedIntrospectionResponse => ErrorCategory::Infrastructure,
+        }
+    }
+}
+
+/// Category of a failure: a malformed or throttled request (4xx), missing or invalid credentials
+/// (401), valid credentials lacking the privileges for the request (403), or a failure of the
+/// policy or of the services it depends on (5xx)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorCategory {
+    Request,
+    Authentication,
+    Authorization,
+    Infrastructure,
+}
+
+impl ErrorCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCategory::Request => "request",
+            ErrorCategory::Authentication => "authentication",
+            ErrorCategory::Authorization => "authorization",
+            ErrorCategory::Infrastructure => "infrastructure",
+        }
+    }
+}
+
+/// State of a policy instance, built once when the policy is configured
+pub struct Policy {
+    pub config: Config,
+    pub rules: Rules,
+    pub instance: String,
+    pub fingerprint: String,
+    pub metrics: Metrics,
+    pub limiter: Option<OutboundLimiter>,
+    pub retry: Option<Backoff>,
+    pub breaker: Option<Rc<CircuitBreaker>>,
+    pub outbound: Outbound,
+    #[cfg(feature = "private-key-jwt")]
+    pub assertion: Option<ClientAssertion>,
+    #[cfg(feature = "cache")]
+    pub cache: Option<Rc<IntrospectionCache>>,
+    #[cfg(feature = "cache")]
+    pub machine_cache: Option<Rc<IntrospectionCache>>,
+    #[cfg(feature = "cache")]
+    pub revalidations: Revalidations,
+    #[cfg(feature = "cache")]
+    pub rejections: Option<Rc<RejectionCache>>,
+    #[cfg(feature = "cache")]
+    pub negatives: Option<Rc<NegativeCache>>,
+    #[cfg(feature = "cache")]
+    pub shared: Option<SharedCache>,
+    #[cfg(feature = "cache")]
+    pub adaptive_ttl: Option<AdaptiveTtl>,
+    #[cfg(feature = "cache")]
+    pub warm_up: Option<WarmUp>,
+    pub google: Option<GoogleServiceAccounts>,
+    pub cognito: Option<CognitoUserPool>,
+    #[cfg(feature = "jwks")]
+    pub jwks: Option<Rc<KeySet>>,
+    pub certificates: Option<ClientCertificates>,
+    pub client_limiter: Option<ClientRateLimiter>,
+    pub denylist: Option<DenyList>,
+    pub dpop: Option<ProofVerifier>,
+    pub egress: Option<EgressToken>,
+    pub exchange: Option<TokenExchange>,
+    pub gateway_keys: Vec<Value>,
+    pub introspection_keys: Vec<Value>,
+    pub profiles: Vec<Profile>,
+    pub receipt_key: Option<SigningKey>,
+    /// Receipt of the request being rejected, added to the headers of its rejection
+    pub rejection_receipt: RefCell<Option<String>>,
+    pub last_idp_contact: Cell<Option<u64>>,
+    pub idp_backoff_until: Cell<Option<u64>>,
+    pub timer: Option<Rc<Timer>>,
+}
+
+impl Policy {
+    pub fn new(config: Config, bytes: &[u8], clock: Clock) -> Result<Self> {
+        //the host ticks at a single period, so the limiter, retries and jobs share the timer
+        let polling = config.max_concurrent_calls.is_some()
+            || config.introspection_retry_attempts > 1
+            || (config.warm_up_seconds.is_some() && config.warm_up_delay_millis > 0);
+        let jobs = config.jwks_background_refresh
+            || config.stale_grace_seconds.is_some()
+            || config.egress_token_upstream.is_some()
+            || config.denylist_upstream.is_some();
+        let timer = match (polling, jobs) {
+            (true, _) => Some(Rc::new(clock.period(concurrency::POLL_INTERVAL))),
+            (false, true) => Some(Rc::new(clock.period(scheduler::JOB_INTERVAL))),
+            (false, false) => None,
+        };
+
+        //keeps the caches and the circuit state when only runtime-tunable settings were updated
+        let keys = reload::Keys::new(bytes)?;
+        let policy = Self::build(config, bytes, timer, reload::take(&keys))?;
+        reload::keep(keys, policy.runtime_state());
+
+        Ok(policy)
+    }
+
+    /// Builds the state without the outbound call limiter and the retries, which need the clock
+    /// of the host
+    #[cfg(any(test, feature = "bench", feature = "decision-export"))]
+    pub(crate) fn detached(config: Config, bytes: &[u8]) -> Result<Self> {
+        Self::build(config, bytes, None, RuntimeState::default())
+    }
+
+    fn build(
+        config: Config,
+        bytes: &[u8],
+        timer: Option<Rc<Timer>>,
+        retained: RuntimeState,
+    ) -> Result<Self> {
+        startup::validate(&config)?;
+        let rules = Rules::compile(&config)?;
+
+        let limiter = match (config.max_concurrent_calls, &timer) {
+            (Some(max), Some(timer)) => Some(OutboundLimiter::new(
+                max.max(1) as usize,
+                Duration::from_millis(config.concurrency_wait_millis.max(0) as u64),
+                timer.clone(),
+            )),
+            _ => None,
+        };
+
+        //the retries wait on the timer between the attempts, so none are made without it
+        let retry = match &timer {
+            Some(_) if config.introspection_retry_attempts > 1 => Some(Backoff::new(
+                config.introspection_retry_attempts as u32,
+                Duration::from_millis(config.introspection_retry_delay_millis.max(0) as u64),
+                config
+                    .introspection_retry_statuses
+                    .iter()
+                    .map(|status| *status as u32)
+                    .collect(),
+            )),
+            _ => None,
+        };
+
+        for source in config.token_sources.iter() {
+            let names = [&source.header, &source.query, &source.cookie];
+            if names.iter().filter(|name| name.is_some()).count() != 1 {
+                bail!("Each token source must name exactly one header, query or cookie")
+            }
+        }
+
+        for route in config.issuer_routes.iter() {
+            if route.client_id.is_some() != route.client_secret.is_some() {
+                bail!(
+                    "clientId and clientSecret of the {} route must be configured together",
+                    route.issuer
+                )
+            }
+        }
+
+        if config.forward_token == "rewriteHeader" && config.forward_token_header.is_none() {
+            bail!("forwardToken rewriteHeader requires forwardTokenHeader")
+        }
+
+        //rejects the settings of the subsystems compiled out of this build
+        let subsystems = [
+            (
+                "private-key-jwt",
+                cfg!(feature = "private-key-jwt"),
+                config.client_auth_method == "private_key_jwt"
+                    || (config.receipt_signing_key.is_some()
+                        && config.receipt_signing_algorithm == "ES256"),
+            ),
+            (
+                "cache",
+                cfg!(feature = "cache"),
+                config.cache_ttl_seconds.is_some()
+                    || config.machine_cache_ttl_seconds.is_some()
+                    || config.stale_grace_seconds.is_some()
+                    || config.malformed_token_cache_seconds.is_some()
+                    || config.negative_cache_ttl_seconds.is_some()
+                    || config.adaptive_ttl_min_seconds.is_some()
+                    || config.shared_cache
+                    || config.cache_control,
+            ),
+            (
+                "jwks",
+                cfg!(feature = "jwks"),
+                config.jwks_upstream.is_some() || config.jwks_background_refresh,
+            ),
+            (
+                "decision-export",
+                cfg!(feature = "decision-export"),
+                config.decision_export,
+            ),
+        ];
+        for (feature, enabled, configured) in subsystems.iter() {
+            if *configured && !enabled {
+                bail!("The configuration requires the {} feature", feature)
+            }
+        }
+
+        #[cfg(feature = "private-key-jwt")]
+        let assertion = ClientAssertion::from_config(&config)?;
+        if config.client_auth_method != "private_key_jwt"
+            && config.client_id.is_some() != config.client_secret.is_some()
+        {
+            bail!("clientId and clientSecret must be configured together")
+        }
+
+        //moves each retained part out on its own, as a closure would capture the whole state
+        let retained_breaker = retained.breaker;
+        let breaker = config.circuit_breaker_threshold.map(|threshold| {
+            let threshold = threshold.max(1) as u32;
+            let cool_down = config.circuit_breaker_cool_down_seconds.max(0) as u64;
+            match retained_breaker {
+                Some(breaker) => {
+                    breaker.retune(threshold, cool_down);
+                    breaker
+                }
+                None => Rc::new(CircuitBreaker::new(threshold, cool_down)),
+            }
+        });
+
+        #[cfg(feature = "cache")]
+        let grace = config.stale_grace_seconds.unwrap_or_default().max(0) as u64;
+        #[cfg(feature = "cache")]
+        let retained_cache = retained.cache;
+        //warms up only the caches starting empty, not the ones kept across an update
+        #[cfg(feature = "cache")]
+        let warm_up = match retained_cache {
+            Some(_) => None,
+            None => WarmUp::from_config(&config, timer.clone()),
+        };
+        #[cfg(feature = "cache")]
+        let cache = config.cache_ttl_seconds.map(|ttl| {
+            let ttl = ttl.max(0) as u64;
+            let max_entries = config.cache_max_entries.max(0) as usize;
+            match retained_cache {
+                Some(cache) => {
+                    cache.retune(ttl, max_entries, grace);
+                    cache
+                }
+                None => Rc::new(IntrospectionCache::new(ttl, max_entries, grace)),
+            }
+        });
+
+        if config.shared_cache && config.cache_ttl_seconds.is_none() {
+            bail!("sharedCache requires cacheTtlSeconds")
+        }
+
+        //keeps the results of the machine tokens apart, under their own limits
+        if config.machine_cache_ttl_seconds.is_some() && config.token_classification == "none" {
+            bail!("machineCacheTtlSeconds requires tokenClassification")
+        }
+        #[cfg(feature = "cache")]
+        let retained_machine_cache = retained.machine_cache;
+        #[cfg(feature = "cache")]
+        let machine_cache = config.machine_cache_ttl_seconds.map(|ttl| {
+            let ttl = ttl.max(0) as u64;
+            let max_entries = config.machine_cache_max_entries.max(0) as usize;
+            match retained_machine_cache {
+                Some(cache) => {
+                    cache.retune(ttl, max_entries, grace);
+                    cache
+                }
+                None => Rc::new(IntrospectionCache::new(ttl, max_entries, grace)),
+            }
+        });
+
+        #[cfg(feature = "cache")]
+        let retained_rejections = retained.rejections;
+        #[cfg(feature = "cache")]
+        let rejections = config.malformed_token_cache_seconds.map(|ttl| {
+            let ttl = ttl.max(0) as u64;
+            match retained_rejections {
+                Some(rejections) => {
+                    rejections.retune(ttl);
+                    rejections
+                }
+                None => Rc::new(RejectionCache::new(ttl)),
+            }
+        });
+
+        #[cfg(feature = "cache")]
+        let retained_negatives = retained.negatives;
+        #[cfg(feature = "cache")]
+        let negatives = config.negative_cache_ttl_seconds.map(|ttl| {
+            let ttl = ttl.max(0) as u64;
+            match retained_negatives {
+                Some(negatives) => {
+                    negatives.retune(ttl);
+                    negatives
+                }
+                None => Rc::new(NegativeCache::new(ttl)),
+            }
+        });
+
+        //drops the results kept from the endpoints the update changed, keeping the others
+        #[cfg(feature = "cache")]
+        {
+            let routes = Endpoint::fingerprints(&config);
+            let dropped: usize = cache
+                .iter()
+                .chain(machine_cache.iter())
+                .map(|cache| cache.retain_routes(&routes))
+                .sum();
+            if dropped > 0 {
+                logger::info!(
+                    "Dropped {} cached validation results of the updated endpoints.",
+                    dropped
+                );
+            }
+            if let Some(negatives) = &negatives {
+                negatives.retain_routes(&routes);
+            }
+        }
+
+        //adapts the TTL of the introspection cache to the rejections of the upstream services
+        #[cfg(feature = "cache")]
+        let adaptive_ttl = match (config.adaptive_ttl_min_seconds, config.cache_ttl_seconds) {
+            (Some(min), Some(max)) => Some(AdaptiveTtl::new(
+                min.max(0) as u64,
+                max.max(0) as u64,
+                config.adaptive_ttl_divergence_percent.max(0) as u64,
+                config.adaptive_ttl_window.max(1) as u64,
+            )),
+            (Some(_), None) => bail!("adaptiveTtlMinSeconds requires cacheTtlSeconds"),
+            (None, _) => None,
+        };
+
+        for dimension in config.analytics_dimensions.iter() {
+            if dimension.claim.is_some() == dimension.decision_field.is_some() {
+                bail!(
+                    "Analytics dimension {} requires either a claim or a decisionField",
+                    dimension.name
+                )
+            }
+        }
+
+        let google = match config.google_issuer_pattern.as_deref() {
+            Some(_)
+                if config.google_audience.is_none() || config.google_certs_upstream.is_none() =>
+            {
+                bail!("googleIssuerPattern requires googleAudience and googleCertsUpstream")
+            }
+            Some(pattern) => Some(GoogleServiceAccounts::new(pattern)?),
+            None => None,
+        };
+
+        let receipt_key = match config.receipt_signing_key.as_deref() {
+            Some(pem) => Some(SigningKey::from_pem(
+                &config.receipt_signing_algorithm,
+                pem,
+                "receiptSigningKey",
+            )?),
+            None => None,
+        };
+
+        let cognito = match config.cognito_user_pool_id.as_deref() {
+            Some(_) if config.cognito_jwks_upstream.is_none() => {
+                bail!("cognitoUserPoolId requires cognitoJwksUpstream")
+            }
+            Some(user_pool_id) => Some(CognitoUserPool::new(user_pool_id)),
+            None => None,
+        };
+
+        #[cfg(feature = "jwks")]
+        let jwks = match (&config.jwks_upstream, &config.jwks_host) {
+            (Some(_), Some(_)) => Some(retained.jwks.unwrap_or_default()),
+            (Some(_), None) => bail!("jwksUpstream requires jwksHost"),
+            (None, _) if config.jwks_background_refresh => {
+                bail!("jwksBackgroundRefresh requires jwksUpstream")
+            }
+            (None, _) => None,
+        };
+
+        let certificates = match config.client_certificate_principals.as_slice() {
+            [] => None,
+            allowed => Some(ClientCertificates::new(allowed)),
+        };
+
+        let gateway_keys =
+            inline_keys(config.trusted_gateway_jwks.as_deref(), "trustedGatewayJwks")?;
+        let introspection_keys = inline_keys(
+            config.introspection_response_jwks.as_deref(),
+            "introspectionResponseJwks",
+        )?;
+
+        //derives a stable name from the configuration when none is set
+        let instance = config
+            .policy_instance_name
+            .clone()
+            .unwrap_or_else(|| format!("policy-{}", &crypto::sha256_hex(bytes)[..8]));
+        let fingerprint = fingerprint(bytes)?;
+
+        Ok(Self {
+            metrics: Metrics::new(&instance, &fingerprint),
+            limiter,
+            retry,
+            breaker,
+            outbound: Outbound::from_config(&config),
+            #[cfg(feature = "private-key-jwt")]
+            assertion,
+            #[cfg(feature = "cache")]
+            cache,
+            #[cfg(feature = "cache")]
+            machine_cache,
+            #[cfg(feature = "cache")]
+            revalidations: Revalidations::default(),
+            #[cfg(feature = "cache")]
+            rejections,
+            #[cfg(feature = "cache")]
+            negatives,
+            #[cfg(feature = "cache")]
+            shared: SharedCache::from_config(&config, &fingerprint),
+            #[cfg(feature = "cache")]
+            adaptive_ttl,
+            #[cfg(feature = "cache")]
+            warm_up,
+            google,
+            cognito,
+            #[cfg(feature = "jwks")]
+            jwks,
+            certificates,
+            client_limiter: ClientRateLimiter::from_config(&config),
+            denylist: DenyList::from_config(&config)?,
+            dpop: ProofVerifier::from_config(&config),
+            egress: EgressToken::from_config(&config)?,
+            exchange: TokenExchange::from_config(&config)?,
+            gateway_keys,
+            introspection_keys,
+            profiles: propagation::profiles(&config, bytes)?,
+            receipt_key,
+            rejection_receipt: RefCell::new(None),
+            last_idp_contact: Cell::new(None),
+            idp_backoff_until: Cell::new(None),
+            timer,
+            config,
+            rules,
+            instance,
+            fingerprint,
+        })
+    }
+
+    /// Returns the caches the validation results are looked up in, the machine tokens one first
+    #[cfg(feature = "cache")]
+    pub(crate) fn caches(&self) -> impl Iterator<Item = &Rc<IntrospectionCache>> {
+        self.machine_cache.iter().chain(self.cache.iter())
+    }
+
+    /// Returns the cache the validation result is kept in, according to the class of the token
+    #[cfg(feature = "cache")]
+    fn cache_for(&self, response: &IntrospectionResponse) -> Option<&Rc<IntrospectionCache>> {
+        match (
+            &self.machine_cache,
+            claims::classify(&response.claims, &self.config),
+        ) {
+            (Some(cache), Some("machine")) => Some(cache),
+            _ => self.cache.as_ref(),
+        }
+    }
+
+    /// Returns whether a cached validation result of the token is about to be served, which is only
+    /// tracked when the cache TTL adapts to the rejections of the upstream services
+    #[cfg_attr(not(feature = "cache"), allow(unused_variables))]
+    fn serves_cached(&self, token: &str, now: u64) -> bool {
+        #[cfg(feature = "cache")]
+        if self.adaptive_ttl.is_some() {
+            return self.caches().any(|cache| cache.holds(token, now));
+        }
+        false
+    }
+
+    /// Returns the Authorization header carrying the token of the policy for an outbound call
+    pub(crate) async fn egress_authorization(
+        &self,
+        call: &str,
+        client: &HttpClient,
+        now: u64,
+    ) -> Option<String> {
+        match &self.egress {
+            Some(egress) => egress.authorization(call, self, client, now).await,
+            None => None,
+        }
+    }
+
+    /// Returns the state to retain across the configuration updates
+    fn runtime_state(&self) -> RuntimeState {
+        RuntimeState {
+            #[cfg(feature = "cache")]
+            cache: self.cache.clone(),
+            #[cfg(feature = "cache")]
+            machine_cache: self.machine_cache.clone(),
+            #[cfg(feature = "cache")]
+            rejections: self.rejections.clone(),
+            #[cfg(feature = "cache")]
+            negatives: self.negatives.clone(),
+            breaker: self.breaker.clone(),
+            #[cfg(feature = "jwks")]
+            jwks: self.jwks.clone(),
+        }
+    }
+}
+
+/// Media type of the signed introspection responses defined by RFC 9701
+const SIGNED_INTROSPECTION_TYPE: &str = "application/token-introspection+jwt";
+
+/// Parses the keys of a JWKS inlined in the configuration
+fn inline_keys(jwks: Option<&str>, property: &str) -> Result<Vec<Value>> {
+    match jwks {
+        Some(jwks) => match serde_json::from_str::<Value>(jwks)?.get("keys") {
+            Some(Value::Array(keys)) => Ok(keys.clone()),
+            _ => bail!("{} must be a JWKS with a keys array", property),
+        },
+        None => Ok(vec![]),
+    }
 }
 
-#[derive(Deserialize)]
+/// Data captured while filtering the request that is applied to the response
+#[derive(Default)]
+pub struct ResponseContext {
+    pub headers: Vec<(String, String)>,
+    pub subject: Option<String>,
+    pub client_id: Option<String>,
+    /// Key bound to the token that the request body must be signed with
+    pub body_key: Option<Value>,
+    /// Trailers appended to the response after its body, when the protocol carries trailers
+    pub trailers: Vec<(String, String)>,
+    /// Whether the validation result of the token was served from the cache
+    pub cached: bool,
+    /// Whether the inbound credential was already forwarded, before the exchanged token took its
+    /// place
+    pub forwarded: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct IntrospectionResponse {
     pub active: bool,
+    /// Seconds the result may be cached for according to the Cache-Control of the response
+    #[serde(skip)]
+    pub max_age: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub exp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub nbf: Option<u64>,
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+/// Members of the introspection response defined by RFC 7662, besides `active`, `exp` and `nbf`
+const STANDARD_MEMBERS: [&str; 9] = [
+    "scope",
+    "client_id",
+    "username",
+    "token_type",
+    "iat",
+    "sub",
+    "aud",
+    "iss",
+    "jti",
+];
+
+/// Standard members of an introspection response, as defined by RFC 7662
+#[derive(Debug, Default, Serialize)]
+pub struct StandardClaims {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aud: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+impl IntrospectionResponse {
+    /// Returns the standard members of the response, which are kept along with the rest of the
+    /// claims so the claim rules apply to them too
+    pub fn standard(&self) -> StandardClaims {
+        let text = |name: &str| claims::claim_as_string(&self.claims, name);
+
+        StandardClaims {
+            scope: Some(claims::scopes(&self.claims).join(" ")).filter(|scope| !scope.is_empty()),
+            client_id: text("client_id"),
+            username: text("username"),
+            token_type: text("token_type"),
+            iat: self.claims.get("iat").and_then(Value::as_u64),
+            sub: text("sub"),
+            aud: claims::audiences(&self.claims)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            iss: text("iss"),
+            jti: text("jti"),
+        }
+    }
+
+    /// Returns the claims that are not members defined by RFC 7662
+    pub fn custom_claims(&self) -> Map<String, Value> {
+        self.claims
+            .iter()
+            .filter(|(name, _)| !STANDARD_MEMBERS.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Serializes the response as the authentication context published to the other policies
+    /// and the upstream service
+    pub fn context(&self) -> Value {
+        json!({
+            "active": self.active,
+            "exp": self.exp,
+            "nbf": self.nbf,
+            "standard": self.standard(),
+            "claims": self.custom_claims(),
+        })
+    }
+}
+
+/// Introspection endpoint a token is validated against
+pub struct Endpoint<'a> {
+    pub upstream: &'a str,
+    pub host: &'a str,
+    pub path: &'a str,
+    pub authorization: &'a str,
+    pub client_id: Option<&'a str>,
+    pub client_secret: Option<&'a str>,
+    /// Whether the configured client assertion authenticates the calls to the endpoint
+    pub assertion: bool,
+}
+
+impl<'a> Endpoint<'a> {
+    /// Returns the endpoint for the token, routing it by its issuer when issuer routes are
+    /// configured, then by its prefix when prefix routes are configured. The issuer is the one
+    /// named by the request, if any, or the unverified `iss` claim of the JWTs.
+    pub fn select(
+        token: &str,
+        issuer: Option<&str>,
+        config: &'a Config,
+    ) -> Result<Self, FilterError> {
+        let issuer = match (config.issuer_routes.is_empty(), issuer) {
+            (true, _) => None,
+            (false, Some(issuer)) => Some(issuer.to_string()),
+            (false, None) => token::unverified_claims(token)
+                .and_then(|claims| claims::claim_as_string(&claims, "iss")),
+        };
+        let route = issuer.and_then(|issuer| {
+            config
+                .issuer_routes
+                .iter()
+                .find(|route| route.issuer == issuer)
+        });
+
+        if let Some(route) = route {
+            return Ok(Self::issuer_route(route));
+        }
+
+        let route = config
+            .prefix_routes
+            .iter()
+            .find(|route| token.starts_with(route.prefix.as_str()));
+
+        match route {
+            Some(route) => Ok(Self::prefix_route(route, config)),
+            None if config.reject_unknown_prefixes && !config.prefix_routes.is_empty() => {
+                Err(FilterError::UnknownTokenPrefix)
+            }
+            None => Ok(Self::default_route(config)),
+        }
+    }
+
+    /// Returns the fingerprints of every endpoint of the configuration
+    #[cfg(feature = "cache")]
+    pub fn fingerprints(config: &'a Config) -> HashSet<String> {
+        let issuers = config.issuer_routes.iter().map(Self::issuer_route);
+        let prefixes = config
+            .prefix_routes
+            .iter()
+            .map(|route| Self::prefix_route(route, config));
+
+        issuers
+            .chain(prefixes)
+            .chain(std::iter::once(Self::default_route(config)))
+            .map(|endpoint| endpoint.fingerprint())
+            .collect()
+    }
+
+    /// Identifies the endpoint a validation result was obtained from, so the cached results are
+    /// only served for the tokens still validated against the same one
+    #[cfg(feature = "cache")]
+    pub fn fingerprint(&self) -> String {
+        crypto::sha256_hex(format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            self.upstream,
+            self.host,
+            self.path,
+            self.authorization,
+            self.client_id.unwrap_or_default(),
+            self.client_secret.unwrap_or_default()
+        ))
+    }
+
+    fn issuer_route(route: &'a IssuerRoutesItem) -> Self {
+        Self {
+            upstream: &route.upstream,
+            host: &route.host,
+            path: &route.path,
+            authorization: &route.authorization,
+            client_id: route.client_id.as_deref(),
+            client_secret: route.client_secret.as_deref(),
+            assertion: false,
+        }
+    }
+
+    fn prefix_route(route: &'a PrefixRoutesItem, config: &'a Config) -> Self {
+        Self {
+            upstream: &route.upstream,
+            host: &route.host,
+            path: &route.path,
+            authorization: &route.authorization,
+            client_id: config.client_id.as_deref(),
+            client_secret: config.client_secret.as_deref(),
+            assertion: true,
+        }
+    }
+
+    fn default_route(config: &'a Config) -> Self {
+        Self {
+            upstream: &config.upstream,
+            host: &config.host,
+            path: &config.path,
+            authorization: &config.authorization,
+            client_id: config.client_id.as_deref(),
+            client_secret: config.client_secret.as_deref(),
+            assertion: true,
+        }
+    }
+}
+
+/// Forwards the inbound credential as set by forwardToken, then hands the exchanged token to the
+/// upstream service in its place, so stripping or moving the credential leaves the exchanged one
+fn forward_exchanged(request: &impl HeadersHandler, config: &Config, exchanged: &str) {
+    extraction::forward_token(request, config);
+    request.set_header("Authorization", &format!("Bearer {}", exchanged));
 }
 
-async fn introspect_token(
+pub(crate) async fn introspect_token(
     token: &str,
-    config: &Config,
-    client: HttpClient,
+    endpoint: &Endpoint<'_>,
+    policy: &Policy,
+    client: &HttpClient,
+) -> Result<IntrospectionResponse, FilterError> {
+    let config = &policy.config;
+
+    #[cfg(feature = "private-key-jwt")]
+    let signed_assertion = match (endpoint.authorization, &policy.assertion) {
+        ("", Some(assertion)) if endpoint.assertion => Some(assertion.sign()?),
+        _ => None,
+    };
+
+    let mut parameters = vec![("token", token)];
+    if let Some(hint) = config.token_type_hint.as_deref() {
+        parameters.push(("token_type_hint", hint));
+    }
+    parameters.extend(
+        config
+            .extra_parameters
+            .iter()
+            .map(|parameter| (parameter.name.as_str(), parameter.value.as_str())),
+    );
+
+    #[cfg(feature = "private-key-jwt")]
+    if let Some(signed_assertion) = signed_assertion.as_deref() {
+        parameters.push(("client_assertion_type", assertion::ASSERTION_TYPE));
+        parameters.push(("client_assertion", signed_assertion));
+    }
+
+    //authenticates with the client credentials when no raw Authorization header is configured
+    let authorization = match (
+        endpoint.authorization,
+        endpoint.client_id,
+        endpoint.client_secret,
+    ) {
+        ("", Some(client_id), Some(client_secret))
+            if config.client_auth_method == "client_secret_post" =>
+        {
+            parameters.push(("client_id", client_id));
+            parameters.push(("client_secret", client_secret));
+            None
+        }
+        ("", Some(client_id), Some(client_secret))
+            if config.client_auth_method == "client_secret_basic" =>
+        {
+            Some(crypto::client_secret_basic(client_id, client_secret))
+        }
+        ("", ..) => None,
+        (authorization, ..) => Some(authorization.to_string()),
+    };
+    let body = serde_urlencoded::to_string(parameters).map_err(|_| FilterError::Unexpected)?;
+    let timeout = config
+        .introspection_timeout_millis
+        .map(|millis| Duration::from_millis(millis.max(1) as u64));
+
+    let mut headers = vec![("content-type", "application/x-www-form-urlencoded")];
+    if let Some(authorization) = authorization.as_deref() {
+        headers.push(("Authorization", authorization));
+    }
+    //asks for the signed responses once there are keys to verify them with
+    if !policy.introspection_keys.is_empty() {
+        headers.push(("accept", SIGNED_INTROSPECTION_TYPE));
+    }
+
+    //retries the transient failures of the introspection endpoint before failing the request
+    let response = Call::new(
+        "introspection",
+        endpoint.upstream,
+        endpoint.host,
+        endpoint.path,
+    )
+    .headers(headers)
+    .body(body.as_bytes())
+    .timeout(timeout)
+    .retried()
+    .send(policy, client)
+    .await?;
+
+    let signed = response.headers().iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("content-type") && value.starts_with(SIGNED_INTROSPECTION_TYPE)
+    });
+
+    let result = match response.status_code() {
+        200 if signed => signed_introspection(bounded(&response, config)?, policy),
+        //a plain response would bypass the verification the keys were configured for
+        200 if !policy.introspection_keys.is_empty() => {
+            Err(FilterError::UnsignedIntrospectionResponse)
+        }
+        200 => serde_json::from_slice(bounded(&response, config)?)
+            .map_err(FilterError::NonParsableIntrospectionBody),
+        429 => Err(FilterError::RateLimited(retry_after(&response.headers()))),
+        status => Err(FilterError::IdpStatus(status)),
+    };
+
+    //lets the authorization server decide for how long each result is cached
+    result.map(|mut introspected: IntrospectionResponse| {
+        if config.cache_control {
+            introspected.max_age = max_age(&response.headers())
+                .map(|max_age| max_age.min(config.cache_control_max_seconds.max(0) as u64));
+        }
+        introspected
+    })
+}
+
+/// Extracts the introspection result from the token_introspection claim of a signed response, as
+/// defined by RFC 9701, once the signature is verified against the configured keys
+fn signed_introspection(
+    body: &[u8],
+    policy: &Policy,
 ) -> Result<IntrospectionResponse, FilterError> {
     let body =
-        serde_urlencoded::to_string([("token", token)]).map_err(|_| FilterError::Unexpected)?;
+        std::str::from_utf8(body).map_err(|_| FilterError::UnverifiedIntrospectionResponse)?;
+    let jwt = Jwt::decode(body.trim()).ok_or(FilterError::UnverifiedIntrospectionResponse)?;
+
+    if policy.introspection_keys.is_empty() || !jwt.verify(&policy.introspection_keys) {
+        return Err(FilterError::UnverifiedIntrospectionResponse);
+    }
+
+    let result = jwt
+        .claims
+        .get("token_introspection")
+        .cloned()
+        .ok_or(FilterError::UnverifiedIntrospectionResponse)?;
+    serde_json::from_value(result).map_err(FilterError::NonParsableIntrospectionBody)
+}
+
+/// Returns the body of a response from an outbound call, rejecting the ones exceeding the
+/// configured size so a misbehaving service cannot exhaust the memory of the workers
+pub(crate) fn bounded<'a>(
+    response: &'a HttpClientResponse,
+    config: &Config,
+) -> Result<&'a [u8], FilterError> {
+    let max = config.max_response_bytes.max(0) as usize;
+
+    //rejects the announced oversized bodies before reading them
+    let declared = response
+        .headers()
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok());
+    if let Some(length) = declared.filter(|length| *length > max) {
+        return Err(FilterError::ResponseTooLarge(length));
+    }
+
+    //the length may be missing or wrong, so the body read is checked too
+    let body = response.body();
+    if body.len() > max {
+        return Err(FilterError::ResponseTooLarge(body.len()));
+    }
+
+    Ok(body)
+}
+
+/// Returns the seconds to wait according to the Retry-After header of a rate limited response,
+/// defaulting to one second when it is missing or expressed as a date
+fn retry_after(headers: &[(String, String)]) -> u64 {
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// Returns the seconds a response may be cached for according to its Cache-Control header, the
+/// no-store and no-cache directives forbidding the caching and s-maxage taking precedence
+fn max_age(headers: &[(String, String)]) -> Option<u64> {
+    let directives = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cache-control"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let seconds = |name: &str| {
+        directives.iter().find_map(|directive| {
+            directive
+                .strip_prefix(name)
+                .and_then(|value| value.strip_prefix('='))
+                .and_then(|value| value.trim_matches('"').parse().ok())
+        })
+    };
 
+    if directives
+        .iter()
+        .any(|directive| directive == "no-store" || directive == "no-cache")
+    {
+        return Some(0);
+    }
+    seconds("s-maxage").or_else(|| seconds("max-age"))
+}
+
+/// Validates the token against the userinfo endpoint, treating a successful response carrying a
+/// subject as an active token whose claims are the returned user attributes
+async fn userinfo_token(
+    token: &str,
+    endpoint: &Endpoint<'_>,
+    path: &str,
+    policy: &Policy,
+    client: &HttpClient,
+) -> Result<IntrospectionResponse, FilterError> {
+    let config = &policy.config;
+    let authorization = format!("Bearer {}", token);
     let headers = vec![
-        ("content-type", "application/x-www-form-urlencoded"),
-        ("Authorization", config.authorization.as_str()),
+        ("accept", "application/json"),
+        ("Authorization", authorization.as_str()),
     ];
 
-    let response = client
-        .request(config.upstream.as_str(), config.host.as_str())
-        .path(config.path.as_str())
+    let response = Call::new("userinfo", endpoint.upstream, endpoint.host, path)
         .headers(headers)
-        .body(body.as_bytes())
-        .post()
-        .await
-        .map_err(FilterError::ClientError)?;
+        .retried()
+        .send(policy, client)
+        .await?;
+
+    if response.status_code() != 200 {
+        return Err(FilterError::IdpStatus(response.status_code()));
+    }
 
-    if response.status_code() == 200 {
-        serde_json::from_slice(response.body()).map_err(FilterError::NonParsableIntrospectionBody)
-    } else {
-        Err(FilterError::InactiveToken)
+    let claims: Map<String, Value> = serde_json::from_slice(bounded(&response, config)?)
+        .map_err(FilterError::NonParsableIntrospectionBody)?;
+
+    if !claims.contains_key("sub") {
+        return Err(FilterError::InactiveToken);
     }
+
+    Ok(IntrospectionResponse {
+        active: true,
+        max_age: None,
+        exp: None,
+        nbf: None,
+        claims,
+    })
 }
 
-async fn do_filter(
-    request: impl HeadersHandler,
-    config: &Config,
-    client: HttpClient,
-) -> Result<(), FilterError> {
-    //Extract the token from the request
+/// Validates the token against a custom validation service, building the request from the
+/// configured templates and mapping the response fields into the standard validation result
+async fn remote_token(
+    token: &str,
+    endpoint: &Endpoint<'_>,
+    policy: &Policy,
+    client: &HttpClient,
+    authorization: Option<String>,
+) -> Result<IntrospectionResponse, FilterError> {
+    let config = &policy.config;
+    let render = |template: &str| template.replace("{{token}}", token);
+
+    let mut headers: Vec<(String, String)> = config
+        .remote_headers
+        .iter()
+        .map(|header| (header.name.clone(), render(&header.value)))
+        .collect();
+    //the configured headers take precedence over the token of the policy
+    if let Some(authorization) = authorization {
+        if !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+        {
+            headers.push(("Authorization".to_string(), authorization));
+        }
+    }
+    let headers = headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let body = config
+        .remote_body
+        .as_deref()
+        .map(render)
+        .unwrap_or_default();
+
+    let call = Call::new("remote", endpoint.upstream, endpoint.host, endpoint.path)
+        .headers(headers)
+        .retried();
+
+    let response = match config.remote_method.as_str() {
+        "GET" => call.send(policy, client).await,
+        _ => call.body(body.as_bytes()).send(policy, client).await,
+    }?;
+
+    if response.status_code() != 200 {
+        return Err(FilterError::IdpStatus(response.status_code()));
+    }
+
+    let body: Value = serde_json::from_slice(bounded(&response, config)?)
+        .map_err(FilterError::NonParsableIntrospectionBody)?;
+
+    let claims = match config.remote_claims_pointer.as_deref() {
+        Some(pointer) => body.pointer(pointer),
+        None => Some(&body),
+    };
+
+    Ok(IntrospectionResponse {
+        active: body.pointer(&config.remote_active_pointer) == Some(&Value::Bool(true)),
+        max_age: None,
+        exp: config
+            .remote_exp_pointer
+            .as_deref()
+            .and_then(|pointer| body.pointer(pointer))
+            .and_then(Value::as_u64),
+        nbf: None,
+        claims: claims
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default(),
+    })
+}
+
+/// Validates the token against the authorization server, or locally for the token types that
+/// support it, returning the claims of the token
+pub(crate) async fn validate_token(
+    token: &str,
+    issuer: Option<&str>,
+    policy: &Policy,
+    client: &HttpClient,
+    introspector: &impl Introspector,
+    now: u64,
+) -> Result<IntrospectionResponse, FilterError> {
+    let config = &policy.config;
+
+    //drops the structurally invalid tokens, remembering them so repeated garbage costs a lookup
+    #[cfg(feature = "cache")]
+    if let Some(rejections) = &policy.rejections {
+        if token.len() > config.max_token_length.max(0) as usize {
+            policy.metrics.increment("rejection.oversized");
+            return Err(FilterError::MalformedToken);
+        }
+
+        if rejections.contains(token, now) {
+            policy.metrics.increment("rejection.malformed.repeated");
+            return Err(FilterError::MalformedToken);
+        }
+
+        if TokenFormat::detect(token) == TokenFormat::Malformed {
+            policy.metrics.increment("rejection.malformed");
+            rejections.insert(token, now);
+            return Err(FilterError::MalformedToken);
+        }
+    }
+
+    if let Some(google) = &policy.google {
+        if let Some(jwt) = google.select(token) {
+            return google.validate(jwt, policy, client, now).await;
+        }
+    }
+
+    //applies the handling configured for the detected format of the token
+    if config.token_format_detection {
+        let handling = match TokenFormat::detect(token) {
+            TokenFormat::Jwt => config.jwt_handling.as_str(),
+            TokenFormat::Opaque => config.opaque_handling.as_str(),
+            TokenFormat::Malformed => return Err(FilterError::MalformedToken),
+        };
+
+        if handling == "reject" {
+            return Err(FilterError::UnsupportedTokenFormat);
+        }
+    }
+
+    //discards the JWTs of unknown issuers before spending an introspection call on them
+    if !config.allowed_issuers.is_empty() && TokenFormat::detect(token) == TokenFormat::Jwt {
+        let issuer = token::unverified_claims(token)
+            .and_then(|claims| claims::claim_as_string(&claims, "iss"))
+            .ok_or(FilterError::UntrustedIssuer)?;
+
+        if !config.allowed_issuers.contains(&issuer) {
+            return Err(FilterError::UntrustedIssuer);
+        }
+    }
+
+    //validates the signed JWTs locally, leaving the opaque tokens to the authorization server
+    #[cfg(feature = "jwks")]
+    if let Some(jwks) = &policy.jwks {
+        if TokenFormat::detect(token) == TokenFormat::Jwt {
+            let authorization = policy.egress_authorization("jwks", client, now).await;
+            return jwks
+                .validate(token, policy, client, authorization.as_deref(), now)
+                .await;
+        }
+    }
+
+    let endpoint = Endpoint::select(token, issuer, config)?;
+    #[cfg(feature = "cache")]
+    let route = endpoint.fingerprint();
+
+    //rejects the tokens reported as inactive or expired moments ago without introspecting them
+    #[cfg(feature = "cache")]
+    if let Some(expired) = policy
+        .negatives
+        .as_ref()
+        .and_then(|negatives| negatives.get(token, &route, now))
+    {
+        policy.metrics.increment("negative_cache.hit");
+        return Err(if expired {
+            FilterError::ExpiredToken
+        } else {
+            FilterError::InactiveToken
+        });
+    }
 
-    let result = config
-        .token_extractor
-        .resolve_on_headers(&request)
-        .map_err(|_| FilterError::NoToken)?;
+    //the results and revocations shared by the other workers take precedence over the local ones
+    #[cfg(feature = "cache")]
+    if let Some(result) = policy
+        .shared
+        .as_ref()
+        .and_then(|shared| shared.get(token, now))
+    {
+        policy.metrics.increment("shared_cache.hit");
+        return result;
+    }
+
+    #[cfg(feature = "cache")]
+    if policy.caches().next().is_some() {
+        if let Some(response) = policy
+            .caches()
+            .find_map(|cache| cache.get(token, &route, now))
+        {
+            policy.metrics.increment("cache.hit");
+            return Ok(response);
+        }
+
+        //serves the result expired moments ago while the scheduler validates the token again
+        if let Some(response) = policy
+            .caches()
+            .find_map(|cache| cache.get_stale(token, &route, now))
+        {
+            policy.metrics.increment("cache.stale");
+            policy.revalidations.enqueue(token, issuer);
+            return Ok(response);
+        }
+        policy.metrics.increment("cache.miss");
+
+        //paces the tokens not seen yet while the cache fills up after a deploy
+        if let Some(warm_up) = &policy.warm_up {
+            if !warm_up.admit(now).await {
+                policy.metrics.increment("warm_up.throttled");
+                return Err(FilterError::WarmingUp);
+            }
+        }
+    }
+
+    //honors the Retry-After of the authorization server instead of calling it while rate limited
+    if let Some(until) = policy.idp_backoff_until.get().filter(|until| now < *until) {
+        return Err(FilterError::RateLimited(until - now));
+    }
+
+    //fails fast while the authorization server is unreachable instead of waiting for the timeout
+    if let Some(breaker) = &policy.breaker {
+        if !breaker.allows(now) {
+            policy.metrics.increment("breaker.short_circuited");
+            return Err(FilterError::CircuitOpen);
+        }
+    }
+
+    let stage = policy.metrics.stage("introspection");
+    let start = SystemTime::now();
+    let result = match (&policy.cognito, config.validation_strategy.as_str()) {
+        //the Cognito access tokens are only accepted once verified against the keys of the user pool
+        (Some(cognito), _) => cognito.validate(token, policy, client, now).await,
+        (None, "userinfo") => {
+            userinfo_token(token, &endpoint, &config.userinfo_path, policy, client).await
+        }
+        (None, "remote") => {
+            let authorization = policy.egress_authorization("remote", client, now).await;
+            remote_token(token, &endpoint, policy, client, authorization).await
+        }
+        (None, _) => introspector.introspect(token, &endpoint).await,
+    };
+
+    let reached = !matches!(
+        result,
+        Err(FilterError::ClientError(_)) | Err(FilterError::Timeout)
+    );
+    if reached {
+        policy.last_idp_contact.set(Some(now));
+    }
+    let outcome = match &result {
+        Ok(response) if response.active => "active",
+        Ok(_) | Err(FilterError::InactiveToken) => "inactive",
+        Err(_) if reached => "failed",
+        Err(_) => "unreached",
+    };
+    policy.metrics.introspection(outcome, start);
+
+    if let Some(breaker) = &policy.breaker {
+        let was_open = breaker.is_open();
+        breaker.record(!breaker_failure(&result, config), now);
+        if breaker.is_open() != was_open {
+            logger::warn!(
+                "Circuit to the authorization server {}.",
+                if was_open { "closed" } else { "opened" }
+            );
+        }
+    }
+
+    if let Err(FilterError::RateLimited(seconds)) = &result {
+        policy.metrics.increment("idp.rate_limited");
+        policy.idp_backoff_until.set(Some(now + seconds));
+    }
+    let result = result.map_err(rejected_token);
+
+    drop(stage);
+
+    //compares the result with the one it refreshes, dropping the results of the revoked tokens
+    #[cfg(feature = "cache")]
+    if let Ok(response) = &result {
+        let previous = match policy.cache_for(response) {
+            Some(cache) if response.active => cache.insert(token, &route, response, now),
+            _ => policy
+                .caches()
+                .fold(None, |previous, cache| cache.remove(token).or(previous)),
+        };
+        if let Some(previous) = previous {
+            drift::report(token, &previous, response, policy);
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    if let (Some(negatives), Ok(response)) = (&policy.negatives, &result) {
+        match check_validity(response, policy, now) {
+            Err(FilterError::InactiveToken) => negatives.insert(token, &route, false, now),
+            Err(FilterError::ExpiredToken) => negatives.insert(token, &route, true, now),
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    if let (Some(shared), Ok(response)) = (&policy.shared, &result) {
+        match check_validity(response, policy, now) {
+            Err(FilterError::InactiveToken) => shared.revoke(token, &policy.metrics, now),
+            _ => shared.insert(token, response, &policy.metrics, now),
+        }
+    }
 
-    let token = result.as_str().ok_or(FilterError::NoToken)?;
+    result
+}
+
+async fn do_filter(
+    request: &impl HeadersHandler,
+    policy: &Policy,
+    client: &HttpClient,
+    introspector: &impl Introspector,
+) -> Result<ResponseContext, FilterError> {
+    let config = &policy.config;
+    let rules = &policy.rules;
+
+    //rejects the paths crafted to be matched differently by the policy and the upstream service
+    if config.strict_path_normalization {
+        let raw = raw_request_path(request);
+        if path::normalize(&raw) != raw {
+            return Err(FilterError::NonCanonicalPath);
+        }
+    }
 
-    let response = introspect_token(token, config, client).await?;
+    //denies the routes not covered by any rule, so new endpoints are not exposed by accident
+    if config.deny_unmatched_routes
+        && !rules.covers(&request_method(request, config), &request_path(request))
+    {
+        policy.metrics.increment("route.unmatched");
+        return Err(FilterError::UnmatchedRoute);
+    }
 
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map_err(|_| FilterError::Unexpected)?
         .as_secs();
 
-    if !response.active {
-        return Err(FilterError::InactiveToken);
+    let (mechanism, mut response, cached, token) =
+        authenticate(request, policy, client, introspector, now).await?;
+
+    let stage = policy.metrics.stage("rules");
+
+    normalize_claims(&mut response, config)?;
+
+    //limits the requests of each client once the token established its identity
+    if let (Some(limiter), Some(client_id)) = (
+        &policy.client_limiter,
+        claims::claim_as_string(&response.claims, "client_id"),
+    ) {
+        if let Err(retry_after) = limiter.acquire(&client_id, now) {
+            policy.metrics.increment("client_rate_limit.exceeded");
+            return Err(FilterError::ClientRateLimited(retry_after));
+        }
     }
 
-    //validates if token has expired
-    if response.exp.map(|exp| now > exp).unwrap_or_default() {
-        return Err(FilterError::ExpiredToken);
+    if let Some(class) = claims::claim_as_string(&response.claims, claims::TOKEN_CLASS_CLAIM) {
+        policy.metrics.increment(&format!("tokens.{}", class));
     }
 
-    //validates if token has started its validity period
-    if response.nbf.map(|nbf| now < nbf).unwrap_or_default() {
-        return Err(FilterError::NotYetActive);
+    let target = Target::of(request, config);
+    let mut failures = vec![];
+    for check in rules.checks.iter() {
+        let result = evaluation::check(check, &response, policy, &target, now);
+        if let Err(err) = evaluation::enforce(check, result, &response, policy) {
+            if config.check_evaluation != "all" {
+                #[cfg(feature = "decision-export")]
+                if config.decision_export {
+                    simulation::export(policy, &response, &target, now, Some(&err));
+                }
+                return Err(err);
+            }
+            failures.push((*check, err));
+        }
     }
 
-    Ok(())
-}
+    #[cfg(feature = "decision-export")]
+    if config.decision_export {
+        let failure = failures.first().map(|(_, err)| err);
+        simulation::export(policy, &response, &target, now, failure);
+    }
 
-/// Generates a standard early response that indicates the token validation failed
-fn unauthorized_response() -> Flow<()> {
-    Flow::Break(Response::new(401).with_headers(vec![(
-        "WWW-Authenticate".to_string(),
-        "Bearer realm=\"oauth2\"".to_string(),
-    )]))
-}
+    //reports every failed check, while the client sees the failure of the highest priority one
+    if !failures.is_empty() {
+        let failed: Vec<Value> = failures
+            .iter()
+            .map(|(check, err)| json!({ "check": check, "error": format!("{:?}", err) }))
+            .collect();
+        audit::emit(
+            &policy.instance,
+            Severity::Info,
+            "checks_failed",
+            json!({
+                "method": target.method,
+                "path": target.path,
+                "client_id": claims::claim_as_string(&response.claims, "client_id"),
+                "count": failed.len(),
+                "failures": failed,
+            }),
+        );
+        return Err(failures.remove(0).1);
+    }
 
-/// Generates a standard early response that indicates that there was an unexpected error
-fn server_error_response() -> Flow<()> {
-    Flow::Break(Response::new(500))
-}
+    validate_token_slots(request, policy, introspector, now).await?;
 
-/// Defines a filter function that works as a wrapper for the real filter function that enables simplified error handling
-async fn request_filter(state: RequestState, client: HttpClient, config: &Config) -> Flow<()> {
-    let state = state.into_headers_state().await;
+    //replaces the edge token with the one the upstream service requires, narrowed to the audience
+    //and resource of the route when it sets them
+    let mut forwarded = false;
+    if let (Some(exchange), Some(token)) = (&policy.exchange, token.as_deref()) {
+        let route = rules.route_for(&target.method, &target.path);
+        let scope = Scope {
+            audience: route
+                .and_then(|route| route.exchange_audience.as_deref())
+                .or(config.exchange_audience.as_deref()),
+            resource: route
+                .and_then(|route| route.exchange_resource.as_deref())
+                .or(config.exchange_resource.as_deref()),
+        };
+        let exchanged = exchange.exchange(token, scope, policy, client, now).await?;
+        forward_exchanged(request, config, &exchanged);
+        forwarded = true;
+    }
 
-    let guess: String = String::from("Hello");
+    let mut context = ResponseContext {
+        subject: claims::claim_as_string(&response.claims, "sub"),
+        client_id: claims::claim_as_string(&response.claims, "client_id"),
+        cached,
+        forwarded,
+        ..Default::default()
+    };
 
-    match do_filter(state, config, client).await {
-        Ok(_) => Flow::Continue(()),
-        Err(err) => match err {
-            FilterError::Unexpected => {
-                logger::warn!("Unexpected error occurred while processing the request.");
-                server_error_response()
-            }
-            FilterError::NoToken => {
-                logger::debug!("No authorization token was provided.");
-                unauthorized_response()
-            }
-            FilterError::InactiveToken => {
-                logger::debug!("Token is marked as inactive by the introspection endpoint.");
-                unauthorized_response()
-            }
-            FilterError::ExpiredToken => {
-                logger::debug!("Expiration time on the token has been exceeded.");
-                unauthorized_response()
-            }
-            FilterError::NotYetActive => {
-                logger::debug!(
-                    "Token is not yet valid, since time set in the nbf claim has not been reached."
-                );
-                unauthorized_response()
-            }
-            FilterError::ClientError(err) => {
-                logger::warn!(
-                    "Error sending the request to the introspection endpoint. {:?}.",
-                    err
-                );
-                server_error_response()
-            }
-            FilterError::NonParsableIntrospectionBody(err) => {
-                logger::warn!(
-                    "Error parsing the response from the introspection endpoint. {}.",
-                    err
-                );
-                server_error_response()
-            }
-        },
+    //the body is signed by the holder of the key the token is bound to
+    if config.body_signature_verification {
+        let key = response.claims.get("cnf").and_then(|cnf| cnf.get("jwk"));
+        context.body_key = Some(key.cloned().ok_or(FilterError::InvalidBodySignature)?);
     }
-}
+
+    if let Some(version) = rules.version_for(&target.path) {
+        context
+            .headers
+            .extend(version.response_headers.iter().cloned());
+    }
+
+    //hints the browsers about to lose their session at the endpoint that refreshes the token
+    if let (Some(target), Some(exp)) = (config.refresh_hint_target.as_deref(), response.exp) {
+        let html = request
+            .header("accept")
+            .is_some_and(|accept| accept.contains("text/html"));
+        let window = config.refresh_hint_window_seconds.max(0) as u64;
+
+        if html && exp.saturating_sub(now) <= window {
+            context
+                .headers
+                .push((config.refresh_hint_header.clone(), target.to_string()));
+        }
+    }
+
+    //stamps the outcome on the response, as rendered from the claims, the expiration of the token
+    //and the mechanism that authenticated the request
+    if !config.outcome_headers.is_empty() {
+        let mut values = response.claims.clone();
+        values.insert("mechanism".to_string(), Value::from(mechanism));
+        if let Some(exp) = response.exp {
+            values.insert("exp".to_string(), Value::from(exp));
+        }
+
+        for header in config.outcome_headers.iter() {
+            if let Some(value) = claims::render(&header.value, &values) {
+                context.headers.push((header.name.clone(), value));
+            }
+        }
+    }
+
+    drop(stage);
+    let stage = policy.metrics.stage("propagation");
+
+    limit_claims(&mut response.claims, config)?;
+
+    let mut injections = Injections::default();
+    for profile in policy.profiles.iter() {
+        let payload = claims_payload(profile, &response, policy)?;
+        injections.set("claims", &profile.header, payload);
+        if config.claims_versioning {
+            injections.set("claims", &profile.version_header(), profile.version.clone());
+        }
+    }
+
+    analytics::tag(config, &response.claims);
+
+    //publishes the validation result so the policies later in the chain and the upstream service
+    //can consume it
+    if config.introspection_context_property.is_some()
+        || config.introspection_context_header.is_some()
+    {
+        //the claims removed from the propagated payloads are kept out of the context as well
+        let mut published = response.clone();
+        claims::remove_claims(&mut published.claims, &config.removed_claims);
+        let context = published.context().to_string();
+
+        if let Some(property) = config.introspection_context_property.as_deref() {
+            let path = property.split('.').collect();
+            if let Err(status) = hostcalls::set_property(path, Some(context.as_bytes())) {
+                logger::debug!("Unable to publish the introspection context. {:?}.", status);
+            }
+        }
+
+        if let Some(header) = config.introspection_context_header.as_deref() {
+            injections.set("context", header, crypto::base64url(context.as_bytes()));
+        }
+    }
+
+    if let Some(header) = config.authentication_mechanism_header.as_deref() {
+        injections.set("context", header, mechanism.to_string());
+    }
+
+    chaining::complete(config, &policy.instance, mechanism, &response);
+
+    //propagates the effective subject and the current actor of delegated tokens
+    let delegation = [
+        (
+            config.subject_header.as_deref(),
+            claims::claim_as_string(&response.claims, "sub"),
+        ),
+        (
+            config.actor_header.as_deref(),
+            claims::actor_chain(&response.claims).first().cloned(),
+        ),
+    ];
+    for (header, value) in delegation.iter() {
+        match (header, value) {
+            (Some(header), Some(value)) => injections.set("identity", header, value.clone()),
+            (Some(header), None) => injections.remove(request, header),
+            (None, _) => {}
+        }
+    }
+
+    //sets the claim headers, discarding any value sent by the client when the claim is missing
+    for mapping in config.claim_headers.iter() {
+        match claims::claim_as_header(&response.claims, &mapping.claim) {
+            Some(value) => injections.set("claimHeaders", &mapping.header, value),
+            None => injections.remove(request, &mapping.header),
+        }
+    }
+
+    //sets the routing hints, discarding any value sent by the client when the claims are missing
+    for hint in config.routing_headers.iter() {
+        match claims::render(&hint.value, &response.claims) {
+            Some(value) => injections.set("routing", &hint.name, value),
+            None => injections.remove(request, &hint.name),
+        }
+    }
+
+    //applies the header mutations whose conditions are met by the claims
+    for mutation in rules.mutations.iter() {
+        if mutation.applies(&response.claims) {
+            if let Some(value) = claims::render(&mutation.value, &response.claims) {
+                injections.set("mutations", &mutation.header, value);
+            }
+        }
+    }
+
+    //routes the request to the authority of the first override whose conditions are met
+    if let Some(rule) = rules
+        .authorities
+        .iter()
+        .find(|rule| rule.applies(&target.path, &response.claims))
+    {
+        if let Some(authority) = claims::render(&rule.authority, &response.claims) {
+            logger::debug!("Routing the request to {}.", authority);
+            injections.set("routing", ":authority", authority);
+        }
+    }
+
+    injections.apply(request, policy);
+    drop(stage);
+
+    Ok(context)
+}
+
+/// Mechanism that authenticated the request, the claims it established, whether they were served
+/// from the cache and the token they were established from
+type Authenticated<'a> = (&'a str, IntrospectionResponse, bool, Option<String>);
+
+/// Authenticates the request with the first mechanism of the route precedence that succeeds,
+/// returning the mechanism along with the claims and the token they were established from
+async fn authenticate<'a>(
+    request: &impl HeadersHandler,
+    policy: &'a Policy,
+    client: &HttpClient,
+    introspector: &impl Introspector,
+    now: u64,
+) -> Result<Authenticated<'a>, FilterError> {
+    let config = &policy.config;
+    let path = request_path(request);
+
+    let mechanisms: Vec<&str> = match config
+        .authentication_routes
+        .iter()
+        .find(|route| path::within(&path, &route.path_prefix))
+    {
+        Some(route) => route.mechanisms.iter().map(String::as_str).collect(),
+        None => DEFAULT_MECHANISMS.to_vec(),
+    };
+
+    //reports the failure of the first mechanism whose credentials were present
+    let mut failure = FilterError::NoToken;
+    let mut cached = false;
+
+    for mechanism in mechanisms {
+        let mut presented = None;
+        let result = match mechanism {
+            "token" => {
+                //Extract the token from the request
+
+                let stage = policy.metrics.stage("extraction");
+                let token = extraction::extract_token(request, config).and_then(|token| {
+                    token
+                        .map(|token| {
+                            extraction::normalize_credential(token, config, &policy.metrics)
+                        })
+                        .transpose()
+                });
+                drop(stage);
+
+                match token? {
+                    Some(token) => {
+                        let result = match break_glass_entry(&token, config, now) {
+                            //accepts the break-glass tokens without contacting the authorization
+                            //server, their claims still going through the local checks
+                            Some(entry) => {
+                                audit::emit(
+                                    &policy.instance,
+                                    Severity::High,
+                                    "break_glass_access",
+                                    json!({
+                                        "sha256": entry.sha256,
+                                        "path": path,
+                                        "notAfter": entry.not_after,
+                                    }),
+                                );
+                                Ok(break_glass_response(entry))
+                            }
+                            None => {
+                                //spares the authorization server the values that cannot be tokens
+                                if config.token_precheck {
+                                    if let Err(err) = token::precheck(&token, config) {
+                                        policy.metrics.increment("token_precheck.rejected");
+                                        return Err(err);
+                                    }
+                                }
+
+                                let issuer = issuer_hint(request, config);
+                                cached = policy.serves_cached(&token, now);
+                                presented = Some(token.clone());
+                                validate_token(
+                                    &token,
+                                    issuer.as_deref(),
+                                    policy,
+                                    client,
+                                    introspector,
+                                    now,
+                                )
+                                .await
+                            }
+                        };
+
+                        //the results are cached while the proofs are verified on every request
+                        let result = match (&policy.dpop, result) {
+                            (Some(dpop), Ok(response)) => dpop
+                                .verify(request, &token, &response, config, now)
+                                .map(|_| response),
+                            (_, result) => result,
+                        };
+                        let result = match (config.certificate_binding.as_str(), result) {
+                            ("disabled", result) => result,
+                            (_, Ok(response)) => {
+                                mtls::verify_binding(&response, config).map(|_| response)
+                            }
+                            (_, result) => result,
+                        };
+
+                        //the revocations take effect at once, even for the cached results
+                        match (&policy.denylist, result) {
+                            (Some(denylist), Ok(response))
+                                if denylist.denies(&token, &response.claims) =>
+                            {
+                                policy.metrics.increment("denylist.hit");
+                                #[cfg(feature = "cache")]
+                                if let Some(shared) = &policy.shared {
+                                    shared.revoke(&token, &policy.metrics, now);
+                                }
+                                Err(FilterError::RevokedToken)
+                            }
+                            (_, result) => result,
+                        }
+                    }
+                    None => Err(FilterError::NoToken),
+                }
+            }
+            "mtls" => match &policy.certificates {
+                Some(certificates) => certificates.authenticate(),
+                None => Err(FilterError::NoToken),
+            },
+            "apiKey" => api_key_principal(request, config),
+            "gateway" => gateway_context(request, policy),
+            _ => Err(FilterError::NoToken),
+        };
+
+        match result {
+            Ok(response) => return Ok((mechanism, response, cached, presented)),
+            Err(FilterError::NoToken) => {}
+            Err(err) => {
+                logger::debug!("Authentication through {} failed.", mechanism);
+                if matches!(failure, FilterError::NoToken) {
+                    failure = err;
+                }
+            }
+        }
+    }
+
+    Err(failure)
+}
+
+/// Accepts the claim context signed by a trusted upstream gateway that already validated the
+/// token, without contacting the authorization server
+fn gateway_context(
+    request: &impl HeadersHandler,
+    policy: &Policy,
+) -> Result<IntrospectionResponse, FilterError> {
+    let header = match policy.config.trusted_gateway_header.as_deref() {
+        Some(header) if !policy.gateway_keys.is_empty() => header,
+        _ => return Err(FilterError::NoToken),
+    };
+
+    let context = request.header(header).ok_or(FilterError::NoToken)?;
+    let jwt = Jwt::decode(&context).ok_or(FilterError::MalformedToken)?;
+
+    if !jwt.verify(&policy.gateway_keys) {
+        return Err(FilterError::InvalidSignature);
+    }
+
+    Ok(IntrospectionResponse {
+        active: true,
+        max_age: None,
+        exp: jwt.claims.get("exp").and_then(Value::as_u64),
+        nbf: jwt.claims.get("nbf").and_then(Value::as_u64),
+        claims: jwt.claims,
+    })
+}
+
+/// Authenticates the request by its API key, taking the principal bound to the key as the subject
+fn api_key_principal(
+    request: &impl HeadersHandler,
+    config: &Config,
+) -> Result<IntrospectionResponse, FilterError> {
+    if config.api_keys.is_empty() {
+        return Err(FilterError::NoToken);
+    }
+
+    let key = extraction::from_headers(&request.headers(), &config.api_key_header, None)
+        .ok_or(FilterError::NoToken)?;
+    let digest = crypto::sha256_hex(key);
+
+    let entry = config
+        .api_keys
+        .iter()
+        .find(|entry| entry.sha256.eq_ignore_ascii_case(&digest))
+        .ok_or(FilterError::InvalidApiKey)?;
+
+    let mut claims = Map::new();
+    claims.insert("sub".to_string(), Value::String(entry.principal.clone()));
+
+    Ok(IntrospectionResponse {
+        active: true,
+        max_age: None,
+        exp: None,
+        nbf: None,
+        claims,
+    })
+}
+
+/// Reshapes the claims of the validated token into the claim context the checks and the
+/// propagation work on, applying the configured mappings and the identity provider presets
+pub(crate) fn normalize_claims(
+    response: &mut IntrospectionResponse,
+    config: &Config,
+) -> Result<(), FilterError> {
+    map_claims(response, config)?;
+    claims::canonicalize(&mut response.claims, &config.claim_canonicalization);
+
+    if let Some(tenant_id) = config.azure_tenant_id.as_deref() {
+        presets::azure(&mut response.claims, tenant_id, &config.azure_audiences)?;
+    }
+
+    if config.keycloak_role_flattening {
+        presets::keycloak(&mut response.claims, &config.keycloak_clients);
+    }
+
+    if let Some(class) = claims::classify(&response.claims, config) {
+        response.claims.insert(
+            claims::TOKEN_CLASS_CLAIM.to_string(),
+            Value::String(class.to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Rebuilds the claim context from the raw validation response through the configured mappings,
+/// so bespoke response shapes can be used by the rules and the propagation
+fn map_claims(response: &mut IntrospectionResponse, config: &Config) -> Result<(), FilterError> {
+    if config.claim_mappings.is_empty() {
+        return Ok(());
+    }
+
+    let raw = serde_json::to_value(&*response).map_err(|_| FilterError::Unexpected)?;
+
+    if config.claim_mappings_only {
+        response.claims.clear();
+    }
+
+    for mapping in config.claim_mappings.iter() {
+        match raw.pointer(&mapping.pointer) {
+            Some(value) => response.claims.insert(mapping.claim.clone(), value.clone()),
+            None => response.claims.remove(&mapping.claim),
+        };
+    }
+
+    Ok(())
+}
+
+/// Bounds the size of the claims before they are propagated, truncating, dropping or denying the
+/// claims exceeding their configured limits
+fn limit_claims(claims: &mut Map<String, Value>, config: &Config) -> Result<(), FilterError> {
+    for limit in config.claim_limits.iter() {
+        let value = match claims.get_mut(&limit.claim) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let max_elements = limit.max_elements.map(|max| max.max(0) as usize);
+        let max_bytes = limit.max_bytes.map(|max| max.max(0) as usize);
+        let too_many = match (value.as_array(), max_elements) {
+            (Some(elements), Some(max)) => elements.len() > max,
+            _ => false,
+        };
+        let too_large = max_bytes.is_some_and(|max| value.to_string().len() > max);
+
+        if !too_many && !too_large {
+            continue;
+        }
+
+        logger::debug!("Claim {} exceeds its configured limits.", limit.claim);
+        match limit.on_exceed.as_str() {
+            "deny" => return Err(FilterError::ClaimTooLarge),
+            "drop" => {
+                claims.remove(&limit.claim);
+            }
+            _ => truncate(value, max_elements, max_bytes),
+        }
+    }
+
+    Ok(())
+}
+
+/// Truncates an array to its maximum elements and then drops elements, or characters of a string,
+/// until its serialized form fits the maximum bytes
+fn truncate(value: &mut Value, max_elements: Option<usize>, max_bytes: Option<usize>) {
+    if let (Value::Array(elements), Some(max)) = (&mut *value, max_elements) {
+        elements.truncate(max);
+    }
+
+    let max_bytes = match max_bytes {
+        Some(max_bytes) => max_bytes,
+        None => return,
+    };
+
+    //cuts long strings upfront, so only their escaped characters are shrunk one by one
+    if let Value::String(text) = value {
+        let mut end = text.len().min(max_bytes);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+
+    while value.to_string().len() > max_bytes {
+        let shrunk = match value {
+            Value::Array(elements) => elements.pop().is_some(),
+            Value::String(text) => text.pop().is_some(),
+            _ => false,
+        };
+
+        //values that cannot be shrunk any further are dropped to null
+        if !shrunk {
+            *value = Value::Null;
+            return;
+        }
+    }
+}
+
+/// Validates that the token is active and within its validity period
+pub(crate) fn check_validity(
+    response: &IntrospectionResponse,
+    policy: &Policy,
+    now: u64,
+) -> Result<(), FilterError> {
+    if !response.active {
+        return Err(FilterError::InactiveToken);
+    }
+
+    //tolerates the drift between the clocks of the authorization server and the gateway
+    let skew = policy.config.clock_skew_seconds.max(0) as u64;
+    let expired = response
+        .exp
+        .map(|exp| now > exp.saturating_add(skew))
+        .unwrap_or_default();
+
+    //an active token with an exp in the past means the authorization server and the token disagree
+    if expired {
+        policy.metrics.increment("validity.divergence");
+        logger::warn!("Introspection endpoint reported as active a token whose exp has passed.");
+    }
+
+    //validates if token has expired
+    if expired && policy.config.validity_precedence != "active" {
+        return Err(FilterError::ExpiredToken);
+    }
+
+    //validates if token has started its validity period
+    if response
+        .nbf
+        .map(|nbf| now.saturating_add(skew) < nbf)
+        .unwrap_or_default()
+    {
+        return Err(FilterError::NotYetActive);
+    }
+
+    Ok(())
+}
+
+/// Validates the additional tokens that must accompany the request, each one against its own
+/// introspection endpoint
+async fn validate_token_slots(
+    request: &impl HeadersHandler,
+    policy: &Policy,
+    introspector: &impl Introspector,
+    now: u64,
+) -> Result<(), FilterError> {
+    let config = &policy.config;
+    if config.token_slots.is_empty() {
+        return Ok(());
+    }
+
+    let headers = request.headers();
+    let any = config.token_slots_mode == "any";
+    let mut failure = None;
+
+    for slot in config.token_slots.iter() {
+        let token = extraction::from_headers(&headers, &slot.header, slot.prefix.as_deref());
+        let endpoint = Endpoint {
+            upstream: slot.upstream.as_deref().unwrap_or(config.upstream.as_str()),
+            host: slot.host.as_deref().unwrap_or(config.host.as_str()),
+            path: slot.path.as_deref().unwrap_or(config.path.as_str()),
+            authorization: slot
+                .authorization
+                .as_deref()
+                .unwrap_or(config.authorization.as_str()),
+            client_id: config.client_id.as_deref(),
+            client_secret: config.client_secret.as_deref(),
+            assertion: true,
+        };
+
+        let result = match token {
+            Some(token) => introspected(introspector, &token, &endpoint, policy, now)
+                .await
+                .map(|_| ()),
+            None => Err(FilterError::NoToken),
+        };
+
+        match result {
+            Ok(()) if any => return Ok(()),
+            Ok(()) => {}
+            Err(err) if any => {
+                logger::debug!("Validation of the {} token failed.", slot.name);
+                //reports the failure of a presented token rather than the absence of another one
+                if failure.is_none() || matches!(failure, Some(FilterError::NoToken)) {
+                    failure = Some(err);
+                }
+            }
+            Err(err) => {
+                logger::debug!("Validation of the {} token failed.", slot.name);
+                return Err(err);
+            }
+        }
+    }
+
+    failure.map_or(Ok(()), Err)
+}
+
+/// Introspects the token and checks the validity of the result, independently of where the
+/// result comes from
+pub(crate) async fn introspected(
+    introspector: &impl Introspector,
+    token: &str,
+    endpoint: &Endpoint<'_>,
+    policy: &Policy,
+    now: u64,
+) -> Result<IntrospectionResponse, FilterError> {
+    let response = introspector
+        .introspect(token, endpoint)
+        .await
+        .map_err(rejected_token)?;
+    check_validity(&response, policy, now)?;
+    Ok(response)
+}
+
+/// Returns whether the outcome of a validation call counts as a failure of the authorization
+/// server for the circuit breaker, according to the categories of circuitBreakerFailures
+fn breaker_failure(result: &Result<IntrospectionResponse, FilterError>, config: &Config) -> bool {
+    let category = match result {
+        Err(FilterError::ClientError(_)) | Err(FilterError::Timeout) => "unreachable",
+        Err(FilterError::IdpStatus(status)) if *status >= 500 => "serverErrors",
+        Err(FilterError::IdpStatus(_)) => "clientErrors",
+        Err(FilterError::NonParsableIntrospectionBody(_))
+        | Err(FilterError::ResponseTooLarge(_))
+        | Err(FilterError::UnverifiedIntrospectionResponse)
+        | Err(FilterError::UnsignedIntrospectionResponse) => "invalidResponses",
+        _ => return false,
+    };
+
+    config
+        .circuit_breaker_failures
+        .iter()
+        .any(|counted| counted == category)
+}
+
+/// Rejects the token the authorization server answered with an unexpected status for, as an
+/// inactive one
+fn rejected_token(err: FilterError) -> FilterError {
+    match err {
+        FilterError::IdpStatus(_) => FilterError::InactiveToken,
+        err => err,
+    }
+}
+
+/// Returns the normalized path of the request, without the query string, which is the one all
+/// the path rules are matched against
+pub(crate) fn request_path(request: &impl HeadersHandler) -> String {
+    path::normalize(&raw_request_path(request))
+}
+
+/// Returns the method the rules are matched against, which is GET for the HEAD requests unless
+/// headRequests tells them apart
+pub(crate) fn request_method(request: &impl HeadersHandler, config: &Config) -> String {
+    let method = request.header(":method").unwrap_or_default();
+
+    match (method.as_str(), config.head_requests.as_str()) {
+        ("HEAD", "asGet") => "GET".to_string(),
+        _ => method,
+    }
+}
+
+/// Returns the path of the request as sent by the client, without the query
+fn raw_request_path(request: &impl HeadersHandler) -> String {
+    let path = request.header(":path").unwrap_or_default();
+
+    match path.split_once('?') {
+        Some((path, _)) => path.to_string(),
+        None => path,
+    }
+}
+
+/// Returns the break-glass entry matching the token, if it is within its validity window
+fn break_glass_entry<'a>(
+    token: &str,
+    config: &'a Config,
+    now: u64,
+) -> Option<&'a BreakGlassTokensItem> {
+    if config.break_glass_tokens.is_empty() {
+        return None;
+    }
+
+    let digest = crypto::sha256_hex(token);
+    let now = now as i64;
+
+    config.break_glass_tokens.iter().find(|entry| {
+        entry.sha256.eq_ignore_ascii_case(&digest)
+            && entry.not_before.map(|nbf| now >= nbf).unwrap_or(true)
+            && now <= entry.not_after
+    })
+}
+
+/// Returns the claims of a break-glass token, valid within its window and granted its scope
+fn break_glass_response(entry: &BreakGlassTokensItem) -> IntrospectionResponse {
+    let mut claims = Map::new();
+    claims.insert("sub".to_string(), json!("break-glass"));
+    if let Some(scope) = &entry.scope {
+        claims.insert("scope".to_string(), json!(scope));
+    }
+
+    IntrospectionResponse {
+        active: true,
+        max_age: None,
+        exp: Some(entry.not_after.max(0) as u64),
+        nbf: entry.not_before.map(|not_before| not_before.max(0) as u64),
+        claims,
+    }
+}
+
+/// Returns the issuer named by the request, routing its token to the endpoint of the issuer
+pub(crate) fn issuer_hint(request: &impl HeadersHandler, config: &Config) -> Option<String> {
+    config
+        .issuer_header
+        .as_deref()
+        .and_then(|header| request.header(header))
+}
+
+/// Renders the introspection payload forwarded to the upstream, stripping the claims that must
+/// not leave the gateway
+fn claims_payload(
+    profile: &Profile,
+    response: &IntrospectionResponse,
+    policy: &Policy,
+) -> Result<String, FilterError> {
+    let mut payload = match serde_json::to_value(response) {
+        Ok(Value::Object(payload)) => payload,
+        _ => return Err(FilterError::Unexpected),
+    };
+
+    let removed = claims::remove_claims(&mut payload, &profile.removed_claims);
+    if !removed.is_empty() {
+        audit::emit(
+            &policy.instance,
+            Severity::Info,
+            "claims_removed",
+            json!({ "header": profile.header, "claims": removed }),
+        );
+    }
+
+    serde_json::to_string(&payload).map_err(|_| FilterError::Unexpected)
+}
+
+/// Hashes the effective configuration, canonicalized so neither the formatting nor the order of
+/// the properties alter it, along with the version of the policy that supplies the defaults
+fn fingerprint(bytes: &[u8]) -> Result<String> {
+    let canonical = serde_json::to_string(&serde_json::from_slice::<Value>(bytes)?)?;
+    Ok(crypto::sha256_hex(format!(
+        "{}:{}",
+        env!("CARGO_PKG_VERSION"),
+        canonical
+    )))
+}
+
+/// Generates a standard early response that indicates the token validation failed, with the error
+/// code and description defined by RFC 6750 when the failure concerns the bearer token
+fn unauthorized_response(
+    policy: &Policy,
+    code: &str,
+    error: Option<(&str, &str)>,
+) -> Flow<ResponseContext> {
+    let challenge = match error {
+        Some((code, description)) => format!(
+            "Bearer realm=\"{}\", error=\"{}\", error_description=\"{}\"",
+            policy.config.realm, code, description
+        ),
+        None => format!("Bearer realm=\"{}\"", policy.config.realm),
+    };
+
+    let headers = vec![("WWW-Authenticate".to_string(), challenge)];
+    rejection::respond(policy, "unauthorized", 401, headers, code)
+}
+
+/// Generates an early response that indicates the DPoP proof of the sender-constrained token is
+/// invalid, with the challenge defined by RFC 9449
+fn dpop_response(policy: &Policy, code: &str, description: &str) -> Flow<ResponseContext> {
+    let challenge = format!(
+        "DPoP realm=\"{}\", algs=\"{}\", error=\"invalid_dpop_proof\", error_description=\"{}\"",
+        policy.config.realm,
+        dpop::ALGORITHMS,
+        description
+    );
+
+    let headers = vec![("WWW-Authenticate".to_string(), challenge)];
+    rejection::respond(policy, "unauthorized", 401, headers, code)
+}
+
+/// Generates a standard early response that indicates the request is not acceptable
+fn bad_request_response(policy: &Policy) -> Flow<ResponseContext> {
+    Flow::Break(Response::new(400).with_headers(fingerprint_headers(policy)))
+}
+
+/// Generates an early response that indicates the request carries a value that cannot be a token,
+/// with the invalid_request error code defined by RFC 6750
+fn invalid_request_response(policy: &Policy, description: &str) -> Flow<ResponseContext> {
+    let challenge = format!(
+        "Bearer realm=\"{}\", error=\"invalid_request\", error_description=\"{}\"",
+        policy.config.realm, description
+    );
+    let mut headers = vec![("WWW-Authenticate".to_string(), challenge)];
+    headers.extend(fingerprint_headers(policy));
+    Flow::Break(Response::new(400).with_headers(headers))
+}
+
+/// Generates a standard early response that indicates the content type is not accepted
+fn unsupported_media_type_response(policy: &Policy) -> Flow<ResponseContext> {
+    Flow::Break(Response::new(415).with_headers(fingerprint_headers(policy)))
+}
+
+/// Generates a standard early response that indicates the token is not allowed to access the resource
+fn forbidden_response(policy: &Policy, code: &str) -> Flow<ResponseContext> {
+    rejection::respond(policy, "forbidden", 403, vec![], code)
+}
+
+/// Generates a standard early response that indicates the token lacks a scope required by the
+/// resource, as defined by RFC 6750
+fn insufficient_scope_response(policy: &Policy, code: &str) -> Flow<ResponseContext> {
+    let headers = vec![(
+        "WWW-Authenticate".to_string(),
+        format!(
+            "Bearer realm=\"{}\", error=\"insufficient_scope\"",
+            policy.config.realm
+        ),
+    )];
+    rejection::respond(policy, "forbidden", 403, headers, code)
+}
+
+/// Generates a standard early response that indicates the policy cannot serve the request right now
+fn service_unavailable_response(policy: &Policy, code: &str) -> Flow<ResponseContext> {
+    rejection::respond(policy, "serverError", 503, retry_hints(policy, None), code)
+}
+
+/// Generates an early response that tells the client when to retry after the authorization server
+/// rate limited the policy
+fn rate_limited_response(policy: &Policy, status: u32, retry_after: u64) -> Flow<ResponseContext> {
+    let mut headers = retry_hints(policy, Some(retry_after));
+    headers.extend(fingerprint_headers(policy));
+    Flow::Break(Response::new(status).with_headers(headers))
+}
+
+/// Generates an early response that tells the client when it may send requests again after
+/// exceeding the rate limit of its client_id
+fn client_rate_limited_response(
+    policy: &Policy,
+    code: &str,
+    retry_after: u64,
+) -> Flow<ResponseContext> {
+    let headers = vec![("Retry-After".to_string(), retry_after.to_string())];
+    rejection::respond(policy, "rateLimited", 429, headers, code)
+}
+
+/// Generates an early response that indicates the authorization server did not validate the token
+/// in time
+fn timeout_response(policy: &Policy, code: &str) -> Flow<ResponseContext> {
+    let status = policy.config.timeout_status as u32;
+    rejection::respond(
+        policy,
+        "serverError",
+        status,
+        retry_hints(policy, None),
+        code,
+    )
+}
+
+/// Generates a standard early response that indicates that there was an unexpected error
+fn server_error_response(policy: &Policy, code: &str) -> Flow<ResponseContext> {
+    rejection::respond(policy, "serverError", 500, retry_hints(policy, None), code)
+}
+
+/// Returns the debugging header that identifies the configuration the request was decided with,
+/// along with the receipt of the rejection being built, if any
+fn fingerprint_headers(policy: &Policy) -> Vec<(String, String)> {
+    let receipt = policy
+        .rejection_receipt
+        .take()
+        .map(|receipt| (policy.config.receipt_header.clone(), receipt));
+
+    policy
+        .config
+        .fingerprint_header
+        .iter()
+        .map(|header| (header.clone(), policy.fingerprint.clone()))
+        .chain(receipt)
+        .collect()
+}
+
+/// Returns the headers that let well-behaved clients coordinate their retries of a transient
+/// failure: a jittered Retry-After and a retry token shared by the clients failing in the same
+/// backoff window
+fn retry_hints(policy: &Policy, retry_after: Option<u64>) -> Vec<(String, String)> {
+    let base = match (retry_after, policy.config.retry_after_seconds) {
+        (Some(retry_after), _) => retry_after,
+        (None, Some(base)) => base.max(1) as u64,
+        (None, None) => return vec![],
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let jitter = match policy.config.retry_jitter_seconds.max(0) as u64 {
+        0 => 0,
+        jitter => u64::from(now.subsec_nanos()) % (jitter + 1),
+    };
+    let window = now.as_secs() / base;
+    let token = crypto::sha256_hex(format!("{}:{}", policy.fingerprint, window));
+
+    vec![
+        ("Retry-After".to_string(), (base + jitter).to_string()),
+        ("X-Retry-Token".to_string(), token[..16].to_string()),
+    ]
+}
+
+/// Defines a filter function that works as a wrapper for the real filter function that enables simplified error handling
+async fn request_filter(
+    state: RequestState,
+    client: HttpClient,
+    policy: &Policy,
+) -> Flow<ResponseContext> {
+    let config = &policy.config;
+    let state = state.into_headers_state().await;
+
+    if let Some(flow) = admin::health(&state, policy) {
+        return flow;
+    }
+
+    if let Some(flow) = admin::metrics(&state, policy) {
+        return flow;
+    }
+
+    if let Some(flow) = admin::explain(&state, policy, &client).await {
+        return flow;
+    }
+
+    let context_id = state.header(&config.context_id_header);
+    let started = SystemTime::now();
+    //the decision log only carries the fingerprint of the token
+    let token_fingerprint = match config.decision_logging.as_str() {
+        "off" => None,
+        _ => extraction::extract_token(&state, config)
+            .ok()
+            .flatten()
+            .map(|token| audit::fingerprint(&token)),
+    };
+
+    //lets the exempted requests through before looking for a token
+    let method = request_method(&state, config);
+    let path = request_path(&state);
+    if policy
+        .rules
+        .exemptions
+        .iter()
+        .any(|exemption| exemption.matches(&method, &path))
+    {
+        logger::debug!("Skipping the validation of an exempted request.");
+        return skipped(policy, context_id.as_deref(), "exempt");
+    }
+
+    //leaves the request to the earlier policy of the chain that already decided on it
+    if let Some(flag) = chaining::veto(config) {
+        logger::debug!(
+            "Skipping the validation vetoed by an earlier policy: {}.",
+            flag
+        );
+        policy.metrics.increment("chaining.vetoed");
+        return skipped(policy, context_id.as_deref(), "vetoed");
+    }
+
+    if let Some(flow) = headers_only(&state, state.contains_body(), policy, context_id.as_deref()) {
+        return flow;
+    }
+
+    let introspector = HttpIntrospector {
+        policy,
+        client: &client,
+    };
+    let mut result = do_filter(&state, policy, &client, &introspector).await;
+
+    //lets the request through unauthenticated when the authorization server cannot be reached,
+    //marking it so the upstream service and the client can tell
+    let mut outcome = "allowed";
+    if let Err(
+        err @ (FilterError::ClientError(_) | FilterError::Timeout | FilterError::WarmingUp),
+    ) = &result
+    {
+        if config.fail_open {
+            outcome = "failOpen";
+            logger::warn!(
+                "Unable to reach the introspection endpoint, failing open. {:?}.",
+                err
+            );
+            policy.metrics.increment("fail_open");
+            state.set_header(&config.fail_open_header, "true");
+            result = Ok(ResponseContext {
+                headers: vec![(config.fail_open_header.clone(), "true".to_string())],
+                ..Default::default()
+            });
+        }
+    }
+
+    //keeps the validated credential from reaching the upstream service
+    if matches!(&result, Ok(context) if !context.forwarded) {
+        extraction::forward_token(&state, config);
+    }
+
+    let route = format!("{} {}", method, path);
+
+    if let Ok(ResponseContext {
+        body_key: Some(key),
+        ..
+    }) = &result
+    {
+        let key = key.clone();
+        let body = state.into_body_state().await.handler().body();
+        if !verify_body_signature(&body, &key) {
+            result = Err(FilterError::InvalidBodySignature);
+        }
+    }
+
+    if let Some(key) = &policy.receipt_key {
+        match &mut result {
+            Ok(context) => {
+                if let Some(receipt) =
+                    issue_receipt(policy, key, "allow", context.subject.as_deref(), &route)
+                {
+                    context
+                        .headers
+                        .push((config.receipt_header.clone(), receipt));
+                }
+            }
+            Err(_) => {
+                policy
+                    .rejection_receipt
+                    .replace(issue_receipt(policy, key, "deny", None, &route));
+            }
+        }
+    }
+
+    if let Ok(context) = &mut result {
+        context.headers.extend(fingerprint_headers(policy));
+        context.trailers = outcome_trailers(policy, context_id.as_deref(), outcome);
+    }
+    policy.metrics.decision(result.as_ref().err());
+    analytics::tag_decision(config, result.as_ref().err().map(FilterError::code));
+    audit::decision(
+        &policy.instance,
+        &config.decision_logging,
+        audit::Decision {
+            request_id: context_id.as_deref(),
+            token_fingerprint: token_fingerprint.as_deref(),
+            client_id: result
+                .as_ref()
+                .ok()
+                .and_then(|context| context.client_id.as_deref()),
+            rejection: result.as_ref().err(),
+            latency_micros: started.elapsed().unwrap_or_default().as_micros() as u64,
+        },
+    );
+
+    let flow = match result {
+        Ok(context) => Flow::Continue(context),
+        Err(err) => {
+            let code = err.code();
+            match err {
+                FilterError::Unexpected => {
+                    logger::warn!("Unexpected error occurred while processing the request.");
+                    server_error_response(policy, code)
+                }
+                FilterError::NoToken => {
+                    logger::debug!("No authorization token was provided.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_request", "The request carries no access token")),
+                    )
+                }
+                FilterError::UnknownTokenPrefix => {
+                    logger::debug!("Token prefix does not match any of the configured routes.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some((
+                            "invalid_token",
+                            "The access token is not accepted by this resource",
+                        )),
+                    )
+                }
+                FilterError::MalformedToken => {
+                    logger::debug!("Token is neither a well formed JWT nor an opaque token.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token is malformed")),
+                    )
+                }
+                FilterError::UnsupportedTokenFormat => {
+                    logger::debug!("Tokens of the detected format are rejected by configuration.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token format is not accepted")),
+                    )
+                }
+                FilterError::UntrustedIssuer => {
+                    logger::debug!("Token was issued by an issuer that is not allowed.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some((
+                            "invalid_token",
+                            "The access token was issued by an untrusted issuer",
+                        )),
+                    )
+                }
+                FilterError::InvalidAudience => {
+                    logger::debug!("Token was not issued for any of the expected audiences.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::UntrustedClientCertificate => {
+                    logger::debug!("Client certificate identity is not in the allowlist.");
+                    unauthorized_response(policy, code, None)
+                }
+                FilterError::InvalidApiKey => {
+                    logger::debug!("API key does not match any of the configured keys.");
+                    unauthorized_response(policy, code, None)
+                }
+                FilterError::CompatibilityCheckFailed(reason) => {
+                    logger::debug!(
+                        "Token failed the identity provider specific checks: {}.",
+                        reason
+                    );
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token failed the issuer checks")),
+                    )
+                }
+                FilterError::InvalidSignature => {
+                    logger::debug!("Token signature could not be verified.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token signature is invalid")),
+                    )
+                }
+                FilterError::GrantTypeNotAllowed => {
+                    logger::debug!("Token was obtained through a grant type that is not allowed.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::ActorNotAllowed => {
+                    logger::debug!("Token delegation chain contains an actor that is not allowed.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::ClientNotAllowed => {
+                    logger::debug!("Machine token was issued to a client that is not allowed.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::InactiveToken | FilterError::IdpStatus(_) => {
+                    logger::debug!("Token is marked as inactive by the introspection endpoint.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token is not active")),
+                    )
+                }
+                FilterError::ExpiredToken => {
+                    logger::debug!("Expiration time on the token has been exceeded.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token expired")),
+                    )
+                }
+                FilterError::LifetimeTooLong => {
+                    logger::debug!("Token lifetime exceeds the configured maximum.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some((
+                            "invalid_token",
+                            "The access token lifetime exceeds the allowed maximum",
+                        )),
+                    )
+                }
+                FilterError::IssuedInFuture => {
+                    logger::debug!("Token was issued at a time in the future.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token was issued in the future")),
+                    )
+                }
+                FilterError::NotYetActive => {
+                    logger::debug!(
+                    "Token is not yet valid, since time set in the nbf claim has not been reached."
+                );
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token is not valid yet")),
+                    )
+                }
+                FilterError::TooManyOutboundCalls => {
+                    logger::warn!(
+                        "Limit of concurrent calls to the introspection endpoint reached."
+                    );
+                    service_unavailable_response(policy, code)
+                }
+                FilterError::WarmingUp => {
+                    logger::debug!("Introspection rate of the warm-up window reached.");
+                    service_unavailable_response(policy, code)
+                }
+                FilterError::CircuitOpen if config.circuit_open_behavior == "failOpen" => {
+                    logger::warn!(
+                        "Circuit to the authorization server is open, letting the request through."
+                    );
+                    skipped(policy, context_id.as_deref(), "failOpen")
+                }
+                FilterError::CircuitOpen => {
+                    logger::debug!("Circuit to the authorization server is open.");
+                    service_unavailable_response(policy, code)
+                }
+                FilterError::TenantMismatch => {
+                    logger::debug!(
+                    "Token was issued for a tenant other than the one addressed by the request host."
+                );
+                    forbidden_response(policy, code)
+                }
+                FilterError::ClaimTooLarge => {
+                    logger::debug!("Token carries a claim exceeding the configured limits.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::InsufficientScope => {
+                    logger::debug!("Token lacks a scope required to access the resource.");
+                    insufficient_scope_response(policy, code)
+                }
+                FilterError::UnmatchedRoute => {
+                    logger::debug!("Request path does not match any of the configured rules.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::RouteClaimMismatch => {
+                    logger::debug!("Token claims do not meet the requirements of the route.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::ClaimValidationFailed => {
+                    logger::debug!("Token claims do not satisfy the claim validations.");
+                    forbidden_response(policy, code)
+                }
+                FilterError::NonCanonicalPath => {
+                    logger::debug!("Request path changes under normalization.");
+                    bad_request_response(policy)
+                }
+                FilterError::UpgradeNotAllowed => {
+                    logger::debug!("Request attempts a protocol upgrade that is not allowed.");
+                    bad_request_response(policy)
+                }
+                FilterError::UnsupportedContentType => {
+                    logger::debug!(
+                        "Request content type is not accepted by the requested API version."
+                    );
+                    unsupported_media_type_response(policy)
+                }
+                FilterError::DuplicateAuthorization => {
+                    logger::debug!("Request carries more than one Authorization header.");
+                    bad_request_response(policy)
+                }
+                FilterError::TokenPrecheckFailed(description) => {
+                    logger::debug!("Token failed the local checks. {}.", description);
+                    invalid_request_response(policy, description)
+                }
+                FilterError::InvalidBodySignature => {
+                    logger::debug!(
+                        "Request body is not signed with the key the token is bound to."
+                    );
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some((
+                            "invalid_token",
+                            "The request body is not signed with the key bound to the access token",
+                        )),
+                    )
+                }
+                FilterError::ExchangeFailed => {
+                    logger::warn!("Unable to exchange the token for the upstream service.");
+                    server_error_response(policy, code)
+                }
+                FilterError::RevokedToken => {
+                    logger::debug!("Token is in the deny-list.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some(("invalid_token", "The access token was revoked")),
+                    )
+                }
+                FilterError::TokenBindingMismatch => {
+                    logger::debug!("Token is not bound to the client certificate.");
+                    unauthorized_response(
+                        policy,
+                        code,
+                        Some((
+                            "invalid_token",
+                            "The access token is not bound to the client certificate",
+                        )),
+                    )
+                }
+                FilterError::InvalidDpopProof(description) => {
+                    logger::debug!("Rejecting the DPoP proof. {}.", description);
+                    dpop_response(policy, code, description)
+                }
+                FilterError::RateLimited(retry_after) => {
+                    logger::warn!(
+                    "Introspection endpoint rate limited the policy, retrying after {} seconds.",
+                    retry_after
+                );
+                    rate_limited_response(
+                        policy,
+                        config.idp_rate_limited_status as u32,
+                        retry_after,
+                    )
+                }
+                FilterError::ClientRateLimited(retry_after) => {
+                    logger::debug!("Client exceeded its rate limit.");
+                    client_rate_limited_response(policy, code, retry_after)
+                }
+                FilterError::ResponseTooLarge(size) => {
+                    logger::warn!(
+                        "Response of {} bytes from an outbound call exceeds the configured limit.",
+                        size
+                    );
+                    server_error_response(policy, code)
+                }
+                FilterError::Timeout => {
+                    logger::warn!(
+                        "Introspection endpoint did not respond within {} milliseconds.",
+                        config.introspection_timeout_millis.unwrap_or_default()
+                    );
+                    timeout_response(policy, code)
+                }
+                FilterError::ClientError(err) => {
+                    logger::warn!(
+                        "Error sending the request to the introspection endpoint. {:?}.",
+                        err
+                    );
+                    server_error_response(policy, code)
+                }
+                FilterError::NonParsableIntrospectionBody(err) => {
+                    logger::warn!(
+                        "Error parsing the response from the introspection endpoint. {}.",
+                        err
+                    );
+                    server_error_response(policy, code)
+                }
+                FilterError::UnverifiedIntrospectionResponse => {
+                    logger::warn!(
+                        "Signed response of the introspection endpoint failed verification."
+                    );
+                    server_error_response(policy, code)
+                }
+                FilterError::UnsignedIntrospectionResponse => {
+                    logger::warn!("Introspection endpoint answered without signing the response.");
+                    server_error_response(policy, code)
+                }
+            }
+        }
+    };
+
+    //the rejections that carry no headers leave no receipt behind for the next request
+    policy.rejection_receipt.take();
+    flow
+}
+
+/// Verifies the request body, a compact JWS, against the JWK the token is confirmed with
+fn verify_body_signature(body: &[u8], key: &Value) -> bool {
+    std::str::from_utf8(body)
+        .ok()
+        .and_then(|body| Jwt::decode(body.trim()))
+        .is_some_and(|jws| jws.verify_with(key))
+}
+
+/// Signs a receipt of the decision taken for the request and records it in the audit log
+fn issue_receipt(
+    policy: &Policy,
+    key: &SigningKey,
+    decision: &str,
+    subject: Option<&str>,
+    route: &str,
+) -> Option<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default();
+
+    let receipt = key
+        .sign(
+            policy.config.receipt_signing_key_id.as_deref(),
+            &json!({ "decision": decision, "sub": subject, "route": route, "iat": now }),
+        )
+        .ok()?;
+    audit::emit(
+        &policy.instance,
+        Severity::Info,
+        "decision_receipt",
+        json!({ "decision": decision, "receipt": receipt }),
+    );
+
+    Some(receipt)
+}
+
+/// Handles upfront the headers-only requests, which end the stream along with the headers, unless
+/// they are validated as the rest of the requests
+fn headers_only(
+    request: &impl HeadersHandler,
+    contains_body: bool,
+    policy: &Policy,
+    context_id: Option<&str>,
+) -> Option<Flow<ResponseContext>> {
+    //a GET or any other request without a body is not one of them
+    if contains_body || !is_grpc(request) {
+        return None;
+    }
+
+    match policy.config.headers_only_requests.as_str() {
+        "skip" => {
+            logger::debug!("Skipping the validation of a headers-only request.");
+            Some(skipped(policy, context_id, "skipped"))
+        }
+        "reject" => {
+            logger::debug!("Rejecting a headers-only request.");
+            Some(bad_request_response(policy))
+        }
+        _ => None,
+    }
+}
+
+/// Returns true for the gRPC requests, the only ones the headers-only handling applies to
+fn is_grpc(request: &impl HeadersHandler) -> bool {
+    let grpc = request
+        .header("content-type")
+        .is_some_and(|content_type| content_type.starts_with("application/grpc"));
+
+    grpc && request.header("te").as_deref() == Some("trailers")
+}
+
+/// Lets the request through without validating it, reporting the outcome in the trailers
+fn skipped(policy: &Policy, context_id: Option<&str>, outcome: &str) -> Flow<ResponseContext> {
+    Flow::Continue(ResponseContext {
+        trailers: outcome_trailers(policy, context_id, outcome),
+        ..Default::default()
+    })
+}
+
+/// Returns the trailers carrying the outcome of the validation and the context identifier of the
+/// request, for the tooling of the streaming protocols that reads the trailers only
+fn outcome_trailers(
+    policy: &Policy,
+    context_id: Option<&str>,
+    outcome: &str,
+) -> Vec<(String, String)> {
+    let config = &policy.config;
+    if !config.outcome_trailers {
+        return vec![];
+    }
+
+    let mut trailers = vec![(config.outcome_trailer.clone(), outcome.to_string())];
+    if let Some(context_id) = context_id {
+        trailers.push((config.context_id_trailer.clone(), context_id.to_string()));
+    }
+    trailers
+}
+
+/// Applies the data captured by the request filter to the response sent back to the client
+#[cfg_attr(not(feature = "cache"), allow(unused_variables))]
+async fn response_filter(
+    state: ResponseState,
+    data: RequestData<ResponseContext>,
+    policy: &Policy,
+) {
+    if let RequestData::Continue(context) = data {
+        let state = state.into_headers_state().await;
+
+        //an upstream service rejecting a token served from the cache hints at a revocation
+        #[cfg(feature = "cache")]
+        if let (Some(adaptive), true) = (&policy.adaptive_ttl, context.cached) {
+            let diverged = state.header(":status").as_deref() == Some("401");
+            if diverged {
+                policy.metrics.increment("cache.upstream_divergence");
+            }
+            if let (Some(ttl), Some(cache)) = (adaptive.observe(diverged), &policy.cache) {
+                logger::info!(
+                    "Adapting the TTL of the introspection cache to {} seconds.",
+                    ttl
+                );
+                cache.set_ttl(ttl);
+            }
+        }
+
+        for (name, value) in context.headers.iter() {
+            state.set_header(name, value);
+        }
+
+        //the trailers follow the body, and are refused by the host when the protocol has none
+        if !context.trailers.is_empty() {
+            let _body = state.into_body_state().await;
+            for (name, value) in context.trailers.iter() {
+                let added = hostcalls::add_map_value(MapType::HttpResponseTrailers, name, value);
+                if let Err(status) = added {
+                    logger::debug!("Unable to append the trailer {}. {:?}.", name, status);
+                }
+            }
+        }
+    }
+}
 
 #[entrypoint]
-async fn configure(launcher: Launcher, Configuration(bytes): Configuration) -> Result<()> {
-    let config = serde_json::from_slice(&bytes)?;
-    let filter = on_request(|request, client| request_filter(request, client, &config));
-    launcher.launch(filter).await?;
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+    clock: Clock,
+    client: HttpClient,
+) -> Result<()> {
+    let config: Config = serde_json::from_slice(&bytes)?;
+    let policy = Policy::new(config, &bytes, clock)?;
+    logger::info!(
+        "Policy {} configured with the configuration {}.",
+        policy.instance,
+        policy.fingerprint
+    );
+    if policy.config.startup_probe {
+        startup::probe(&policy, &client).await?;
+    }
+
+    let filter = on_request(|request, client| request_filter(request, client, &policy))
+        .on_response(|state, data| response_filter(state, data, &policy));
+
+    //runs the background jobs alongside the filter, on the timer shared with the request processing
+    let scheduler = Scheduler::of(&policy, &client);
+    match &policy.timer {
+        Some(timer) if !scheduler.is_empty() => {
+            let (launched, _) =
+                futures::join!(launcher.launch(filter), scheduler.run(&policy, timer));
+            launched?;
+        }
+        _ => launcher.launch(filter).await?,
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    use super::*;
+    use crate::introspector::MockIntrospector;
+    use crate::testing::{self, Headers};
+
+    const TOKEN: &str = "opaque-token";
+
+    fn filter(policy: &Policy, claims: Value) -> Result<ResponseContext, FilterError> {
+        let request = Headers::new(&[
+            (":method", "GET"),
+            (":path", "/orders"),
+            (":authority", "api.example.com"),
+            ("authorization", &format!("Bearer {}", TOKEN)),
+        ]);
+        let introspector = MockIntrospector::default().with(TOKEN, testing::active(claims));
+
+        block_on(do_filter(
+            &request,
+            policy,
+            &HttpClient::default(),
+            &introspector,
+        ))
+    }
+
+    /// Headers of a gRPC health check, which ends the stream along with the headers
+    fn health_check(authorization: Option<&str>) -> Headers {
+        let mut headers = vec![
+            (":method", "POST"),
+            (":path", "/grpc.health.v1.Health/Check"),
+            ("content-type", "application/grpc"),
+            ("te", "trailers"),
+        ];
+        headers.extend(authorization.map(|authorization| ("authorization", authorization)));
+        Headers::new(&headers)
+    }
+
+    #[test]
+    fn validates_the_headers_only_requests() {
+        let policy = testing::policy(json!({ "headersOnlyRequests": "validate" }));
+        let now = testing::now();
+        let introspector =
+            MockIntrospector::default().with(TOKEN, testing::active(json!({ "exp": now + 600 })));
+        let bearer = format!("Bearer {}", TOKEN);
+
+        let authorized = health_check(Some(&bearer));
+        let anonymous = health_check(None);
+        let client = HttpClient::default();
+
+        assert!(headers_only(&anonymous, false, &policy, None).is_none());
+        assert!(block_on(do_filter(&authorized, &policy, &client, &introspector)).is_ok());
+        assert!(matches!(
+            block_on(do_filter(&anonymous, &policy, &client, &introspector)),
+            Err(FilterError::NoToken)
+        ));
+    }
+
+    #[test]
+    fn skips_the_headers_only_requests() {
+        let policy = testing::policy(json!({ "headersOnlyRequests": "skip" }));
+        let get = Headers::new(&[(":method", "GET"), (":path", "/orders")]);
+
+        assert!(matches!(
+            headers_only(&health_check(None), false, &policy, None),
+            Some(Flow::Continue(_))
+        ));
+        assert!(headers_only(&health_check(None), true, &policy, None).is_none());
+        assert!(headers_only(&get, false, &policy, None).is_none());
+    }
+
+    #[test]
+    fn rejects_the_headers_only_requests() {
+        let policy = testing::policy(json!({ "headersOnlyRequests": "reject" }));
+        let get = Headers::new(&[(":method", "GET"), (":path", "/orders")]);
+
+        assert!(matches!(
+            headers_only(&health_check(None), false, &policy, None),
+            Some(Flow::Break(_))
+        ));
+        assert!(headers_only(&health_check(None), true, &policy, None).is_none());
+        assert!(headers_only(&get, false, &policy, None).is_none());
+    }
+
+    /// Validates the token at the given time through the caches, the breaker and the introspector
+    fn validate_at(
+        policy: &Policy,
+        introspector: &MockIntrospector,
+        now: u64,
+    ) -> Result<IntrospectionResponse, FilterError> {
+        block_on(validate_token(
+            TOKEN,
+            None,
+            policy,
+            &HttpClient::default(),
+            introspector,
+            now,
+        ))
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn serves_the_cached_result_without_introspecting_again() {
+        let policy = testing::policy(json!({ "cacheTtlSeconds": 60 }));
+        let now = testing::now();
+        let introspector =
+            MockIntrospector::default().with(TOKEN, testing::active(json!({ "exp": now + 600 })));
+
+        assert!(validate_at(&policy, &introspector, now).is_ok());
+        assert!(validate_at(&policy, &introspector, now + 59).is_ok());
+        assert_eq!(introspector.calls(), 1);
+        assert!(validate_at(&policy, &introspector, now + 60).is_ok());
+        assert_eq!(introspector.calls(), 2);
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn serves_the_stale_result_while_the_token_is_validated_again() {
+        let policy = testing::policy(json!({ "cacheTtlSeconds": 60, "staleGraceSeconds": 30 }));
+        let now = testing::now();
+        let introspector =
+            MockIntrospector::default().with(TOKEN, testing::active(json!({ "exp": now + 600 })));
+        assert!(validate_at(&policy, &introspector, now).is_ok());
+
+        let unreachable = MockIntrospector::default().unreachable();
+        assert!(validate_at(&policy, &unreachable, now + 70).is_ok());
+        assert_eq!(unreachable.calls(), 0);
+        assert_eq!(policy.revalidations.pop(), Some((TOKEN.to_string(), None)));
+        assert!(matches!(
+            validate_at(&policy, &unreachable, now + 90),
+            Err(FilterError::Timeout)
+        ));
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn rejects_the_tokens_found_inactive_moments_ago_without_introspecting_them() {
+        let policy = testing::policy(json!({ "negativeCacheTtlSeconds": 30 }));
+        let now = testing::now();
+        let mut inactive = testing::active(json!({}));
+        inactive.active = false;
+        let introspector = MockIntrospector::default().with(TOKEN, inactive);
+        assert!(!validate_at(&policy, &introspector, now).unwrap().active);
+
+        let reactivated =
+            MockIntrospector::default().with(TOKEN, testing::active(json!({ "exp": now + 600 })));
+        assert!(matches!(
+            validate_at(&policy, &reactivated, now + 29),
+            Err(FilterError::InactiveToken)
+        ));
+        assert_eq!(reactivated.calls(), 0);
+        assert!(validate_at(&policy, &reactivated, now + 30).is_ok());
+    }
+
+    #[test]
+    fn short_circuits_the_authorization_server_once_the_circuit_opens() {
+        let policy = testing::policy(json!({
+            "circuitBreakerThreshold": 2,
+            "circuitBreakerCoolDownSeconds": 30
+        }));
+        let now = testing::now();
+        let unreachable = MockIntrospector::default().unreachable();
+
+        for _ in 0..2 {
+            assert!(matches!(
+                validate_at(&policy, &unreachable, now),
+                Err(FilterError::Timeout)
+            ));
+        }
+        assert!(matches!(
+            validate_at(&policy, &unreachable, now + 29),
+            Err(FilterError::CircuitOpen)
+        ));
+        assert_eq!(unreachable.calls(), 2);
+
+        //the probe made once the cool-down elapses closes the circuit when it succeeds
+        let recovered =
+            MockIntrospector::default().with(TOKEN, testing::active(json!({ "exp": now + 600 })));
+        assert!(validate_at(&policy, &recovered, now + 30).is_ok());
+        assert!(validate_at(&policy, &recovered, now + 31).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_token_of_another_tenant() {
+        let policy = testing::policy(json!({
+            "tenantHostPattern": "^([a-z]+)\\.api\\.example\\.com$",
+            "tenantClaim": "tenant"
+        }));
+        let now = testing::now();
+        let request = Headers::new(&[
+            (":method", "GET"),
+            (":path", "/orders"),
+            (":authority", "acme.api.example.com"),
+            ("authorization", &format!("Bearer {}", TOKEN)),
+        ]);
+        let tenant = |tenant: &str| {
+            let introspector = MockIntrospector::default().with(
+                TOKEN,
+                testing::active(json!({ "tenant": tenant, "exp": now + 600 })),
+            );
+            block_on(do_filter(
+                &request,
+                &policy,
+                &HttpClient::default(),
+                &introspector,
+            ))
+        };
+
+        assert!(tenant("acme").is_ok());
+        assert!(matches!(tenant("globex"), Err(FilterError::TenantMismatch)));
+    }
+
+    #[cfg(feature = "private-key-jwt")]
+    #[test]
+    fn signs_a_verifiable_receipt_of_the_decision() {
+        let signing = p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+        let point = signing.verifying_key().to_encoded_point(false);
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": crypto::base64url(point.x().unwrap()),
+            "y": crypto::base64url(point.y().unwrap()),
+        });
+        let policy = testing::policy(json!({ "receiptSigningKeyId": "receipts-1" }));
+
+        let receipt = issue_receipt(
+            &policy,
+            &SigningKey::Es256(signing),
+            "allow",
+            Some("user-1"),
+            "GET /orders",
+        )
+        .unwrap();
+        let jwt = Jwt::decode(&receipt).unwrap();
+
+        assert!(jwt.verify_with(&jwk));
+        assert_eq!(jwt.key_id(), Some("receipts-1"));
+        assert_eq!(jwt.claims.get("decision"), Some(&json!("allow")));
+        assert_eq!(jwt.claims.get("sub"), Some(&json!("user-1")));
+        assert_eq!(jwt.claims.get("route"), Some(&json!("GET /orders")));
+    }
+
+    /// A policy validating the access tokens of a Cognito user pool
+    fn cognito(properties: Value) -> Policy {
+        let mut config = json!({
+            "cognitoUserPoolId": "us-east-1_AbCdEf",
+            "cognitoJwksUpstream": "cognito"
+        });
+        if let (Some(config), Value::Object(properties)) = (config.as_object_mut(), properties) {
+            config.extend(properties);
+        }
+        testing::policy(config)
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn serves_the_cognito_tokens_from_the_cache() {
+        let policy = cognito(json!({ "cacheTtlSeconds": 60 }));
+        let now = testing::now();
+        let route = Endpoint::select(TOKEN, None, &policy.config)
+            .unwrap()
+            .fingerprint();
+        let response = testing::active(json!({ "sub": "user-1", "exp": now + 600 }));
+        for cache in policy.caches() {
+            cache.insert(TOKEN, &route, &response, now);
+        }
+
+        let result = validate_at(&policy, &MockIntrospector::default(), now);
+
+        assert_eq!(result.unwrap().claims.get("sub"), Some(&json!("user-1")));
+    }
+
+    #[test]
+    fn short_circuits_the_cognito_tokens_while_the_circuit_is_open() {
+        let policy = cognito(json!({ "circuitBreakerThreshold": 1 }));
+        let now = testing::now();
+        let breaker = policy.breaker.as_ref().unwrap();
+        breaker.record(false, now);
+
+        let result = validate_at(&policy, &MockIntrospector::default(), now);
+
+        assert!(matches!(result, Err(FilterError::CircuitOpen)));
+    }
+
+    /// Validates a request carrying the break-glass token configured with the given scope
+    fn break_glass(properties: Value, scope: &str) -> Result<ResponseContext, FilterError> {
+        let now = testing::now() as i64;
+        let mut config = json!({
+            "breakGlassTokens": [{
+                "sha256": crypto::sha256_hex("break-glass-token"),
+                "notAfter": now + 600,
+                "scope": scope
+            }]
+        });
+        if let (Some(config), Value::Object(properties)) = (config.as_object_mut(), properties) {
+            config.extend(properties);
+        }
+        let request = Headers::new(&[
+            (":method", "GET"),
+            (":path", "/orders"),
+            ("authorization", "Bearer break-glass-token"),
+        ]);
+
+        block_on(do_filter(
+            &request,
+            &testing::policy(config),
+            &HttpClient::default(),
+            &MockIntrospector::default(),
+        ))
+    }
+
+    #[test]
+    fn accepts_a_break_glass_token_without_introspecting_it() {
+        let context = break_glass(json!({}), "orders:read").unwrap();
+
+        assert_eq!(context.subject.as_deref(), Some("break-glass"));
+    }
+
+    #[test]
+    fn checks_the_scope_of_a_break_glass_token() {
+        let properties = json!({ "requiredScopes": ["orders:write"] });
+
+        assert!(break_glass(properties.clone(), "orders:write").is_ok());
+        assert!(matches!(
+            break_glass(properties, "orders:read"),
+            Err(FilterError::InsufficientScope)
+        ));
+    }
+
+    #[test]
+    fn accepts_a_valid_token() {
+        let policy = testing::policy(json!({}));
+        let now = testing::now();
+
+        let result = filter(&policy, json!({ "sub": "user-1", "exp": now + 600 }));
+
+        assert_eq!(result.unwrap().subject.as_deref(), Some("user-1"));
+    }
+
+    #[test]
+    fn rejects_an_unknown_token() {
+        let policy = testing::policy(json!({}));
+        let request = Headers::new(&[(":path", "/"), ("authorization", "Bearer other")]);
+        let introspector = MockIntrospector::default();
+
+        let result = block_on(do_filter(
+            &request,
+            &policy,
+            &HttpClient::default(),
+            &introspector,
+        ));
+
+        assert!(matches!(result, Err(FilterError::InactiveToken)));
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let policy = testing::policy(json!({}));
+        let now = testing::now();
+
+        let result = filter(&policy, json!({ "exp": now - 600 }));
+
+        assert!(matches!(result, Err(FilterError::ExpiredToken)));
+    }
+
+    #[test]
+    fn rejects_a_token_not_yet_active() {
+        let policy = testing::policy(json!({}));
+        let now = testing::now();
+
+        let result = filter(&policy, json!({ "nbf": now + 600, "exp": now + 1200 }));
+
+        assert!(matches!(result, Err(FilterError::NotYetActive)));
+    }
+
+    #[test]
+    fn rejects_a_token_lacking_a_required_scope() {
+        let policy = testing::policy(json!({ "requiredScopes": ["orders:write"] }));
+        let now = testing::now();
+
+        let granted = filter(
+            &policy,
+            json!({ "scope": "orders:write", "exp": now + 600 }),
+        );
+        let lacking = filter(&policy, json!({ "scope": "orders:read", "exp": now + 600 }));
+
+        assert!(granted.is_ok());
+        assert!(matches!(lacking, Err(FilterError::InsufficientScope)));
+    }
+
+    #[test]
+    fn rejects_a_token_issued_for_another_audience() {
+        let policy = testing::policy(json!({ "expectedAudiences": ["orders-api"] }));
+        let now = testing::now();
+
+        let expected = filter(&policy, json!({ "aud": ["orders-api"], "exp": now + 600 }));
+        let other = filter(&policy, json!({ "aud": "billing-api", "exp": now + 600 }));
+
+        assert!(expected.is_ok());
+        assert!(matches!(other, Err(FilterError::InvalidAudience)));
+    }
+
+    #[test]
+    #[cfg(feature = "cache")]
+    fn paces_the_unseen_tokens_while_warming_up() {
+        let policy = testing::policy(json!({
+            "cacheTtlSeconds": 60,
+            "warmUpSeconds": 60,
+            "warmUpRate": 1
+        }));
+        let now = testing::now();
+        let introspector = MockIntrospector::default()
+            .with("seen", testing::active(json!({ "exp": now + 600 })))
+            .with("unseen", testing::active(json!({ "exp": now + 600 })));
+        let client = HttpClient::default();
+        let validate = |token| {
+            block_on(validate_token(
+                token,
+                None,
+                &policy,
+                &client,
+                &introspector,
+                now,
+            ))
+        };
+
+        assert!(validate("seen").is_ok());
+        assert!(validate("seen").is_ok());
+        assert!(matches!(validate("unseen"), Err(FilterError::WarmingUp)));
+    }
+
+    #[test]
+    #[cfg(feature = "cache")]
+    fn keeps_the_cached_results_of_the_unchanged_endpoints() {
+        let now = testing::now();
+        let client = HttpClient::default();
+        let reloaded = |billing_host| {
+            let bytes = testing::configuration(json!({
+                "cacheTtlSeconds": 60,
+                "prefixRoutes": [
+                    {
+                        "prefix": "orders-",
+                        "upstream": "idp",
+                        "host": "orders.example.com",
+                        "path": "/introspect",
+                        "authorization": ""
+                    },
+                    {
+                        "prefix": "billing-",
+                        "upstream": "idp",
+                        "host": billing_host,
+                        "path": "/introspect",
+                        "authorization": ""
+                    }
+                ]
+            }));
+            let keys = reload::Keys::new(bytes.as_bytes()).unwrap();
+            let config = serde_json::from_str(&bytes).unwrap();
+            let policy =
+                Policy::build(config, bytes.as_bytes(), None, reload::take(&keys)).unwrap();
+            reload::keep(keys, policy.runtime_state());
+            policy
+        };
+        let validate = |policy, token, introspector| {
+            block_on(validate_token(
+                token,
+                None,
+                policy,
+                &client,
+                introspector,
+                now,
+            ))
+        };
+
+        let before = reloaded("billing.example.com");
+        let introspector = MockIntrospector::default()
+            .with("orders-1", testing::active(json!({ "exp": now + 600 })))
+            .with("billing-1", testing::active(json!({ "exp": now + 600 })));
+        assert!(validate(&before, "orders-1", &introspector).is_ok());
+        assert!(validate(&before, "billing-1", &introspector).is_ok());
+
+        let after = reloaded("accounts.example.com");
+        let unreachable = MockIntrospector::default();
+        assert!(validate(&after, "orders-1", &unreachable).is_ok());
+        assert!(matches!(
+            validate(&after, "billing-1", &unreachable),
+            Err(FilterError::InactiveToken)
+        ));
+    }
+
+    #[test]
+    fn forwards_the_exchanged_token_once_the_inbound_one_is_stripped() {
+        let request = Headers::new(&[("authorization", "Bearer inbound")]);
+        let policy = testing::policy(json!({ "forwardToken": "strip" }));
+
+        forward_exchanged(&request, &policy.config, "exchanged");
+
+        assert_eq!(
+            request.header("authorization").as_deref(),
+            Some("Bearer exchanged")
+        );
+    }
+
+    #[test]
+    fn forwards_the_exchanged_token_once_the_inbound_one_is_moved() {
+        let request = Headers::new(&[("authorization", "Bearer inbound")]);
+        let policy = testing::policy(json!({
+            "forwardToken": "rewriteHeader",
+            "forwardTokenHeader": "x-inbound-authorization"
+        }));
+
+        forward_exchanged(&request, &policy.config, "exchanged");
+
+        assert_eq!(
+            request.header("authorization").as_deref(),
+            Some("Bearer exchanged")
+        );
+        assert_eq!(
+            request.header("x-inbound-authorization").as_deref(),
+            Some("Bearer inbound")
+        );
+    }
+
+    /// Validates a request to the orders with the given method and headers, carrying the token
+    /// granted the given scope
+    fn validate(
+        policy: &Policy,
+        method: &str,
+        headers: &[(&str, &str)],
+        scope: &str,
+    ) -> Result<ResponseContext, FilterError> {
+        let bearer = format!("Bearer {}", TOKEN);
+        let mut all = vec![
+            (":method", method),
+            (":path", "/orders"),
+            ("authorization", &bearer),
+        ];
+        all.extend_from_slice(headers);
+        let claims = json!({ "scope": scope, "exp": testing::now() + 600 });
+        let introspector = MockIntrospector::default().with(TOKEN, testing::active(claims));
+
+        block_on(do_filter(
+            &Headers::new(&all),
+            policy,
+            &HttpClient::default(),
+            &introspector,
+        ))
+    }
+
+    /// Configuration requiring the orders:read scope to read the orders
+    fn head_requests(mode: &str) -> Policy {
+        testing::policy(json!({
+            "headRequests": mode,
+            "routes": [
+                { "pathPattern": "/orders", "methods": ["GET"], "requiredScopes": ["orders:read"] }
+            ],
+        }))
+    }
+
+    #[test]
+    fn matches_the_head_requests_as_get_requests() {
+        let policy = head_requests("asGet");
+        let request = Headers::new(&[(":method", "HEAD")]);
+
+        assert_eq!(request_method(&request, &policy.config), "GET");
+        assert!(validate(&policy, "HEAD", &[], "orders:read").is_ok());
+        assert!(matches!(
+            validate(&policy, "HEAD", &[], "profile"),
+            Err(FilterError::InsufficientScope)
+        ));
+        assert!(matches!(
+            validate(&policy, "GET", &[], "profile"),
+            Err(FilterError::InsufficientScope)
+        ));
+    }
+
+    #[test]
+    fn tells_the_head_requests_apart() {
+        let policy = head_requests("distinct");
+        let request = Headers::new(&[(":method", "HEAD")]);
+
+        assert_eq!(request_method(&request, &policy.config), "HEAD");
+        assert!(validate(&policy, "HEAD", &[], "profile").is_ok());
+        assert!(matches!(
+            validate(&policy, "GET", &[], "profile"),
+            Err(FilterError::InsufficientScope)
+        ));
+    }
+
+    #[test]
+    fn validates_the_requests_with_an_empty_body() {
+        let policy = testing::policy(json!({ "headersOnlyRequests": "reject" }));
+        let empty = [("content-length", "0")];
+
+        //an empty body may arrive as a headers-only request, only rejected for the gRPC requests
+        let request = Headers::new(&[(":method", "POST"), (":path", "/orders"), empty[0]]);
+        assert!(headers_only(&request, false, &policy, None).is_none());
+        assert!(validate(&policy, "POST", &empty, "orders").is_ok());
+        assert!(matches!(
+            block_on(do_filter(
+                &Headers::new(&[(":method", "POST"), (":path", "/orders"), empty[0]]),
+                &policy,
+                &HttpClient::default(),
+                &MockIntrospector::default(),
+            )),
+            Err(FilterError::NoToken)
+        ));
+    }
+
+    #[test]
+    fn validates_the_requests_expecting_a_continue() {
+        let policy = testing::policy(json!({}));
+        let expect = [("expect", "100-continue"), ("content-length", "512")];
+
+        let request = Headers::new(&[(":method", "PUT"), (":path", "/orders"), expect[0]]);
+
+        //the token is validated from the headers, before the client sends the body
+        assert!(headers_only(&request, true, &policy, None).is_none());
+        assert!(validate(&policy, "PUT", &expect, "orders").is_ok());
+        assert!(matches!(
+            block_on(do_filter(
+                &request,
+                &policy,
+                &HttpClient::default(),
+                &MockIntrospector::default(),
+            )),
+            Err(FilterError::NoToken)
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::MetricType;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use crate::FilterError;
+
+/// Common prefix of all the metrics emitted by the policy
+const PREFIX: &str = "oauth_validate_token";
+
+/// Metrics of a policy instance, defined lazily in the host and prefixed with the instance name
+pub struct Metrics {
+    instance: String,
+    fingerprint: String,
+    ids: RefCell<HashMap<String, u32>>,
+    samples: RefCell<BTreeMap<String, Sample>>,
+}
+
+/// Local copy of a metric of this worker, kept to export it in the OpenMetrics text format
+enum Sample {
+    Counter(u64),
+    Summary { count: u64, sum: u64 },
+}
+
+impl Metrics {
+    pub fn new(instance: &str, fingerprint: &str) -> Self {
+        Self {
+            instance: instance.to_string(),
+            fingerprint: fingerprint.to_string(),
+            ids: RefCell::new(HashMap::new()),
+            samples: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Increments the counter with the given name
+    pub fn increment(&self, name: &str) {
+        if let Some(id) = self.id(MetricType::Counter, name) {
+            let _ = hostcalls::increment_metric(id, 1);
+        }
+
+        let mut samples = self.samples.borrow_mut();
+        if let Sample::Counter(value) = samples
+            .entry(name.to_string())
+            .or_insert(Sample::Counter(0))
+        {
+            *value += 1;
+        }
+    }
+
+    /// Records a value in the histogram with the given name
+    pub fn record(&self, name: &str, value: u64) {
+        if let Some(id) = self.id(MetricType::Histogram, name) {
+            let _ = hostcalls::record_metric(id, value);
+        }
+
+        let mut samples = self.samples.borrow_mut();
+        if let Sample::Summary { count, sum } = samples
+            .entry(name.to_string())
+            .or_insert(Sample::Summary { count: 0, sum: 0 })
+        {
+            *count += 1;
+            *sum += value;
+        }
+    }
+
+    /// Counts the decision taken for a request, the rejections by the code of their reason and by
+    /// its category
+    pub fn decision(&self, rejection: Option<&FilterError>) {
+        match rejection {
+            Some(err) => {
+                let code = err.code().to_ascii_lowercase();
+                self.increment(&format!("decision.rejected.{}", code));
+                self.increment(&format!("decision.{}_failure", err.category().name()));
+            }
+            None => self.increment("decision.accepted"),
+        }
+    }
+
+    /// Records the latency of a validation call made to the authorization server, tagged by its
+    /// outcome, counting the failures to reach the server
+    pub fn introspection(&self, outcome: &str, start: SystemTime) {
+        let elapsed = start.elapsed().unwrap_or_default().as_micros() as u64;
+        self.record(&format!("introspection.{}.micros", outcome), elapsed);
+        if outcome == "unreached" {
+            self.increment("introspection.failures");
+        }
+    }
+
+    /// Starts timing a stage of the decision, which is recorded once the returned guard is dropped
+    pub fn stage(&self, name: &'static str) -> Stage<'_> {
+        Stage {
+            metrics: self,
+            name,
+            start: SystemTime::now(),
+        }
+    }
+
+    /// Renders the metrics of this worker in the OpenMetrics text format, along with the given gauges
+    pub fn open_metrics(&self, gauges: &[(&str, u64)]) -> String {
+        let mut text = String::new();
+        let labels = format!(
+            "{{instance=\"{}\",config=\"{}\"}}",
+            self.instance.replace('"', "\\\""),
+            self.fingerprint
+        );
+
+        for (name, value) in gauges.iter() {
+            let name = metric_name(name);
+            let _ = writeln!(text, "# TYPE {} gauge", name);
+            let _ = writeln!(text, "{}{} {}", name, labels, value);
+        }
+
+        for (name, sample) in self.samples.borrow().iter() {
+            let name = metric_name(name);
+            match sample {
+                Sample::Counter(value) => {
+                    let _ = writeln!(text, "# TYPE {} counter", name);
+                    let _ = writeln!(text, "{}_total{} {}", name, labels, value);
+                }
+                Sample::Summary { count, sum } => {
+                    let _ = writeln!(text, "# TYPE {} summary", name);
+                    let _ = writeln!(text, "{}_count{} {}", name, labels, count);
+                    let _ = writeln!(text, "{}_sum{} {}", name, labels, sum);
+                }
+            }
+        }
+
+        text.push_str("# EOF\n");
+        text
+    }
+
+    fn id(&self, metric_type: MetricType, name: &str) -> Option<u32> {
+        if let Some(id) = self.ids.borrow().get(name) {
+            return Some(*id);
+        }
+
+        let full_name = format!("{}.{}.{}", PREFIX, self.instance, name);
+        match hostcalls::define_metric(metric_type, &full_name) {
+            Ok(id) => {
+                self.ids.borrow_mut().insert(name.to_string(), id);
+                Some(id)
+            }
+            Err(status) => {
+                logger::debug!("Unable to define the metric {}. {:?}.", full_name, status);
+                None
+            }
+        }
+    }
+}
+
+/// Guard measuring the time spent in a stage of the decision, in microseconds
+pub struct Stage<'a> {
+    metrics: &'a Metrics,
+    name: &'static str,
+    start: SystemTime,
+}
+
+impl Drop for Stage<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().unwrap_or_default().as_micros() as u64;
+        self.metrics
+            .record(&format!("stage.{}.micros", self.name), elapsed);
+    }
+}
+
+/// Returns the OpenMetrics name of a metric, which only allows alphanumerics and underscores
+fn metric_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("{}_{}", PREFIX, name)
+}
@@ -0,0 +1,126 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use proxy_wasm::hostcalls;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::generated::config::Config;
+use crate::{crypto, FilterError, IntrospectionResponse};
+
+/// Number of certificates whose decision is remembered before the memo is reset
+const MAX_DECISIONS: usize = 1024;
+
+/// Authenticates the requests of mTLS-only routes by the client certificate presented on the
+/// downstream connection, remembering the decision taken for each certificate
+pub struct ClientCertificates {
+    allowed: Vec<String>,
+    decisions: RefCell<HashMap<String, Option<String>>>,
+}
+
+impl ClientCertificates {
+    pub fn new(allowed: &[String]) -> Self {
+        Self {
+            allowed: allowed.to_vec(),
+            decisions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the allowlisted identity of the client certificate as the principal of the request
+    pub fn authenticate(&self) -> Result<IntrospectionResponse, FilterError> {
+        let thumbprint =
+            connection_property("sha256_peer_certificate_digest").ok_or(FilterError::NoToken)?;
+
+        let principal = self.decision(&thumbprint);
+        let subject = principal.ok_or(FilterError::UntrustedClientCertificate)?;
+
+        let mut claims = Map::new();
+        claims.insert("sub".to_string(), Value::String(subject));
+        claims.insert("cnf".to_string(), json!({ "x5t#S256": thumbprint }));
+
+        Ok(IntrospectionResponse {
+            active: true,
+            max_age: None,
+            exp: None,
+            nbf: None,
+            claims,
+        })
+    }
+
+    /// Returns the memoized principal of the certificate, resolving it on the first sight
+    fn decision(&self, thumbprint: &str) -> Option<String> {
+        if let Some(principal) = self.decisions.borrow().get(thumbprint) {
+            return principal.clone();
+        }
+
+        let principal = self.principal(thumbprint);
+        let mut decisions = self.decisions.borrow_mut();
+        if decisions.len() >= MAX_DECISIONS {
+            decisions.clear();
+        }
+        decisions.insert(thumbprint.to_string(), principal.clone());
+
+        principal
+    }
+
+    /// Returns the first identity of the certificate found in the allowlist, checking the URI
+    /// and DNS subject alternative names before the thumbprint
+    fn principal(&self, thumbprint: &str) -> Option<String> {
+        let identities = [
+            connection_property("uri_san_peer_certificate"),
+            connection_property("dns_san_peer_certificate"),
+            Some(thumbprint.to_string()),
+        ];
+
+        identities
+            .iter()
+            .flatten()
+            .find(|identity| {
+                self.allowed
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(identity))
+            })
+            .cloned()
+    }
+}
+
+/// Verifies that the token is bound to the client certificate presented on the downstream
+/// connection, comparing its thumbprint with the `x5t#S256` confirmation defined by RFC 8705. The
+/// unbound tokens are accepted unless the binding is required.
+pub fn verify_binding(
+    response: &IntrospectionResponse,
+    config: &Config,
+) -> Result<(), FilterError> {
+    let bound = response
+        .claims
+        .get("cnf")
+        .and_then(|cnf| cnf.get("x5t#S256"))
+        .and_then(Value::as_str);
+
+    match bound {
+        Some(bound) if certificate_thumbprint().as_deref() == Some(bound) => Ok(()),
+        Some(_) => Err(FilterError::TokenBindingMismatch),
+        None if config.certificate_binding == "required" => Err(FilterError::TokenBindingMismatch),
+        None => Ok(()),
+    }
+}
+
+/// Returns the base64url SHA-256 thumbprint of the client certificate, which the host exposes as
+/// a hexadecimal digest
+fn certificate_thumbprint() -> Option<String> {
+    let digest = connection_property("sha256_peer_certificate_digest")?;
+    let bytes = (0..digest.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(digest.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(crypto::base64url(&bytes))
+}
+
+/// Reads an attribute of the downstream connection exposed by the host
+fn connection_property(name: &str) -> Option<String> {
+    hostcalls::get_property(vec!["connection", name])
+        .ok()
+        .flatten()
+        .and_then(|value| String::from_utf8(value).ok())
+        .filter(|value| !value.is_empty())
+}
@@ -0,0 +1,254 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, SystemTime};
+
+use crate::generated::config::Config;
+use crate::{FilterError, Policy};
+
+/// Headers whose values are logged as they are, the values of the others being redacted
+const LOGGED_HEADERS: [&str; 2] = ["accept", "content-type"];
+
+/// Settings shared by every outbound call of the policy instance, whatever the service it reaches
+pub struct Outbound {
+    timeout: Option<Duration>,
+    trace_header: Option<String>,
+    proxy_upstream: Option<String>,
+    headers: Vec<(String, String)>,
+    sequence: Cell<u64>,
+}
+
+impl Outbound {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            timeout: config
+                .outbound_timeout_millis
+                .map(|millis| Duration::from_millis(millis.max(1) as u64)),
+            trace_header: config.outbound_trace_header.clone(),
+            proxy_upstream: config.outbound_proxy_upstream.clone(),
+            headers: config
+                .outbound_headers
+                .iter()
+                .map(|header| (header.name.clone(), header.value.clone()))
+                .collect(),
+            sequence: Cell::new(0),
+        }
+    }
+
+    /// Returns the identifier of the next call, naming the instance and the service called so
+    /// the logs of the service can be correlated with the ones of the policy
+    fn trace_id(&self, instance: &str, name: &str) -> String {
+        self.sequence.set(self.sequence.get().wrapping_add(1));
+        format!("{}.{}.{}", instance, name, self.sequence.get())
+    }
+}
+
+/// Outbound call of the policy, named after the service it reaches: introspection, userinfo,
+/// remote, jwks, google_certs, cognito_keys, denylist, egress_token or exchange
+pub struct Call<'a> {
+    name: &'static str,
+    upstream: &'a str,
+    host: &'a str,
+    path: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+    body: Option<&'a [u8]>,
+    timeout: Option<Duration>,
+    retried: bool,
+}
+
+impl<'a> Call<'a> {
+    pub fn new(name: &'static str, upstream: &'a str, host: &'a str, path: &'a str) -> Self {
+        Self {
+            name,
+            upstream,
+            host,
+            path,
+            headers: vec![],
+            body: None,
+            timeout: None,
+            retried: false,
+        }
+    }
+
+    pub fn headers(mut self, headers: Vec<(&'a str, &'a str)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sends the call as a POST with the given body, instead of a GET
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Overrides the timeout shared by the outbound calls
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout.or(self.timeout);
+        self
+    }
+
+    /// Retries the transient failures of the call according to the retry settings of the policy
+    pub fn retried(mut self) -> Self {
+        self.retried = true;
+        self
+    }
+
+    /// Sends the call, recording its latency and outcome under its name
+    pub async fn send(
+        self,
+        policy: &Policy,
+        client: &HttpClient,
+    ) -> Result<HttpClientResponse, FilterError> {
+        let outbound = &policy.outbound;
+        let timeout = self.timeout.or(outbound.timeout);
+        let trace_id = outbound.trace_id(&policy.instance, self.name);
+        //the egress proxy forwards the call to the host named by its authority, and is the only
+        //one the fixed headers are meant for, as they may carry its credentials
+        let (upstream, fixed) = match outbound.proxy_upstream.as_deref() {
+            Some(proxy) => (proxy, outbound.headers.as_slice()),
+            None => (self.upstream, &[][..]),
+        };
+
+        let mut attempt = 1;
+        loop {
+            let mut headers = self.headers.clone();
+            //the fixed headers never replace the ones of the call
+            headers.extend(
+                fixed
+                    .iter()
+                    .filter(|(name, _)| {
+                        !self
+                            .headers
+                            .iter()
+                            .any(|(own, _)| own.eq_ignore_ascii_case(name))
+                    })
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            );
+            if let Some(header) = outbound.trace_header.as_deref() {
+                headers.push((header, trace_id.as_str()));
+            }
+            logger::debug!(
+                "Outbound {} call {} through {} to {}{} with headers {}.",
+                self.name,
+                trace_id,
+                upstream,
+                self.host,
+                self.path.split('?').next().unwrap_or_default(),
+                redacted(&headers)
+            );
+
+            let mut request = client
+                .request(upstream, self.host)
+                .path(self.path)
+                .headers(headers);
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout);
+            }
+
+            //every outbound call counts against the limit, whatever the service it reaches
+            let permit = match &policy.limiter {
+                Some(limiter) => Some(
+                    limiter
+                        .acquire()
+                        .await
+                        .ok_or(FilterError::TooManyOutboundCalls)?,
+                ),
+                None => None,
+            };
+            let start = SystemTime::now();
+            let result = match self.body {
+                Some(body) => request.body(body).post().await,
+                None => request.get().await,
+            };
+            let elapsed = start.elapsed().unwrap_or_default();
+            drop(permit);
+            //the host reports the calls cancelled at their deadline as failed calls
+            let timed_out = timeout.is_some_and(|timeout| elapsed >= timeout);
+
+            let metrics = &policy.metrics;
+            metrics.record(
+                &format!("outbound.{}.micros", self.name),
+                elapsed.as_micros() as u64,
+            );
+            let status = result.as_ref().ok().map(|response| response.status_code());
+            match status {
+                Some(status) if status < 500 => {}
+                Some(_) => metrics.increment(&format!("outbound.{}.errors", self.name)),
+                None => metrics.increment(&format!("outbound.{}.failures", self.name)),
+            }
+
+            match (&policy.retry, &policy.timer) {
+                (Some(retry), Some(timer)) if self.retried && retry.retries(attempt, status) => {
+                    logger::debug!(
+                        "Outbound {} call {} attempt {} failed, retrying.",
+                        self.name,
+                        trace_id,
+                        attempt
+                    );
+                    metrics.increment(&format!("outbound.{}.retry", self.name));
+                    retry.wait(attempt, timer).await;
+                    attempt += 1;
+                }
+                _ if timed_out && result.is_err() => {
+                    metrics.increment(&format!("outbound.{}.timeouts", self.name));
+                    return Err(FilterError::Timeout);
+                }
+                _ => return result.map_err(FilterError::ClientError),
+            }
+        }
+    }
+}
+
+/// Renders the headers of a call for the logs, keeping only the names of the credentials and of
+/// any other header that may carry one
+fn redacted(headers: &[(&str, &str)]) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            if LOGGED_HEADERS
+                .iter()
+                .any(|kept| name.eq_ignore_ascii_case(kept))
+            {
+                format!("{}: {}", name, value)
+            } else {
+                format!("{}: [redacted]", name)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Validators of the last document returned by a periodic fetch, sent back with the next fetch
+/// so the service answers 304 instead of the same document when it did not change
+#[derive(Default)]
+pub struct Validators {
+    etag: RefCell<Option<String>>,
+    last_modified: RefCell<Option<String>>,
+}
+
+impl Validators {
+    /// Returns the conditional headers of the next fetch
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let etag = self.etag.borrow().clone();
+        let last_modified = self.last_modified.borrow().clone();
+
+        etag.map(|etag| ("If-None-Match", etag))
+            .into_iter()
+            .chain(last_modified.map(|date| ("If-Modified-Since", date)))
+            .collect()
+    }
+
+    /// Keeps the validators of a fetched document, forgetting the ones it no longer carries
+    pub fn store(&self, response: &HttpClientResponse) {
+        let headers = response.headers();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(own, _)| own.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+
+        self.etag.replace(header("etag"));
+        self.last_modified.replace(header("last-modified"));
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+
+/// Canonicalizes the path the rules are matched against, so encoded dot segments, repeated
+/// slashes or lowercase escapes cannot be used to bypass them. Encoded unreserved characters are
+/// decoded, the remaining escapes uppercased, repeated slashes collapsed and dot segments
+/// resolved as defined by RFC 3986.
+pub fn normalize(path: &str) -> String {
+    let decoded = decode_unreserved(path);
+
+    let mut segments: Vec<&str> = vec![];
+    for segment in decoded.split('/').filter(|segment| !segment.is_empty()) {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    let last = decoded.rsplit('/').next().unwrap_or_default();
+    if !segments.is_empty() && matches!(last, "" | "." | "..") {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+/// Returns whether the path falls under the prefix, which only matches whole segments so `/v1`
+/// does not cover `/v10`
+pub fn within(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix.trim_end_matches('/'))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Decodes the percent-encoded unreserved characters and uppercases the other escapes
+fn decode_unreserved(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = String::with_capacity(path.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = match (bytes[i], bytes.get(i + 1), bytes.get(i + 2)) {
+            (b'%', Some(high), Some(low)) => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match escape {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                decoded.push(byte as char);
+                i += 3;
+            }
+            Some(byte) => {
+                decoded.push_str(&format!("%{:02X}", byte));
+                i += 3;
+            }
+            None => {
+                let char = path[i..].chars().next().unwrap_or_default();
+                decoded.push(char);
+                i += char.len_utf8();
+            }
+        }
+    }
+
+    decoded
+}
@@ -0,0 +1,339 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::anyhow;
+use pdk::api::hl::*;
+use regex_lite::Regex;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::jwt::Jwt;
+use crate::outbound::Call;
+use crate::{claims, FilterError, IntrospectionResponse, Policy};
+
+/// Seconds the keys of a Cognito user pool are kept before fetching them again
+const COGNITO_KEYS_TTL: u64 = 3600;
+
+/// Minimum seconds between two fetches of the keys triggered by tokens signed with unknown keys
+const COGNITO_MIN_REFRESH_INTERVAL: u64 = 60;
+
+/// Validates the Cognito access tokens locally against the keys of the user pool, which Cognito
+/// publishes along with the issuer of its tokens
+pub struct CognitoUserPool {
+    issuer: String,
+    host: String,
+    path: String,
+    keys: RefCell<Option<(u64, Vec<Value>)>>,
+}
+
+impl CognitoUserPool {
+    pub fn new(user_pool_id: &str) -> Self {
+        //the region is the prefix of the user pool id, as in us-east-1_AbCdEf
+        let region = user_pool_id.split('_').next().unwrap_or_default();
+        let host = format!("cognito-idp.{}.amazonaws.com", region);
+
+        Self {
+            issuer: format!("https://{}/{}", host, user_pool_id),
+            path: format!("/{}/.well-known/jwks.json", user_pool_id),
+            host,
+            keys: RefCell::new(None),
+        }
+    }
+
+    pub async fn validate(
+        &self,
+        token: &str,
+        policy: &Policy,
+        client: &HttpClient,
+        now: u64,
+    ) -> Result<IntrospectionResponse, FilterError> {
+        let config = &policy.config;
+        let jwt = Jwt::decode(token)
+            .ok_or(FilterError::CompatibilityCheckFailed("token is not a JWT"))?;
+
+        let keys = self.keys_for(jwt.key_id(), policy, client, now).await?;
+        if !jwt.verify(&keys) {
+            return Err(FilterError::InvalidSignature);
+        }
+
+        if claims::claim_as_string(&jwt.claims, "iss").as_deref() != Some(self.issuer.as_str()) {
+            return Err(FilterError::UntrustedIssuer);
+        }
+
+        //the ID tokens of the pool are signed with the same keys
+        if claims::claim_as_string(&jwt.claims, "token_use").as_deref() != Some("access") {
+            return Err(FilterError::CompatibilityCheckFailed(
+                "token_use is not access",
+            ));
+        }
+
+        let client_id = claims::claim_as_string(&jwt.claims, "client_id").unwrap_or_default();
+        if !config.cognito_app_client_ids.is_empty()
+            && !config.cognito_app_client_ids.contains(&client_id)
+        {
+            return Err(FilterError::CompatibilityCheckFailed(
+                "client_id is not allowed",
+            ));
+        }
+
+        Ok(IntrospectionResponse {
+            active: true,
+            max_age: None,
+            exp: jwt.claims.get("exp").and_then(Value::as_u64),
+            nbf: jwt.claims.get("nbf").and_then(Value::as_u64),
+            claims: jwt.claims,
+        })
+    }
+
+    /// Returns the cached keys, fetching them again once they expire or, at most once a minute,
+    /// when the token was signed with a key that is not in the cached set
+    async fn keys_for(
+        &self,
+        kid: Option<&str>,
+        policy: &Policy,
+        client: &HttpClient,
+        now: u64,
+    ) -> Result<Vec<Value>, FilterError> {
+        if let Some((fetched_at, keys)) = self.keys.borrow().as_ref() {
+            let known = kid.is_none_or(|kid| {
+                keys.iter()
+                    .any(|key| key.get("kid").and_then(Value::as_str) == Some(kid))
+            });
+            let fresh = now < fetched_at + COGNITO_KEYS_TTL;
+            if fresh && (known || now < fetched_at + COGNITO_MIN_REFRESH_INTERVAL) {
+                return Ok(keys.clone());
+            }
+        }
+
+        let config = &policy.config;
+        let response = Call::new(
+            "cognito_keys",
+            config.cognito_jwks_upstream.as_deref().unwrap_or_default(),
+            self.host.as_str(),
+            self.path.as_str(),
+        )
+        .send(policy, client)
+        .await?;
+
+        if response.status_code() != 200 {
+            return Err(FilterError::CompatibilityCheckFailed(
+                "keys of the user pool are not available",
+            ));
+        }
+
+        let jwks: Value = serde_json::from_slice(crate::bounded(&response, config)?)
+            .map_err(FilterError::NonParsableIntrospectionBody)?;
+        let keys = jwks
+            .get("keys")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+
+        self.keys.replace(Some((now, keys.clone())));
+
+        Ok(keys)
+    }
+}
+
+/// Applies the Azure AD specific checks and maps its permission claims into the standard scope
+/// claim: delegated permissions come in `scp`, while application permissions come in `roles`
+pub fn azure(
+    claims: &mut Map<String, Value>,
+    tenant_id: &str,
+    audiences: &[String],
+) -> Result<(), FilterError> {
+    //v1 tokens are issued by sts.windows.net, while v2 tokens are issued by login.microsoftonline.com
+    let issuers = [
+        format!("https://sts.windows.net/{}/", tenant_id),
+        format!("https://login.microsoftonline.com/{}/v2.0", tenant_id),
+    ];
+
+    let issuer = claims::claim_as_string(claims, "iss").unwrap_or_default();
+    if !issuers.contains(&issuer) {
+        return Err(FilterError::UntrustedIssuer);
+    }
+
+    //the audience is either the client id or the App ID URI, depending on how the token was requested
+    let audience = claims::claim_as_string(claims, "aud").unwrap_or_default();
+    if !audiences.is_empty() && !audiences.contains(&audience) {
+        return Err(FilterError::CompatibilityCheckFailed("aud is not allowed"));
+    }
+
+    if !claims.contains_key("scope") {
+        let scope = match (claims.get("scp"), claims.get("roles")) {
+            (Some(Value::String(scp)), _) => Some(scp.clone()),
+            (_, Some(Value::Array(roles))) => Some(
+                roles
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            _ => None,
+        };
+
+        if let Some(scope) = scope {
+            claims.insert("scope".to_string(), Value::String(scope));
+        }
+    }
+
+    Ok(())
+}
+
+/// Flattens the Keycloak realm roles and the client roles nested under
+/// `resource_access.<client>.roles` into the standard `roles` claim, prefixing the client roles
+/// with the name of the client they belong to, as in `orders:reader`
+pub fn keycloak(claims: &mut Map<String, Value>, clients: &[String]) {
+    let mut roles: Vec<Value> = match claims.get("roles") {
+        Some(Value::Array(roles)) => roles.clone(),
+        _ => vec![],
+    };
+
+    let realm_roles = claims
+        .get("realm_access")
+        .and_then(|access| access.get("roles"))
+        .and_then(Value::as_array);
+    for role in realm_roles.into_iter().flatten().filter_map(Value::as_str) {
+        roles.push(Value::String(role.to_string()));
+    }
+
+    if let Some(Value::Object(resources)) = claims.get("resource_access") {
+        for (client, access) in resources {
+            if !clients.is_empty() && !clients.contains(client) {
+                continue;
+            }
+
+            let client_roles = access.get("roles").and_then(Value::as_array);
+            for role in client_roles.into_iter().flatten().filter_map(Value::as_str) {
+                roles.push(Value::String(format!("{}:{}", client, role)));
+            }
+        }
+    }
+
+    let mut unique = Vec::with_capacity(roles.len());
+    for role in roles {
+        if !unique.contains(&role) {
+            unique.push(role);
+        }
+    }
+    claims.insert("roles".to_string(), Value::Array(unique));
+}
+
+/// Seconds the keys of a Google service account are kept before fetching them again
+const GOOGLE_KEYS_TTL: u64 = 3600;
+
+/// Maximum lifetime Google allows for self-signed service account tokens
+const GOOGLE_MAX_LIFETIME: u64 = 3600;
+
+/// Validates the self-signed JWTs of Google service accounts, whose issuer and subject are the
+/// email of the account and whose keys are published by Google for each account
+pub struct GoogleServiceAccounts {
+    issuer_pattern: Regex,
+    keys: RefCell<HashMap<String, (u64, Vec<Value>)>>,
+}
+
+impl GoogleServiceAccounts {
+    pub fn new(issuer_pattern: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            issuer_pattern: Regex::new(issuer_pattern)
+                .map_err(|err| anyhow!("Invalid googleIssuerPattern: {}", err))?,
+            keys: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the decoded token when it is self-signed by a service account matching the pattern
+    pub fn select<'a>(&self, token: &'a str) -> Option<Jwt<'a>> {
+        let jwt = Jwt::decode(token)?;
+        let issuer = claims::claim_as_string(&jwt.claims, "iss")?;
+        let subject = claims::claim_as_string(&jwt.claims, "sub")?;
+
+        if issuer == subject && self.issuer_pattern.is_match(&issuer) {
+            Some(jwt)
+        } else {
+            None
+        }
+    }
+
+    pub async fn validate(
+        &self,
+        jwt: Jwt<'_>,
+        policy: &Policy,
+        client: &HttpClient,
+        now: u64,
+    ) -> Result<IntrospectionResponse, FilterError> {
+        let config = &policy.config;
+        let audience = claims::claim_as_string(&jwt.claims, "aud");
+        if audience.is_none() || audience != config.google_audience {
+            return Err(FilterError::CompatibilityCheckFailed("aud is not the API"));
+        }
+
+        let exp = jwt.claims.get("exp").and_then(Value::as_u64);
+        let iat = jwt.claims.get("iat").and_then(Value::as_u64);
+        match (iat, exp) {
+            (Some(iat), Some(exp)) if exp.saturating_sub(iat) <= GOOGLE_MAX_LIFETIME => {}
+            _ => {
+                return Err(FilterError::CompatibilityCheckFailed(
+                    "lifetime is missing or exceeds one hour",
+                ))
+            }
+        }
+
+        let email = claims::claim_as_string(&jwt.claims, "iss").unwrap_or_default();
+        let keys = self.keys_of(&email, policy, client, now).await?;
+        if !jwt.verify(&keys) {
+            return Err(FilterError::InvalidSignature);
+        }
+
+        Ok(IntrospectionResponse {
+            active: true,
+            max_age: None,
+            exp,
+            nbf: jwt.claims.get("nbf").and_then(Value::as_u64),
+            claims: jwt.claims,
+        })
+    }
+
+    async fn keys_of(
+        &self,
+        email: &str,
+        policy: &Policy,
+        client: &HttpClient,
+        now: u64,
+    ) -> Result<Vec<Value>, FilterError> {
+        if let Some((expiration, keys)) = self.keys.borrow().get(email) {
+            if *expiration > now {
+                return Ok(keys.clone());
+            }
+        }
+
+        let config = &policy.config;
+        let path = format!("/service_accounts/v1/jwk/{}", email);
+        let response = Call::new(
+            "google_certs",
+            config.google_certs_upstream.as_deref().unwrap_or_default(),
+            config.google_certs_host.as_str(),
+            path.as_str(),
+        )
+        .send(policy, client)
+        .await?;
+
+        if response.status_code() != 200 {
+            return Err(FilterError::CompatibilityCheckFailed(
+                "keys of the service account are not available",
+            ));
+        }
+
+        let jwks: Value = serde_json::from_slice(crate::bounded(&response, config)?)
+            .map_err(FilterError::NonParsableIntrospectionBody)?;
+        let keys = jwks
+            .get("keys")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+
+        self.keys
+            .borrow_mut()
+            .insert(email.to_string(), (now + GOOGLE_KEYS_TTL, keys.clone()));
+
+        Ok(keys)
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+use crate::crypto;
+use crate::generated::config::Config;
+
+/// Properties shaping the claims of every propagation profile, besides the ones of the profile
+const SHAPING_PROPERTIES: [&str; 4] = [
+    "claimMappings",
+    "claimMappingsOnly",
+    "claimCanonicalization",
+    "claimLimits",
+];
+
+/// Header the validated claims are forwarded in, along with the version of the shape they have
+pub struct Profile {
+    pub header: String,
+    pub removed_claims: Vec<String>,
+    pub version: String,
+}
+
+impl Profile {
+    /// Returns the header carrying the version of the claims forwarded by the profile
+    pub fn version_header(&self) -> String {
+        format!("{}-Version", self.header)
+    }
+}
+
+/// Builds the propagation profiles: the one of claimsHeader followed by the additional ones,
+/// emitted simultaneously while the upstream services migrate from a shape to another
+pub fn profiles(config: &Config, bytes: &[u8]) -> Result<Vec<Profile>> {
+    let properties: Map<String, Value> = serde_json::from_slice(bytes)?;
+    let mut profiles = vec![];
+
+    if let Some(header) = config.claims_header.as_deref() {
+        profiles.push(profile(&properties, header, &config.removed_claims)?);
+    }
+    for additional in config.propagation_profiles.iter() {
+        if profiles.iter().any(|profile: &Profile| {
+            profile
+                .header
+                .eq_ignore_ascii_case(&additional.claims_header)
+        }) {
+            bail!(
+                "Propagation profile header {} is already in use",
+                additional.claims_header
+            )
+        }
+        profiles.push(profile(
+            &properties,
+            &additional.claims_header,
+            &additional.removed_claims,
+        )?);
+    }
+
+    Ok(profiles)
+}
+
+/// Derives the version of a profile from the settings that shape the claims it forwards
+fn profile(properties: &Map<String, Value>, header: &str, removed: &[String]) -> Result<Profile> {
+    let mut shape: Map<String, Value> = SHAPING_PROPERTIES
+        .iter()
+        .map(|name| {
+            let value = properties.get(*name).cloned().unwrap_or(Value::Null);
+            (name.to_string(), value)
+        })
+        .collect();
+    shape.insert("removedClaims".to_string(), Value::from(removed.to_vec()));
+
+    let version = crypto::sha256_hex(serde_json::to_vec(&shape)?);
+
+    Ok(Profile {
+        header: header.to_string(),
+        removed_claims: removed.to_vec(),
+        version: version[..12].to_string(),
+    })
+}
@@ -0,0 +1,64 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::generated::config::Config;
+
+/// Tokens left in the bucket of a client and when they were last refilled
+struct Bucket {
+    tokens: f64,
+    updated: u64,
+}
+
+/// Token buckets keyed by the client_id of the validated tokens, refilled at the configured
+/// number of requests per window, so a single OAuth client cannot monopolize the API
+pub struct ClientRateLimiter {
+    capacity: f64,
+    window: u64,
+    max_clients: usize,
+    buckets: RefCell<HashMap<String, Bucket>>,
+}
+
+impl ClientRateLimiter {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.client_rate_limit.map(|limit| Self {
+            capacity: limit.max(1) as f64,
+            window: config.client_rate_limit_window_seconds.max(1) as u64,
+            max_clients: config.client_rate_limit_max_clients.max(1) as usize,
+            buckets: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Takes a token from the bucket of the client, returning the seconds until the next one is
+    /// available when the bucket is empty
+    pub fn acquire(&self, client_id: &str, now: u64) -> Result<(), u64> {
+        let rate = self.capacity / self.window as f64;
+        let mut buckets = self.buckets.borrow_mut();
+
+        //makes room for a new client by forgetting the one idle for the longest time
+        if !buckets.contains_key(client_id) && buckets.len() >= self.max_clients {
+            let idlest = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(client_id, _)| client_id.clone());
+            if let Some(idlest) = idlest {
+                buckets.remove(&idlest);
+            }
+        }
+
+        let bucket = buckets.entry(client_id.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_sub(bucket.updated) as f64;
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64)
+        }
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+
+use crate::{Policy, ResponseContext};
+
+/// Builds the early response of a rejection category, applying the status, headers and body
+/// template configured for it. The templates may refer to the `{{error}}` code and `{{status}}`.
+pub fn respond(
+    policy: &Policy,
+    category: &str,
+    status: u32,
+    mut headers: Vec<(String, String)>,
+    error: &str,
+) -> Flow<ResponseContext> {
+    headers.extend(crate::fingerprint_headers(policy));
+
+    let template = policy
+        .config
+        .rejection_responses
+        .iter()
+        .find(|template| template.category == category);
+
+    let template = match template {
+        Some(template) => template,
+        None => return Flow::Break(Response::new(status).with_headers(headers)),
+    };
+
+    let status = template.status.map_or(status, |status| status as u32);
+    let render = |value: &str| {
+        value
+            .replace("{{error}}", error)
+            .replace("{{status}}", &status.to_string())
+    };
+
+    for header in template.headers.iter() {
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case(&header.name));
+        headers.push((header.name.clone(), render(&header.value)));
+    }
+
+    match template.body.as_deref() {
+        Some(body) => {
+            //the templates are meant for JSON bodies unless told otherwise
+            if !headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            {
+                headers.push(("Content-Type".to_string(), "application/json".to_string()));
+            }
+            Flow::Break(
+                Response::new(status)
+                    .with_headers(headers)
+                    .with_body(render(body)),
+            )
+        }
+        None => Flow::Break(Response::new(status).with_headers(headers)),
+    }
+}
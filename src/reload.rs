@@ -0,0 +1,197 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::breaker::CircuitBreaker;
+#[cfg(feature = "cache")]
+use crate::cache::{IntrospectionCache, NegativeCache, RejectionCache};
+use crate::crypto;
+#[cfg(feature = "jwks")]
+use crate::jwks::KeySet;
+
+/// Properties that bind the runtime state to the services and keys it was built for. An update
+/// changing any of them starts over with a fresh state, while the updates changing only the
+/// runtime-tunable settings, such as TTLs, thresholds or enforcement modes, keep it.
+const STRUCTURAL_PROPERTIES: [&str; 28] = [
+    "clientId",
+    "clientSecret",
+    "clientAuthMethod",
+    "clientAssertionKey",
+    "issuerHeader",
+    "validationStrategy",
+    "userinfoPath",
+    "cognitoUserPoolId",
+    "cognitoJwksUpstream",
+    "azureTenantId",
+    "googleCertsUpstream",
+    "googleCertsHost",
+    "jwksUpstream",
+    "jwksHost",
+    "jwksPath",
+    "trustedGatewayJwks",
+    "apiKeys",
+    "clientCertificatePrincipals",
+    "introspectionResponseJwks",
+    "remoteActivePointer",
+    "remoteClaimsPointer",
+    "remoteExpPointer",
+    "remoteHeaders",
+    "remoteBody",
+    "remoteMethod",
+    "googleIssuerPattern",
+    "tokenTypeHint",
+    "extraParameters",
+];
+
+/// Properties that bind the runtime state to the introspection endpoints. An update changing only
+/// them keeps the caches, whose results are dropped for the endpoints it changed, but starts over
+/// with the rest of the state, such as the circuit of the previous authorization server.
+const ENDPOINT_PROPERTIES: [&str; 6] = [
+    "upstream",
+    "host",
+    "path",
+    "authorization",
+    "prefixRoutes",
+    "issuerRoutes",
+];
+
+/// State that outlives the configuration updates of the policy instance
+#[derive(Default, Clone)]
+pub struct RuntimeState {
+    #[cfg(feature = "cache")]
+    pub cache: Option<Rc<IntrospectionCache>>,
+    #[cfg(feature = "cache")]
+    pub machine_cache: Option<Rc<IntrospectionCache>>,
+    #[cfg(feature = "cache")]
+    pub rejections: Option<Rc<RejectionCache>>,
+    #[cfg(feature = "cache")]
+    pub negatives: Option<Rc<NegativeCache>>,
+    pub breaker: Option<Rc<CircuitBreaker>>,
+    #[cfg(feature = "jwks")]
+    pub jwks: Option<Rc<KeySet>>,
+}
+
+thread_local! {
+    static RETAINED: RefCell<Option<(Keys, RuntimeState)>> = const { RefCell::new(None) };
+}
+
+/// Keys identifying the structural settings of a configuration, with and without its endpoints
+#[derive(Clone, PartialEq)]
+pub struct Keys {
+    structural: String,
+    caches: String,
+}
+
+impl Keys {
+    pub fn new(bytes: &[u8]) -> Result<Self> {
+        let properties: Map<String, Value> = serde_json::from_slice(bytes)?;
+        let structural = STRUCTURAL_PROPERTIES
+            .iter()
+            .chain(ENDPOINT_PROPERTIES.iter());
+
+        Ok(Self {
+            structural: key(&properties, structural)?,
+            caches: key(&properties, STRUCTURAL_PROPERTIES.iter())?,
+        })
+    }
+}
+
+/// Derives a key from the values of the named properties
+fn key<'a>(
+    properties: &Map<String, Value>,
+    names: impl Iterator<Item = &'a &'static str>,
+) -> Result<String> {
+    let values: Vec<&Value> = names
+        .map(|name| properties.get(*name).unwrap_or(&Value::Null))
+        .collect();
+
+    Ok(crypto::sha256_hex(&serde_json::to_vec(&values)?))
+}
+
+impl RuntimeState {
+    /// Keeps the caches only, the policy dropping the results of the endpoints that changed
+    #[cfg_attr(not(feature = "cache"), allow(unused_variables))]
+    fn caches(self) -> Self {
+        Self {
+            #[cfg(feature = "cache")]
+            cache: self.cache,
+            #[cfg(feature = "cache")]
+            machine_cache: self.machine_cache,
+            #[cfg(feature = "cache")]
+            rejections: self.rejections,
+            #[cfg(feature = "cache")]
+            negatives: self.negatives,
+            ..Self::default()
+        }
+    }
+}
+
+/// Takes the state retained by the previous configuration if its structural settings match, or
+/// only its caches if they match but for the endpoints
+pub fn take(keys: &Keys) -> RuntimeState {
+    RETAINED.with(|retained| match retained.borrow_mut().take() {
+        Some((previous, state)) if previous == *keys => state,
+        Some((previous, state)) if previous.caches == keys.caches => state.caches(),
+        _ => RuntimeState::default(),
+    })
+}
+
+/// Retains the state of the current configuration for the next update
+pub fn keep(keys: Keys, state: RuntimeState) {
+    RETAINED.with(|retained| retained.replace(Some((keys, state))));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    fn keys(properties: Value) -> Keys {
+        Keys::new(testing::configuration(properties).as_bytes()).unwrap()
+    }
+
+    /// A state carrying a cache and a breaker, to tell what an update keeps of it
+    #[cfg_attr(not(feature = "cache"), allow(clippy::needless_update))]
+    fn retained() -> RuntimeState {
+        RuntimeState {
+            #[cfg(feature = "cache")]
+            cache: Some(Rc::new(IntrospectionCache::new(60, 100, 0))),
+            breaker: Some(Rc::new(CircuitBreaker::new(5, 30))),
+            ..RuntimeState::default()
+        }
+    }
+
+    #[test]
+    fn keeps_the_state_when_only_a_tunable_setting_changes() {
+        keep(keys(json!({ "cacheTtlSeconds": 60 })), retained());
+
+        let state = take(&keys(json!({ "cacheTtlSeconds": 120 })));
+        assert!(state.breaker.is_some());
+    }
+
+    #[test]
+    fn drops_the_caches_when_the_interpretation_of_the_response_changes() {
+        let before = keys(json!({ "remoteActivePointer": "/active" }));
+        for after in [
+            json!({ "remoteActivePointer": "/valid" }),
+            json!({ "remoteActivePointer": "/active", "tokenTypeHint": "access_token" }),
+            json!({
+                "remoteActivePointer": "/active",
+                "extraParameters": [{ "name": "resource", "value": "orders" }]
+            }),
+        ] {
+            let after = keys(after);
+            assert!(before.caches != after.caches);
+
+            keep(before.clone(), retained());
+            let state = take(&after);
+            #[cfg(feature = "cache")]
+            assert!(state.cache.is_none());
+            assert!(state.breaker.is_none());
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use std::time::{Duration, SystemTime};
+
+/// Retries the introspection calls that failed transiently, doubling the delay between attempts
+pub struct Backoff {
+    attempts: u32,
+    base: Duration,
+    statuses: Vec<u32>,
+}
+
+impl Backoff {
+    pub fn new(attempts: u32, base: Duration, statuses: Vec<u32>) -> Self {
+        Self {
+            attempts,
+            base,
+            statuses,
+        }
+    }
+
+    /// Returns true when a call that ended with the given status, or without a response, can be
+    /// attempted again
+    pub fn retries(&self, attempt: u32, status: Option<u32>) -> bool {
+        attempt < self.attempts && status.is_none_or(|status| self.statuses.contains(&status))
+    }
+
+    /// Returns the delay before the attempt following the given one
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.base * 2u32.saturating_pow(attempt.saturating_sub(1))
+    }
+
+    /// Waits on the timer of the policy before the attempt following the given one
+    pub async fn wait(&self, attempt: u32, timer: &Timer) {
+        let delay = self.delay(attempt);
+        let start = SystemTime::now();
+
+        while start.elapsed().unwrap_or_default() < delay {
+            timer.next_tick().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_the_unreachable_calls_and_the_listed_statuses() {
+        let backoff = Backoff::new(3, Duration::from_millis(100), vec![502, 503]);
+
+        assert!(backoff.retries(1, None));
+        assert!(backoff.retries(2, Some(503)));
+        assert!(!backoff.retries(1, Some(500)));
+        assert!(!backoff.retries(1, Some(401)));
+        assert!(!backoff.retries(3, None));
+    }
+
+    #[test]
+    fn doubles_the_delay_between_the_attempts() {
+        let backoff = Backoff::new(4, Duration::from_millis(100), vec![]);
+
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,559 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{anyhow, bail, Result};
+use regex_lite::Regex;
+use serde_json::{Map, Value};
+
+use crate::budget::INJECTIONS;
+use crate::claims;
+use crate::evaluation::CHECKS;
+use crate::generated::config::Config;
+use crate::path;
+
+/// Checks a cohort can put in monitor mode, so a new scope requirement can be phased in while the
+/// checks guarding the validity, the audience or the tenant of the token are always enforced
+const COHORT_CHECKS: [&str; 2] = ["scope", "route"];
+
+/// Requires the tenant captured from the request host to match the tenant claim of the token
+pub struct TenantRule {
+    host_pattern: Regex,
+    claim: String,
+}
+
+impl TenantRule {
+    /// Returns true when the tenant captured from the host equals the one carried by the token
+    pub fn matches(&self, host: &str, claims: &Map<String, Value>) -> bool {
+        let tenant = self
+            .host_pattern
+            .captures(host)
+            .and_then(|captures| captures.get(1))
+            .map(|tenant| tenant.as_str());
+
+        match (tenant, claims::claim_as_string(claims, &self.claim)) {
+            (Some(tenant), Some(claimed)) => tenant == claimed,
+            _ => false,
+        }
+    }
+}
+
+/// Extra requirements and response headers for the requests addressed to a deprecated API version
+pub struct VersionRule {
+    path_prefix: String,
+    required_scopes: Vec<String>,
+    content_types: Vec<String>,
+    pub response_headers: Vec<(String, String)>,
+}
+
+impl VersionRule {
+    /// Returns the first required scope that was not granted to the token
+    pub fn missing_scope(&self, claims: &Map<String, Value>) -> Option<&str> {
+        let granted = claims::scopes(claims);
+
+        self.required_scopes
+            .iter()
+            .map(String::as_str)
+            .find(|scope| !granted.contains(scope))
+    }
+
+    /// Returns true when the content type, without its parameters, is accepted by the version.
+    /// Requests without a body carry no content type and are always accepted.
+    pub fn accepts(&self, content_type: Option<&str>) -> bool {
+        match content_type {
+            Some(content_type) if !self.content_types.is_empty() => {
+                let media_type = content_type.split(';').next().unwrap_or_default().trim();
+                self.content_types
+                    .iter()
+                    .any(|accepted| accepted.eq_ignore_ascii_case(media_type))
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Condition on the claims of the token, such as a claim having a given value or a scope being
+/// granted
+pub struct ClaimCondition {
+    claim: Option<String>,
+    equals: Option<String>,
+    scope: Option<String>,
+}
+
+impl ClaimCondition {
+    /// Returns true when every configured part of the condition holds for the claims
+    pub fn holds(&self, claims: &Map<String, Value>) -> bool {
+        let claim_holds = match (&self.claim, &self.equals) {
+            (Some(claim), Some(expected)) => {
+                claims::claim_as_string(claims, claim).as_ref() == Some(expected)
+            }
+            (Some(claim), None) => claims::lookup(claims, claim).is_some(),
+            (None, _) => true,
+        };
+
+        let scope_holds = match &self.scope {
+            Some(scope) => claims::scopes(claims).contains(&scope.as_str()),
+            None => true,
+        };
+
+        claim_holds && scope_holds
+    }
+}
+
+/// Sets a request header when the claims of the token meet the condition
+pub struct HeaderMutation {
+    condition: ClaimCondition,
+    pub header: String,
+    pub value: String,
+}
+
+impl HeaderMutation {
+    pub fn applies(&self, claims: &Map<String, Value>) -> bool {
+        self.condition.holds(claims)
+    }
+}
+
+/// Routes the requests whose token meets the condition to a dedicated upstream authority
+pub struct AuthorityOverride {
+    condition: ClaimCondition,
+    path_prefix: Option<String>,
+    pub authority: String,
+}
+
+impl AuthorityOverride {
+    pub fn applies(&self, path: &str, claims: &Map<String, Value>) -> bool {
+        self.path_prefix
+            .as_deref()
+            .is_none_or(|prefix| path::within(path, prefix))
+            && self.condition.holds(claims)
+    }
+}
+
+/// Methods and anchored path pattern matched by the exemptions and the route rules, where no
+/// methods match all of them
+pub struct RequestPattern {
+    path: Regex,
+    methods: Vec<String>,
+}
+
+impl RequestPattern {
+    fn compile(path_pattern: &str, methods: &[String], kind: &str) -> Result<Self> {
+        Ok(Self {
+            path: Regex::new(&format!("^(?:{})$", path_pattern))
+                .map_err(|err| anyhow!("Invalid {} {}: {}", kind, path_pattern, err))?,
+            methods: methods.to_vec(),
+        })
+    }
+
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        (self.methods.is_empty()
+            || self
+                .methods
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(method)))
+            && self.path.is_match(path)
+    }
+}
+
+/// Scopes and claim values required by the requests matching a route, along with the audience
+/// and resource the token is exchanged for when the request is forwarded
+pub struct RouteRule {
+    pub pattern: RequestPattern,
+    required_scopes: Vec<String>,
+    required_claims: Vec<(String, String)>,
+    pub exchange_audience: Option<String>,
+    pub exchange_resource: Option<String>,
+}
+
+impl RouteRule {
+    /// Returns the first required scope that was not granted to the token
+    pub fn missing_scope(&self, claims: &Map<String, Value>) -> Option<&str> {
+        let granted = claims::scopes(claims);
+
+        self.required_scopes
+            .iter()
+            .map(String::as_str)
+            .find(|scope| !granted.contains(scope))
+    }
+
+    /// Returns the first required claim whose value does not match the one of the token
+    pub fn unmet_claim(&self, claims: &Map<String, Value>) -> Option<&str> {
+        self.required_claims
+            .iter()
+            .find(|(claim, value)| {
+                claims::claim_as_string(claims, claim).as_deref() != Some(value.as_str())
+            })
+            .map(|(claim, _)| claim.as_str())
+    }
+}
+
+/// Condition a claim of the token must satisfy to belong to a cohort
+enum Predicate {
+    Equals(String),
+    Before(i64),
+    After(i64),
+}
+
+/// Enforcement mode of a check for the tokens of a cohort, so a check can be tightened for some
+/// clients while its failures are only reported for the others
+pub struct Cohort {
+    check: &'static str,
+    pub name: String,
+    pub monitor: bool,
+    predicate: Option<(String, Predicate)>,
+}
+
+impl Cohort {
+    /// Returns whether the token belongs to the cohort, comparing the numeric claims such as the
+    /// creation dates as numbers
+    fn includes(&self, claims: &Map<String, Value>) -> bool {
+        let (claim, predicate) = match &self.predicate {
+            Some(predicate) => predicate,
+            None => return true,
+        };
+        let number = || {
+            claims.get(claim).and_then(|value| {
+                value
+                    .as_i64()
+                    .or_else(|| value.as_str().and_then(|text| text.parse().ok()))
+            })
+        };
+
+        match predicate {
+            Predicate::Equals(value) => {
+                claims::claim_as_string(claims, claim).as_deref() == Some(value.as_str())
+            }
+            Predicate::Before(bound) => number().is_some_and(|number| number < *bound),
+            Predicate::After(bound) => number().is_some_and(|number| number > *bound),
+        }
+    }
+}
+
+/// Assertion the value of a claim must satisfy
+enum Assertion {
+    Equals(String),
+    Contains(String),
+    Matches(Regex),
+    Present,
+}
+
+/// Assertion on a claim of the token, which is looked up as a dot separated path when nested
+pub struct ClaimValidation {
+    claim: String,
+    assertion: Assertion,
+}
+
+impl ClaimValidation {
+    /// Returns whether the claim satisfies the assertion, the arrays and the space delimited
+    /// strings such as `groups` or `scope` containing the member if any of their elements equals it
+    fn holds(&self, claims: &Map<String, Value>) -> bool {
+        let value = match claims::lookup(claims, &self.claim) {
+            None | Some(Value::Null) => return false,
+            Some(value) => value,
+        };
+        let text = || claims::claim_as_string(claims, &self.claim).unwrap_or_default();
+
+        match &self.assertion {
+            Assertion::Present => true,
+            Assertion::Equals(expected) => text() == *expected,
+            Assertion::Contains(member) => match value {
+                Value::Array(values) => values.iter().any(|value| match value {
+                    Value::String(value) => value == member,
+                    value => {
+                        serde_json::from_str::<Value>(member).is_ok_and(|member| member == *value)
+                    }
+                }),
+                Value::String(values) => values.split_whitespace().any(|value| value == member),
+                _ => false,
+            },
+            Assertion::Matches(pattern) => pattern.is_match(&text()),
+        }
+    }
+}
+
+/// Rules compiled once from the configuration and evaluated after the token has been introspected
+pub struct Rules {
+    pub tenant: Option<TenantRule>,
+    pub versions: Vec<VersionRule>,
+    pub mutations: Vec<HeaderMutation>,
+    pub authorities: Vec<AuthorityOverride>,
+    pub exemptions: Vec<RequestPattern>,
+    pub routes: Vec<RouteRule>,
+    pub cohorts: Vec<Cohort>,
+    pub validations: Vec<ClaimValidation>,
+    pub checks: Vec<&'static str>,
+    pub injections: Vec<&'static str>,
+}
+
+impl Rules {
+    pub fn compile(config: &Config) -> Result<Self> {
+        let tenant = match (&config.tenant_host_pattern, &config.tenant_claim) {
+            (Some(pattern), Some(claim)) => Some(TenantRule {
+                host_pattern: Regex::new(pattern)
+                    .map_err(|err| anyhow!("Invalid tenantHostPattern: {}", err))?,
+                claim: claim.clone(),
+            }),
+            (None, None) => None,
+            _ => bail!("tenantHostPattern and tenantClaim must be configured together"),
+        };
+
+        let versions = config
+            .version_rules
+            .iter()
+            .map(|rule| VersionRule {
+                path_prefix: rule.path_prefix.clone(),
+                required_scopes: rule.required_scopes.clone(),
+                content_types: rule.content_types.clone(),
+                response_headers: rule
+                    .response_headers
+                    .iter()
+                    .map(|header| (header.name.clone(), header.value.clone()))
+                    .collect(),
+            })
+            .collect();
+
+        let mutations = config
+            .header_mutations
+            .iter()
+            .map(|mutation| HeaderMutation {
+                condition: ClaimCondition {
+                    claim: mutation.claim.clone(),
+                    equals: mutation.equals.clone(),
+                    scope: mutation.scope.clone(),
+                },
+                header: mutation.header.clone(),
+                value: mutation.value.clone(),
+            })
+            .collect();
+
+        let authorities = config
+            .authority_overrides
+            .iter()
+            .map(|rule| AuthorityOverride {
+                condition: ClaimCondition {
+                    claim: rule.claim.clone(),
+                    equals: rule.equals.clone(),
+                    scope: rule.scope.clone(),
+                },
+                path_prefix: rule.path_prefix.clone(),
+                authority: rule.authority.clone(),
+            })
+            .collect();
+
+        let exemptions = config
+            .exemptions
+            .iter()
+            .map(|exemption| {
+                RequestPattern::compile(&exemption.path_pattern, &exemption.methods, "exemption")
+            })
+            .collect::<Result<_>>()?;
+
+        let routes = config
+            .routes
+            .iter()
+            .map(|route| {
+                Ok(RouteRule {
+                    pattern: RequestPattern::compile(&route.path_pattern, &route.methods, "route")?,
+                    required_scopes: route.required_scopes.clone(),
+                    required_claims: route
+                        .required_claims
+                        .iter()
+                        .map(|required| (required.claim.clone(), required.value.clone()))
+                        .collect(),
+                    exchange_audience: route.exchange_audience.clone(),
+                    exchange_resource: route.exchange_resource.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let cohorts = config
+            .enforcement_cohorts
+            .iter()
+            .map(|cohort| {
+                let check = COHORT_CHECKS
+                    .iter()
+                    .copied()
+                    .find(|check| *check == cohort.check.as_str())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "enforcementCohorts only apply to the scope and route checks: {}",
+                            cohort.check
+                        )
+                    })?;
+                let bound = || {
+                    cohort.value.parse().map_err(|_| {
+                        anyhow!("Cohort {} must compare against a number", cohort.name)
+                    })
+                };
+                let predicate = match (&cohort.claim, cohort.operator.as_str()) {
+                    (None, _) => None,
+                    (Some(claim), "before") => Some((claim.clone(), Predicate::Before(bound()?))),
+                    (Some(claim), "after") => Some((claim.clone(), Predicate::After(bound()?))),
+                    (Some(claim), _) => {
+                        Some((claim.clone(), Predicate::Equals(cohort.value.clone())))
+                    }
+                };
+
+                Ok(Cohort {
+                    check,
+                    name: cohort.name.clone(),
+                    monitor: cohort.mode == "monitor",
+                    predicate,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let validations = config
+            .claim_validations
+            .iter()
+            .map(|validation| {
+                let assertion = match validation.operator.as_str() {
+                    "present" => Assertion::Present,
+                    "contains" => Assertion::Contains(validation.value.clone()),
+                    "matches" => Assertion::Matches(
+                        Regex::new(&validation.value)
+                            .map_err(|err| anyhow!("Invalid claimValidations pattern: {}", err))?,
+                    ),
+                    _ => Assertion::Equals(validation.value.clone()),
+                };
+
+                Ok(ClaimValidation {
+                    claim: validation.claim.clone(),
+                    assertion,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        //the prioritized checks are evaluated first, followed by the rest in their default order
+        let mut checks = vec![];
+        for name in config.check_priorities.iter() {
+            let check = CHECKS
+                .iter()
+                .copied()
+                .find(|check| *check == name.as_str())
+                .ok_or_else(|| anyhow!("Unknown check in checkPriorities: {}", name))?;
+            if !checks.contains(&check) {
+                checks.push(check);
+            }
+        }
+        for check in CHECKS.iter() {
+            if !checks.contains(check) {
+                checks.push(*check);
+            }
+        }
+
+        //the injections dropped last to keep within the header budget are the prioritized ones
+        let mut injections = vec![];
+        for name in config.header_priorities.iter() {
+            let category = INJECTIONS
+                .iter()
+                .copied()
+                .find(|category| *category == name.as_str())
+                .ok_or_else(|| anyhow!("Unknown category in headerPriorities: {}", name))?;
+            if !injections.contains(&category) {
+                injections.push(category);
+            }
+        }
+        for category in INJECTIONS.iter() {
+            if !injections.contains(category) {
+                injections.push(*category);
+            }
+        }
+
+        Ok(Self {
+            tenant,
+            versions,
+            mutations,
+            authorities,
+            exemptions,
+            routes,
+            cohorts,
+            validations,
+            checks,
+            injections,
+        })
+    }
+
+    /// Returns the first cohort of the check the token belongs to, if any
+    pub fn cohort_for(&self, check: &str, claims: &Map<String, Value>) -> Option<&Cohort> {
+        self.cohorts
+            .iter()
+            .find(|cohort| cohort.check == check && cohort.includes(claims))
+    }
+
+    /// Returns the claim of the first validation the token does not satisfy
+    pub fn failed_validation(&self, claims: &Map<String, Value>) -> Option<&str> {
+        self.validations
+            .iter()
+            .find(|validation| !validation.holds(claims))
+            .map(|validation| validation.claim.as_str())
+    }
+
+    /// Returns the rule of the first route matching the request, if any
+    pub fn route_for(&self, method: &str, path: &str) -> Option<&RouteRule> {
+        self.routes
+            .iter()
+            .find(|route| route.pattern.matches(method, path))
+    }
+
+    /// Returns the rule of the API version the request path belongs to
+    pub fn version_for(&self, path: &str) -> Option<&VersionRule> {
+        self.versions
+            .iter()
+            .find(|version| path::within(path, &version.path_prefix))
+    }
+
+    /// Returns whether a version or a route rule covers the request, when there are any
+    pub fn covers(&self, method: &str, path: &str) -> bool {
+        (self.versions.is_empty() && self.routes.is_empty())
+            || self.version_for(path).is_some()
+            || self.route_for(method, path).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    fn compile(properties: serde_json::Value) -> Result<Rules> {
+        let config: Config = serde_json::from_str(&testing::configuration(properties)).unwrap();
+        Rules::compile(&config)
+    }
+
+    #[test]
+    fn overrides_the_authority_of_the_whole_segments_of_the_prefix() {
+        let rules = compile(json!({
+            "authorityOverrides": [
+                { "pathPrefix": "/v1", "scope": "orders:read", "authority": "v1.internal" }
+            ]
+        }))
+        .unwrap();
+        let claims = testing::active(json!({ "scope": "orders:read" })).claims;
+        let rule = &rules.authorities[0];
+
+        assert!(rule.applies("/v1", &claims));
+        assert!(rule.applies("/v1/orders", &claims));
+        assert!(!rule.applies("/v10/orders", &claims));
+    }
+
+    #[test]
+    fn monitors_the_scope_check_of_a_cohort() {
+        let rules = compile(json!({
+            "enforcementCohorts": [{ "check": "scope", "name": "legacy", "mode": "monitor" }]
+        }))
+        .unwrap();
+
+        assert!(rules.cohort_for("scope", &Map::new()).unwrap().monitor);
+    }
+
+    #[test]
+    fn rejects_a_monitor_cohort_on_the_validity_check() {
+        for check in ["validity", "claims", "audience", "tenant"] {
+            let rules = compile(json!({
+                "enforcementCohorts": [{ "check": check, "name": "legacy", "mode": "monitor" }]
+            }));
+
+            assert!(rules.is_err(), "{} must always be enforced", check);
+        }
+    }
+}
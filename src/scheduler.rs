@@ -0,0 +1,199 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "cache")]
+use crate::introspector::HttpIntrospector;
+#[cfg(feature = "cache")]
+use crate::FilterError;
+use crate::Policy;
+
+/// Tick of the timer when only the background jobs need it
+pub const JOB_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest wait between two runs of a failing job, unless its interval is longer
+const MAX_BACKOFF: u64 = 3600;
+
+/// Run of a background job, resolving to whether it succeeded
+pub type Run<'a> = Pin<Box<dyn Future<Output = bool> + 'a>>;
+
+/// Periodic job, run no sooner than its interval after the previous run and backed off
+/// exponentially while it fails
+struct Job<'a> {
+    name: &'static str,
+    interval: u64,
+    /// Seconds the successful runs may be brought forward by, so the replicas spread their
+    /// refreshes instead of fetching the metadata at the same instant
+    jitter: u64,
+    task: Box<dyn Fn(u64) -> Run<'a> + 'a>,
+    due: Cell<u64>,
+    failures: Cell<u32>,
+}
+
+/// Runs the background jobs of the policy instance one at a time, so they never overlap each other
+/// nor take more than one outbound slot away from the request processing
+pub struct Scheduler<'a> {
+    jobs: Vec<Job<'a>>,
+}
+
+impl<'a> Scheduler<'a> {
+    /// Schedules the background jobs enabled by the configuration of the policy
+    pub fn of(policy: &'a Policy, client: &'a HttpClient) -> Self {
+        let mut scheduler = Self { jobs: vec![] };
+
+        #[cfg(feature = "jwks")]
+        if let (Some(jwks), true) = (&policy.jwks, policy.config.jwks_background_refresh) {
+            let config = &policy.config;
+            let interval = config.jwks_refresh_seconds;
+            let jitter = config.refresh_jitter_percent;
+            scheduler.every("jwks_refresh", interval, jitter, move |now| {
+                Box::pin(async move {
+                    let authorization = policy.egress_authorization("jwks", client, now).await;
+                    jwks.refresh(policy, client, authorization.as_deref(), now)
+                        .await
+                })
+            });
+        }
+
+        //acquires the token of the policy again ahead of its expiration
+        if let Some(egress) = &policy.egress {
+            scheduler.every("egress_token", 1, 0, move |now| {
+                Box::pin(async move {
+                    !egress.expiring(now) || egress.acquire(policy, client, now).await
+                })
+            });
+        }
+
+        if let Some(denylist) = &policy.denylist {
+            let interval = policy.config.denylist_refresh_seconds;
+            let jitter = policy.config.refresh_jitter_percent;
+            scheduler.every("denylist_refresh", interval, jitter, move |now| {
+                Box::pin(denylist.refresh(policy, client, now))
+            });
+        }
+
+        //validates again the tokens whose stale results were served, dropping the stale entries
+        //first so the validation reaches the authorization server
+        #[cfg(feature = "cache")]
+        if policy.config.stale_grace_seconds.is_some() {
+            scheduler.every("cache_revalidation", 1, 0, move |now| {
+                Box::pin(async move {
+                    let mut succeeded = true;
+                    while let Some((token, issuer)) = policy.revalidations.pop() {
+                        let previous = policy
+                            .caches()
+                            .fold(None, |previous, cache| cache.remove(&token).or(previous));
+                        let issuer = issuer.as_deref();
+                        let introspector = HttpIntrospector { policy, client };
+                        let result = crate::validate_token(
+                            &token,
+                            issuer,
+                            policy,
+                            client,
+                            &introspector,
+                            now,
+                        )
+                        .await;
+                        if let (Some(previous), Ok(response)) = (&previous, &result) {
+                            crate::drift::report(&token, previous, response, policy);
+                        }
+                        succeeded &= !matches!(
+                            result,
+                            Err(FilterError::ClientError(_)) | Err(FilterError::Timeout)
+                        );
+                    }
+                    succeeded
+                })
+            });
+        }
+
+        scheduler
+    }
+
+    /// Schedules a job every interval, bringing the runs forward by up to the given percentage of
+    /// the interval
+    fn every(
+        &mut self,
+        name: &'static str,
+        interval: i64,
+        jitter_percent: i64,
+        task: impl Fn(u64) -> Run<'a> + 'a,
+    ) {
+        let interval = interval.max(1) as u64;
+        self.jobs.push(Job {
+            name,
+            interval,
+            jitter: interval * jitter_percent.clamp(0, 50) as u64 / 100,
+            task: Box::new(task),
+            due: Cell::new(0),
+            failures: Cell::new(0),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Runs the due jobs on the ticks of the timer, the most overdue first, until the timer stops
+    pub async fn run(&self, policy: &Policy, timer: &Timer) {
+        while timer.next_tick().await {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or_default();
+
+            let job = self
+                .jobs
+                .iter()
+                .filter(|job| job.due.get() <= now)
+                .min_by_key(|job| job.due.get());
+            let job = match job {
+                Some(job) => job,
+                None => continue,
+            };
+
+            //the outbound calls of the job take their own permits, so none is held around it
+            let succeeded = (job.task)(now).await;
+
+            if succeeded {
+                policy.metrics.increment(&format!("job.{}.runs", job.name));
+                job.failures.set(0);
+                job.due.set(now + job.interval - ahead(job.jitter));
+            } else {
+                policy
+                    .metrics
+                    .increment(&format!("job.{}.failures", job.name));
+                job.failures.set(job.failures.get().saturating_add(1));
+
+                let backoff = job
+                    .interval
+                    .saturating_mul(1 << job.failures.get().min(16))
+                    .min(MAX_BACKOFF.max(job.interval));
+                logger::warn!(
+                    "Background job {} failed, retrying in {} seconds.",
+                    job.name,
+                    backoff
+                );
+                job.due.set(now + backoff);
+            }
+        }
+    }
+}
+
+/// Returns a pseudo-random number of seconds up to the given jitter, drawn from the nanoseconds of
+/// the clock
+fn ahead(jitter: u64) -> u64 {
+    match jitter {
+        0 => 0,
+        jitter => {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.subsec_nanos())
+                .unwrap_or_default();
+            u64::from(nanos) % (jitter + 1)
+        }
+    }
+}
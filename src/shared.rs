@@ -0,0 +1,150 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::Status;
+use serde::{Deserialize, Serialize};
+
+use crate::generated::config::Config;
+use crate::metrics::Metrics;
+use crate::{crypto, FilterError, IntrospectionResponse};
+
+/// Version of the layout of the shared entries, part of their keys so the workers running a
+/// release of the policy with another layout ignore them
+const LAYOUT: u32 = 1;
+
+/// Updates of an entry lost to the other workers after which the update is given up
+const CAS_ATTEMPTS: usize = 3;
+
+/// Validation result shared by the workers, its version increased by every update, without a
+/// response when the token was found revoked
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    version: u64,
+    expiration: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<IntrospectionResponse>,
+}
+
+/// Validation results and revocations kept in the shared data of the host, so a token introspected
+/// or found revoked by one worker is known to all of them
+pub struct SharedCache {
+    prefix: String,
+    ttl: u64,
+    revocation_ttl: u64,
+}
+
+impl SharedCache {
+    pub fn from_config(config: &Config, fingerprint: &str) -> Option<Self> {
+        if !config.shared_cache {
+            return None;
+        }
+
+        //the entries are only shared by the workers running the same configuration
+        Some(Self {
+            prefix: format!("oauth-validate-token.v{}.{}.", LAYOUT, &fingerprint[..16]),
+            ttl: config.cache_ttl_seconds.unwrap_or_default().max(0) as u64,
+            revocation_ttl: config.shared_cache_revocation_seconds.max(0) as u64,
+        })
+    }
+
+    /// Returns the shared result of the token, or the rejection of the revoked tokens
+    pub fn get(&self, token: &str, now: u64) -> Option<Result<IntrospectionResponse, FilterError>> {
+        let key = self.key(token);
+        let (entry, cas) = read(&key);
+        let entry = entry?;
+        if entry.expiration <= now {
+            //the host never evicts the shared data, so the expired entries are emptied instead
+            let _ = hostcalls::set_shared_data(&key, None, cas);
+            return None;
+        }
+
+        match entry.response {
+            Some(response) => Some(Ok(response)),
+            None => Some(Err(FilterError::InactiveToken)),
+        }
+    }
+
+    /// Shares the result of an active token until it expires or the TTL of the cache, or the one
+    /// of its Cache-Control, elapses
+    pub fn insert(
+        &self,
+        token: &str,
+        response: &IntrospectionResponse,
+        metrics: &Metrics,
+        now: u64,
+    ) {
+        let ttl = response.max_age.unwrap_or(self.ttl);
+        let expiration = response.exp.map_or(now + ttl, |exp| exp.min(now + ttl));
+        if response.active && expiration > now {
+            self.update(token, Some(response), expiration, metrics, now);
+        }
+    }
+
+    /// Shares the revocation of a token, which the other workers then reject without introspecting
+    pub fn revoke(&self, token: &str, metrics: &Metrics, now: u64) {
+        self.update(token, None, now + self.revocation_ttl, metrics, now);
+    }
+
+    /// Writes the entry over the current one unless another worker changed it in the meantime, in
+    /// which case the update is retried against the entry that worker wrote
+    fn update(
+        &self,
+        token: &str,
+        response: Option<&IntrospectionResponse>,
+        expiration: u64,
+        metrics: &Metrics,
+        now: u64,
+    ) {
+        let key = self.key(token);
+        for _ in 0..CAS_ATTEMPTS {
+            let (current, cas) = read(&key);
+
+            //a revocation is not overwritten by a result introspected before it was learned
+            let revoked = current
+                .as_ref()
+                .is_some_and(|entry| entry.response.is_none() && entry.expiration > now);
+            if revoked && response.is_some() {
+                return;
+            }
+
+            let entry = Entry {
+                version: current.map_or(1, |entry| entry.version + 1),
+                expiration,
+                response: response.cloned(),
+            };
+            let bytes = match serde_json::to_vec(&entry) {
+                Ok(bytes) => bytes,
+                Err(_) => return,
+            };
+
+            match hostcalls::set_shared_data(&key, Some(&bytes), cas) {
+                Ok(()) => return,
+                Err(Status::CasMismatch) => metrics.increment("shared_cache.conflicts"),
+                Err(status) => {
+                    logger::warn!("Shared cache could not be updated. {:?}.", status);
+                    return;
+                }
+            }
+        }
+        metrics.increment("shared_cache.abandoned");
+    }
+
+    fn key(&self, token: &str) -> String {
+        format!("{}{}", self.prefix, crypto::sha256_hex(token))
+    }
+}
+
+/// Reads the entry stored under the key along with the CAS token of its current value, ignoring
+/// the emptied entries and the ones whose layout cannot be parsed
+fn read(key: &str) -> (Option<Entry>, Option<u32>) {
+    match hostcalls::get_shared_data(key) {
+        Ok((bytes, cas)) => (
+            bytes.and_then(|bytes| serde_json::from_slice(&bytes).ok()),
+            cas,
+        ),
+        Err(status) => {
+            logger::debug!("Shared cache could not be read. {:?}.", status);
+            (None, None)
+        }
+    }
+}
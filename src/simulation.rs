@@ -0,0 +1,269 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Records the inputs and outcomes of the decisions taken by the policy, and re-evaluates them
+//! offline against a candidate configuration
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::audit::{self, Severity};
+use crate::evaluation::{self, Target};
+use crate::generated::config::Config;
+use crate::{claims, crypto, FilterError, IntrospectionResponse, Policy};
+
+/// Version of the schema of the recorded decisions
+const SCHEMA: u64 = 2;
+
+/// Claims read by the checks, whose values are recorded. Every other claim is reduced to its type.
+const EVALUATED_CLAIMS: [&str; 4] = ["scope", "scp", "grant_type", claims::TOKEN_CLASS_CLAIM];
+
+/// Claims read by the checks that identify a party, whose values are recorded pseudonymized
+const IDENTIFIER_CLAIMS: [&str; 4] = ["iss", "aud", "act", "client_id"];
+
+/// Prefix of the pseudonymized values, keyed hashes of the values they replace
+const PSEUDONYM_PREFIX: &str = "hmac:";
+
+/// Placeholder of the path segments that look like identifiers
+const SEGMENT_PLACEHOLDER: &str = "{id}";
+
+/// Claims holding instants, recorded relative to the time of the decision
+const TIME_CLAIMS: [&str; 3] = ["exp", "iat", "nbf"];
+
+/// Instant the relative times of the recorded decisions are replayed from
+const REPLAY_EPOCH: u64 = 1 << 32;
+
+/// Outcome of a recorded decision along with the one the candidate configuration takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// Outcome recorded by the policy, either `allow` or the name of the error
+    pub recorded: String,
+    /// Outcome of the candidate configuration
+    pub replayed: String,
+}
+
+impl Replay {
+    pub fn changed(&self) -> bool {
+        self.recorded != self.replayed
+    }
+}
+
+/// Records the anonymized inputs of the claim checks and their outcome in the audit stream
+pub(crate) fn export(
+    policy: &Policy,
+    response: &IntrospectionResponse,
+    target: &Target,
+    now: u64,
+    failure: Option<&FilterError>,
+) {
+    let config = &policy.config;
+    let key = config.decision_export_key.as_deref().unwrap_or_default();
+    let mut claims = Map::new();
+    let mut shape = Map::new();
+
+    for (name, value) in response.claims.iter() {
+        if TIME_CLAIMS.contains(&name.as_str()) {
+            if let Some(instant) = value.as_u64() {
+                claims.insert(name.clone(), json!(instant as i64 - now as i64));
+            }
+        } else if EVALUATED_CLAIMS.contains(&name.as_str()) {
+            claims.insert(name.clone(), value.clone());
+        } else if IDENTIFIER_CLAIMS.contains(&name.as_str())
+            || config.tenant_claim.as_deref() == Some(name.as_str())
+            || config.machine_claim.as_deref() == Some(name.as_str())
+        {
+            claims.insert(name.clone(), pseudonymize(value, key));
+        } else {
+            shape.insert(name.clone(), json!(kind(value)));
+        }
+    }
+
+    audit::emit(
+        &policy.instance,
+        Severity::Info,
+        "decision",
+        json!({
+            "schema": SCHEMA,
+            "method": target.method,
+            "host": target.host,
+            "path": template(&target.path),
+            "upgrade": target.upgrade,
+            "contentType": target.content_type,
+            "claims": claims,
+            "shape": shape,
+            "outcome": outcome(failure),
+        }),
+    );
+}
+
+/// Re-evaluates the claim checks of a batch of recorded decisions against a candidate
+/// configuration, reporting the outcome each of them would have. The validity of the tokens is
+/// not evaluated, since it depends on the authorization server at the time of the decision. The
+/// pseudonymized values are restored when the candidate names them and is keyed like the export.
+pub fn replay(config: &[u8], records: &[Value]) -> Result<Vec<Replay>> {
+    let policy = Policy::detached(serde_json::from_slice::<Config>(config)?, config)?;
+    let known = known_values(&serde_json::from_slice(config)?, &policy.config);
+
+    records
+        .iter()
+        .map(|record| {
+            if record["schema"].as_u64() != Some(SCHEMA) {
+                return Err(anyhow!("unsupported decision schema {}", record["schema"]));
+            }
+
+            let text = |name: &str| record[name].as_str().map(str::to_string);
+            let target = Target {
+                method: text("method").unwrap_or_default(),
+                host: text("host").unwrap_or_default(),
+                path: text("path").unwrap_or_default(),
+                upgrade: text("upgrade"),
+                content_type: text("contentType"),
+            };
+
+            //the tenants are named by a label of the host rather than by the configuration
+            let mut known = known.clone();
+            if let Some(key) = policy.config.decision_export_key.as_deref() {
+                for label in target.host.split('.') {
+                    known.insert(pseudonym(label, key), label.to_string());
+                }
+            }
+
+            let mut claims: Map<String, Value> = record["claims"]
+                .as_object()
+                .map(|claims| {
+                    claims
+                        .iter()
+                        .map(|(name, value)| (name.clone(), restore(value, &known)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            for name in TIME_CLAIMS.iter() {
+                if let Some(offset) = claims.get(*name).and_then(Value::as_i64) {
+                    claims.insert(name.to_string(), json!(REPLAY_EPOCH as i64 + offset));
+                }
+            }
+            let response = IntrospectionResponse {
+                active: true,
+                max_age: None,
+                exp: claims.get("exp").and_then(Value::as_u64),
+                nbf: claims.get("nbf").and_then(Value::as_u64),
+                claims,
+            };
+
+            let failure = policy
+                .rules
+                .checks
+                .iter()
+                .filter(|check| **check != "validity")
+                .find_map(|check| {
+                    evaluation::check(check, &response, &policy, &target, REPLAY_EPOCH).err()
+                });
+
+            Ok(Replay {
+                recorded: text("outcome").unwrap_or_default(),
+                replayed: outcome(failure.as_ref()),
+            })
+        })
+        .collect()
+}
+
+/// Replaces the strings of a claim value with their keyed hashes, which keeps the equal values
+/// equal without revealing them
+fn pseudonymize(value: &Value, key: &str) -> Value {
+    match value {
+        Value::String(text) => json!(pseudonym(text, key)),
+        Value::Array(values) => Value::Array(values.iter().map(|v| pseudonymize(v, key)).collect()),
+        Value::Object(members) => Value::Object(
+            members
+                .iter()
+                .map(|(name, value)| (name.clone(), pseudonymize(value, key)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn pseudonym(text: &str, key: &str) -> String {
+    let hash = crypto::hmac_sha256(key.as_bytes(), text.as_bytes());
+    format!("{}{}", PSEUDONYM_PREFIX, crypto::base64url(&hash))
+}
+
+/// Returns the pseudonyms of the strings of the candidate configuration, the only values its
+/// checks can compare the recorded claims against
+fn known_values(document: &Value, config: &Config) -> HashMap<String, String> {
+    fn strings<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::String(text) => found.push(text),
+            Value::Array(values) => values.iter().for_each(|value| strings(value, found)),
+            Value::Object(members) => members.values().for_each(|value| strings(value, found)),
+            _ => {}
+        }
+    }
+
+    let key = match config.decision_export_key.as_deref() {
+        Some(key) => key,
+        None => return HashMap::new(),
+    };
+    let mut found = vec![];
+    strings(document, &mut found);
+
+    found
+        .into_iter()
+        .map(|text| (pseudonym(text, key), text.to_string()))
+        .collect()
+}
+
+/// Restores the pseudonymized strings of a recorded claim value known to the candidate
+fn restore(value: &Value, known: &HashMap<String, String>) -> Value {
+    match value {
+        Value::String(text) => match known.get(text) {
+            Some(original) => json!(original),
+            None => value.clone(),
+        },
+        Value::Array(values) => Value::Array(values.iter().map(|v| restore(v, known)).collect()),
+        Value::Object(members) => Value::Object(
+            members
+                .iter()
+                .map(|(name, value)| (name.clone(), restore(value, known)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Returns the path with the segments that look like identifiers, such as numbers, UUIDs or
+/// emails, replaced by a placeholder, keeping the route the request was sent to
+fn template(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            //the short names with digits, such as v1, are kept
+            let digits = segment.chars().filter(char::is_ascii_digit).count();
+            let identifier = (digits > 0 && (digits == segment.len() || segment.len() >= 8))
+                || segment.len() > 24
+                || segment.contains('@');
+            if identifier {
+                SEGMENT_PLACEHOLDER
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn outcome(failure: Option<&FilterError>) -> String {
+    match failure {
+        Some(err) => format!("{:?}", err),
+        None => "allow".to_string(),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
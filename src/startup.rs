@@ -0,0 +1,145 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use anyhow::{anyhow, bail, Result};
+use pdk::api::hl::*;
+
+use crate::generated::config::Config;
+use crate::{Endpoint, FilterError, Policy};
+
+/// Token sent by the probe when none is configured, which the endpoint reports as inactive
+const PROBE_TOKEN: &str = "startup-probe";
+
+/// Checks the settings of the introspection endpoints the schema cannot express, so a mistyped
+/// value fails the configuration instead of every request
+pub fn validate(config: &Config) -> Result<()> {
+    endpoint("", &config.upstream, &config.host, &config.path)?;
+    authorization("authorization", &config.authorization)?;
+
+    for route in config.issuer_routes.iter() {
+        let name = format!("issuerRoutes[{}].", route.issuer);
+        endpoint(&name, &route.upstream, &route.host, &route.path)?;
+        authorization(&format!("{}authorization", name), &route.authorization)?;
+    }
+    for route in config.prefix_routes.iter() {
+        let name = format!("prefixRoutes[{}].", route.prefix);
+        endpoint(&name, &route.upstream, &route.host, &route.path)?;
+        authorization(&format!("{}authorization", name), &route.authorization)?;
+    }
+
+    match (&config.client_id, &config.client_secret) {
+        (Some(_), None) if config.client_auth_method != "private_key_jwt" => {
+            bail!(
+                "clientId requires clientSecret with {}",
+                config.client_auth_method
+            );
+        }
+        (None, Some(_)) => bail!("clientSecret requires clientId"),
+        _ => {}
+    }
+
+    if config.decision_export && config.decision_export_key.is_none() {
+        bail!("decisionExport requires decisionExportKey");
+    }
+
+    if !config.outbound_headers.is_empty() && config.outbound_proxy_upstream.is_none() {
+        bail!("outboundHeaders requires outboundProxyUpstream");
+    }
+
+    if config.warm_up_seconds.is_some() && config.cache_ttl_seconds.is_none() {
+        bail!("warmUpSeconds requires cacheTtlSeconds");
+    }
+
+    if config.startup_probe
+        && (config.validation_strategy != "introspection" || config.cognito_user_pool_id.is_some())
+    {
+        bail!("startupProbe requires the introspection validation strategy");
+    }
+
+    Ok(())
+}
+
+/// Checks an endpoint is addressed by a service name, a bare authority and an absolute path
+fn endpoint(name: &str, upstream: &str, host: &str, path: &str) -> Result<()> {
+    if upstream.trim().is_empty() {
+        bail!("{}upstream must name the upstream service", name);
+    }
+    if host.is_empty()
+        || host.contains("://")
+        || host.contains(|c: char| c == '/' || c.is_whitespace())
+    {
+        bail!(
+            "{}host must be an authority such as idp.example.com:443, not {:?}",
+            name,
+            host
+        );
+    }
+    if !path.starts_with('/') || path.contains(char::is_whitespace) {
+        bail!(
+            "{}path must be an absolute path such as /introspect, not {:?}",
+            name,
+            path
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks a raw Authorization header has the form of a scheme followed by its credentials
+fn authorization(name: &str, value: &str) -> Result<()> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    match value.split_once(' ') {
+        Some((scheme, credentials))
+            if !scheme.is_empty()
+                && scheme
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+                && !credentials.trim().is_empty() =>
+        {
+            Ok(())
+        }
+        _ => bail!(
+            "{} must be a scheme followed by credentials, such as Basic <value>",
+            name
+        ),
+    }
+}
+
+/// Introspects a probe token at launch, failing the configuration when the introspection endpoint
+/// cannot be reached or rejects the credentials of the policy
+pub async fn probe(policy: &Policy, client: &HttpClient) -> Result<()> {
+    let config = &policy.config;
+    let token = config.startup_probe_token.as_deref().unwrap_or(PROBE_TOKEN);
+    let endpoint = Endpoint::select(token, None, config)
+        .map_err(|err| anyhow!("Startup probe could not select an endpoint. {:?}", err))?;
+
+    match crate::introspect_token(token, &endpoint, policy, client).await {
+        Ok(_) => {
+            logger::info!("Startup probe reached the introspection endpoint.");
+            Ok(())
+        }
+        Err(FilterError::IdpStatus(status @ (401 | 403))) => Err(anyhow!(
+            "Introspection endpoint rejected the credentials of the policy with status {}",
+            status
+        )),
+        Err(FilterError::IdpStatus(404)) => Err(anyhow!(
+            "Introspection endpoint {}{} was not found",
+            endpoint.host,
+            endpoint.path
+        )),
+        Err(FilterError::IdpStatus(status)) if status >= 500 => Err(anyhow!(
+            "Introspection endpoint failed with status {}",
+            status
+        )),
+        Err(err @ (FilterError::ClientError(_) | FilterError::Timeout)) => Err(anyhow!(
+            "Introspection endpoint {} could not be reached. {:?}",
+            endpoint.host,
+            err
+        )),
+        Err(err) => {
+            logger::warn!("Startup probe received an unexpected answer. {:?}.", err);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde_json::{Map, Value};
+
+use crate::generated::config::Config;
+use crate::jwt::Jwt;
+use crate::{crypto, FilterError};
+
+/// Format of a bearer token, guessed from its structure
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenFormat {
+    Jwt,
+    Opaque,
+    Malformed,
+}
+
+impl TokenFormat {
+    /// Classifies the token as a JWT when it has three base64url segments, as opaque when it has
+    /// no segments at all, and as malformed otherwise
+    pub fn detect(token: &str) -> Self {
+        if !token.contains('.') {
+            return TokenFormat::Opaque;
+        }
+
+        let segments: Vec<&str> = token.split('.').collect();
+        let well_formed = segments.len() == 3
+            && segments
+                .iter()
+                .all(|segment| !segment.is_empty() && segment.bytes().all(is_base64url));
+
+        if well_formed {
+            TokenFormat::Jwt
+        } else {
+            TokenFormat::Malformed
+        }
+    }
+}
+
+/// Decodes the claims of a JWT without verifying its signature, so they must only be used to
+/// discard tokens early and never to accept them
+pub fn unverified_claims(token: &str) -> Option<Map<String, Value>> {
+    let payload = token.split('.').nth(1)?;
+    let payload = crypto::base64url_decode(payload)?;
+
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Rejects the tokens that cannot be valid before any outbound call is made for them: the ones
+/// longer than maxTokenLength, carrying characters outside of tokenCharset or, with
+/// tokenRequireJwt, not shaped as a JWT whose header and claims are JSON objects
+pub fn precheck(token: &str, config: &Config) -> Result<(), FilterError> {
+    if token.len() > config.max_token_length.max(0) as usize {
+        return Err(FilterError::TokenPrecheckFailed(
+            "The access token is too long",
+        ));
+    }
+
+    let allowed: fn(u8) -> bool = match config.token_charset.as_str() {
+        "base64url" => |byte: u8| is_base64url(byte) || byte == b'.' || byte == b'=',
+        "printable" => |byte: u8| byte.is_ascii_graphic(),
+        _ => |byte: u8| is_base64url(byte) || b".~+/=".contains(&byte),
+    };
+    if !token.bytes().all(allowed) {
+        return Err(FilterError::TokenPrecheckFailed(
+            "The access token contains characters that are not allowed",
+        ));
+    }
+
+    if config.token_require_jwt && Jwt::decode(token).is_none() {
+        return Err(FilterError::TokenPrecheckFailed(
+            "The access token is not a JWT",
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_base64url(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'
+}
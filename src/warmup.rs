@@ -0,0 +1,72 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::hl::*;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::generated::config::Config;
+
+/// Paces the introspection of the tokens missing from the cache for a while after the policy
+/// starts with an empty cache, so a deploy does not turn into a burst of calls to the
+/// authorization server
+pub struct WarmUp {
+    until: u64,
+    rate: f64,
+    wait: Duration,
+    tokens: Cell<f64>,
+    updated: Cell<f64>,
+    timer: Option<Rc<Timer>>,
+}
+
+impl WarmUp {
+    pub fn from_config(config: &Config, timer: Option<Rc<Timer>>) -> Option<Self> {
+        let now = seconds();
+        let rate = config.warm_up_rate.max(1) as f64;
+
+        config.warm_up_seconds.map(|window| Self {
+            until: now as u64 + window.max(0) as u64,
+            rate,
+            wait: Duration::from_millis(config.warm_up_delay_millis.max(0) as u64),
+            tokens: Cell::new(rate),
+            updated: Cell::new(now),
+            timer,
+        })
+    }
+
+    /// Takes a slot for introspecting a token missing from the cache, waiting up to the configured
+    /// delay for one once the rate is exceeded. Returns false when none was available in time.
+    pub async fn admit(&self, now: u64) -> bool {
+        if now >= self.until {
+            return true;
+        }
+
+        let start = SystemTime::now();
+        loop {
+            let current = seconds();
+            let elapsed = (current - self.updated.get()).max(0.0);
+            self.tokens
+                .set((self.tokens.get() + elapsed * self.rate).min(self.rate));
+            self.updated.set(current);
+
+            if self.tokens.get() >= 1.0 {
+                self.tokens.set(self.tokens.get() - 1.0);
+                return true;
+            }
+
+            match &self.timer {
+                Some(timer) if start.elapsed().unwrap_or_default() < self.wait => {
+                    timer.next_tick().await;
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Returns the current time in seconds, with the precision the rate is enforced at
+fn seconds() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64())
+        .unwrap_or_default()
+}